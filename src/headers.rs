@@ -1,13 +1,25 @@
 use std::convert::Infallible;
 use std::fmt;
 
+/// Read access to the request headers.
+///
+/// Implementations must match header names ASCII-case-insensitively, as HTTP header names are case-insensitive.
 pub trait HeaderMap {
     type Error: fmt::Debug;
 
+    /// Returns the first value of the header `name`, matched case-insensitively.
     fn extract(&self, name: &str) -> Result<Option<&str>, Self::Error>;
 }
 
-impl HeaderMap for Vec<(String, String)> {
+impl<T: HeaderMap + ?Sized> HeaderMap for &T {
+    type Error = T::Error;
+
+    fn extract(&self, name: &str) -> Result<Option<&str>, Self::Error> {
+        (**self).extract(name)
+    }
+}
+
+impl HeaderMap for [(String, String)] {
     type Error = Infallible;
 
     fn extract(&self, name: &str) -> Result<Option<&str>, Self::Error> {
@@ -20,11 +32,20 @@ impl HeaderMap for Vec<(String, String)> {
     }
 }
 
+impl HeaderMap for Vec<(String, String)> {
+    type Error = Infallible;
+
+    fn extract(&self, name: &str) -> Result<Option<&str>, Self::Error> {
+        self.as_slice().extract(name)
+    }
+}
+
 #[cfg(feature = "http")]
 impl HeaderMap for http::HeaderMap {
     type Error = http::header::ToStrError;
 
     fn extract(&self, name: &str) -> Result<Option<&str>, Self::Error> {
+        // Header names are case-insensitive, http::HeaderMap::get normalizes the name
         self.get(name).map(|hv| hv.to_str()).transpose()
     }
 }
@@ -5,27 +5,32 @@ mod retries;
 mod service_protocol;
 mod vm;
 
-use bytes::Bytes;
+use bytes::{Buf, Bytes};
 use std::borrow::Cow;
 use std::fmt;
+use std::sync::Arc;
 use std::time::Duration;
 
 pub use crate::retries::RetryPolicy;
 pub use headers::HeaderMap;
 #[cfg(feature = "request_identity")]
 pub use request_identity::*;
-pub use service_protocol::Version;
-pub use vm::CoreVM;
+pub use service_protocol::{
+    decode_command, DecodedCommand, Decoder, DecodingError, MessageType, ProtocolFeature,
+    RawMessage, Version, DEFAULT_MAX_MESSAGE_SIZE,
+};
+pub use vm::{decode_awakeable_id, encode_awakeable_id, validate_journal, CoreVM};
 
 // Re-export only some stuff from vm::errors
 pub mod error {
     pub use crate::vm::errors::codes;
+    pub use crate::vm::errors::ErrorCode;
     pub use crate::vm::errors::InvocationErrorCode;
 }
 
 use crate::vm::AsyncResultAccessTrackerInner;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Header {
     pub key: Cow<'static, str>,
     pub value: Cow<'static, str>,
@@ -67,6 +72,30 @@ impl Error {
         self.code
     }
 
+    /// Returns false if retrying the invocation can't fix this error, that is for bad requests, journal mismatches, protocol violations,
+    /// unsupported media types, unsupported features of the negotiated protocol version, invalid async result handles,
+    /// outputs exceeding [`VMOptions::max_total_output_bytes`] and async results left unawaited with [`VMOptions::strict_completion_check`].
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self.code_enum(),
+            Some(
+                error::ErrorCode::BadRequest
+                    | error::ErrorCode::JournalMismatch
+                    | error::ErrorCode::ProtocolViolation
+                    | error::ErrorCode::UnsupportedMediaType
+                    | error::ErrorCode::UnsupportedFeature
+                    | error::ErrorCode::InvalidAsyncResultHandle
+                    | error::ErrorCode::OutputTooLarge
+                    | error::ErrorCode::UnawaitedAsyncResults
+            )
+        )
+    }
+
+    /// Returns the typed code, if [`Error::code`] is one of the well-known [`error::codes`].
+    pub fn code_enum(&self) -> Option<error::ErrorCode> {
+        error::ErrorCode::from_code(self.code)
+    }
+
     pub fn message(&self) -> &str {
         &self.message
     }
@@ -109,12 +138,25 @@ pub enum SuspendedOrVMError {
 }
 
 #[derive(Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub struct Input {
     pub invocation_id: String,
     pub random_seed: u64,
     pub key: String,
     pub headers: Vec<Header>,
     pub input: Bytes,
+    /// Additional attributes of the invocation sent by the runtime when starting it.
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Input {
+    /// Returns the value of the start attribute `key`, if the runtime sent it.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -126,9 +168,56 @@ pub struct Target {
     pub headers: Vec<Header>,
 }
 
+impl Target {
+    /// Checks the target can be invoked, that is the service and handler names are not empty,
+    /// and the idempotency key, when set, is not empty.
+    ///
+    /// This is checked by [`VM::sys_call`] and [`VM::sys_send`] too, failing the invocation.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.service.is_empty() {
+            return Err(vm::errors::EMPTY_SERVICE_NAME);
+        }
+        if self.handler.is_empty() {
+            return Err(vm::errors::EMPTY_HANDLER_NAME);
+        }
+        if self.idempotency_key.as_ref().is_some_and(String::is_empty) {
+            return Err(vm::errors::EMPTY_IDEMPOTENCY_KEY);
+        }
+        Ok(())
+    }
+
+    /// Add a header to the request. If the same key is added more than once, the last value wins.
+    pub fn with_header(
+        mut self,
+        key: impl Into<Cow<'static, str>>,
+        value: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        self.headers.push(Header {
+            key: key.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    /// Add the given headers to the request. If the same key is added more than once, the last value wins.
+    pub fn with_headers(
+        mut self,
+        headers: impl IntoIterator<Item = (Cow<'static, str>, Cow<'static, str>)>,
+    ) -> Self {
+        self.headers.extend(
+            headers
+                .into_iter()
+                .map(|(key, value)| Header { key, value }),
+        );
+        self
+    }
+}
+
 #[derive(Debug, Hash, Clone, Copy, Eq, PartialEq)]
 pub struct AsyncResultHandle(u32);
 
+/// Unchecked conversion, the handle might not correspond to any async result of the VM.
+/// Prefer [`VM::resolve_handle`] for raw values coming from outside the SDK.
 impl From<u32> for AsyncResultHandle {
     fn from(value: u32) -> Self {
         AsyncResultHandle(value)
@@ -156,19 +245,135 @@ impl From<SendHandle> for u32 {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+/// Type of a journal entry, as recorded by the runtime.
+#[derive(Debug, Hash, Clone, Copy, Eq, PartialEq)]
+pub enum CommandType {
+    Input,
+    Output,
+    GetState,
+    GetStateKeys,
+    SetState,
+    ClearState,
+    ClearAllState,
+    ClearStatePrefix,
+    GetPromise,
+    PeekPromise,
+    CompletePromise,
+    CompletePromiseIfUnset,
+    Sleep,
+    Call,
+    OneWayCall,
+    Awakeable,
+    CompleteAwakeable,
+    Run,
+    CancelInvocation,
+    GetCallInvocationId,
+    AttachInvocation,
+    GetInvocationOutput,
+    Combinator,
+}
+
+impl CommandType {
+    /// Human readable name of the command, to be used in logs and error messages.
+    pub const fn display_name(&self) -> &'static str {
+        match self {
+            CommandType::Input => "input",
+            CommandType::Output => "output",
+            CommandType::GetState => "get state",
+            CommandType::GetStateKeys => "get state keys",
+            CommandType::SetState => "set state",
+            CommandType::ClearState => "clear state",
+            CommandType::ClearAllState => "clear all state",
+            CommandType::ClearStatePrefix => "clear state by prefix",
+            CommandType::GetPromise => "get promise",
+            CommandType::PeekPromise => "peek promise",
+            CommandType::CompletePromise => "complete promise",
+            CommandType::CompletePromiseIfUnset => "complete promise if unset",
+            CommandType::Sleep => "sleep",
+            CommandType::Call => "call",
+            CommandType::OneWayCall => "one way call",
+            CommandType::Awakeable => "awakeable",
+            CommandType::CompleteAwakeable => "complete awakeable",
+            CommandType::Run => "run",
+            CommandType::CancelInvocation => "cancel invocation",
+            CommandType::GetCallInvocationId => "get call invocation id",
+            CommandType::AttachInvocation => "attach invocation",
+            CommandType::GetInvocationOutput => "get invocation output",
+            CommandType::Combinator => "combinator",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Value {
     /// a void/None/undefined success
     Void,
     Success(Bytes),
     Failure(TerminalFailure),
-    /// Only returned for get_state_keys
+    /// Only returned for get_state_keys, always sorted, both when resolved eagerly or by the runtime.
     StateKeys(Vec<String>),
     /// Only returned for get_call_invocation_id
     InvocationId(String),
+    /// Only returned for complete_promise_if_unset: true if this call completed the promise, false if it was already completed
+    PromiseCompleted(bool),
     CombinatorResult(Vec<AsyncResultHandle>),
 }
 
+impl Value {
+    pub fn is_void(&self) -> bool {
+        matches!(self, Value::Void)
+    }
+
+    pub fn into_success(self) -> Option<Bytes> {
+        match self {
+            Value::Success(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn into_failure(self) -> Option<TerminalFailure> {
+        match self {
+            Value::Failure(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    pub fn as_state_keys(&self) -> Option<&[String]> {
+        match self {
+            Value::StateKeys(keys) => Some(keys),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> Cow<'static, str> {
+        match self {
+            Value::Void => Cow::Borrowed("void"),
+            Value::Success(_) => Cow::Borrowed("success"),
+            Value::Failure(f) => Cow::Owned(format!("failure [{}]: {}", f.code, f.message)),
+            Value::StateKeys(_) => Cow::Borrowed("state keys"),
+            Value::InvocationId(_) => Cow::Borrowed("invocation id"),
+            Value::PromiseCompleted(_) => Cow::Borrowed("promise completed"),
+            Value::CombinatorResult(_) => Cow::Borrowed("combinator result"),
+        }
+    }
+}
+
+/// Returned when converting a [`Value`] other than [`Value::Success`] into [`Bytes`]. Contains the original value.
+#[derive(Debug, thiserror::Error)]
+#[error("Expected a success value, got {}", .0.describe())]
+pub struct NotSuccessValueError(pub Value);
+
+impl TryFrom<Value> for Bytes {
+    type Error = NotSuccessValueError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Success(b) => Ok(b),
+            v => Err(NotSuccessValueError(v)),
+        }
+    }
+}
+
 /// Terminal failure
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct TerminalFailure {
@@ -176,7 +381,91 @@ pub struct TerminalFailure {
     pub message: String,
 }
 
-#[derive(Debug, Default)]
+impl TerminalFailure {
+    /// Create a new terminal failure.
+    ///
+    /// ```
+    /// use restate_sdk_shared_core::TerminalFailure;
+    ///
+    /// let failure = TerminalFailure::new(404, "Not found");
+    /// assert_eq!(failure.code, 404);
+    /// assert_eq!(failure.message, "Not found");
+    /// ```
+    pub fn new(code: u16, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Create a new terminal failure with code 400 Bad Request.
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(vm::errors::codes::BAD_REQUEST.into(), message)
+    }
+
+    /// Create a new terminal failure with code 404 Not Found.
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(404, message)
+    }
+
+    /// Create the terminal failure the runtime uses for cancelled invocations.
+    pub fn cancelled() -> Self {
+        Self::new(
+            vm::errors::codes::CANCELLED.into(),
+            vm::errors::CANCELLED_MESSAGE,
+        )
+    }
+}
+
+/// Journal progress, see [`VM::journal_stats`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct JournalStats {
+    /// Index of the last journal entry, or -1 if no entry was processed yet.
+    pub entry_index: i64,
+    /// Number of async results created and not taken yet, either still waiting for their completion or ready.
+    pub pending_async_results: usize,
+}
+
+/// State machine transition, see [`CoreVM::transition_trace`].
+#[cfg(feature = "trace_events")]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TransitionEvent {
+    pub event: &'static str,
+    pub from_state: &'static str,
+    /// `Errored` if the transition failed.
+    pub to_state: &'static str,
+    /// Journal index after the transition.
+    pub command_index: i64,
+}
+
+/// Counters of the journal operations of the current invocation, see [`CoreVM::metrics`].
+#[cfg(feature = "vm_stats")]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct InvocationMetrics {
+    /// Number of commands, both replayed and new, by type.
+    pub commands: std::collections::HashMap<CommandType, u64>,
+    pub suspensions: u64,
+    pub journal_mismatches: u64,
+}
+
+/// Kind of the invoked service, as far as it can be inferred from the start message, see [`VM::invocation_kind`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InvocationKind {
+    /// A virtual object or a workflow, invoked with a non-empty key.
+    Keyed,
+    /// Either a plain service, or a virtual object or workflow invoked with an empty key.
+    /// The start message carries the key only, without telling these cases apart.
+    Unknown,
+}
+
+/// Describes a suspension, see [`VM::last_suspension`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SuspensionInfo {
+    /// Indexes of the entries whose completion the VM was waiting on.
+    pub entry_indexes: Vec<u32>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct EntryRetryInfo {
     /// Number of retries that happened so far for this entry.
     pub retry_count: u32,
@@ -190,6 +479,16 @@ pub enum RunEnterResult {
     NotExecuted(EntryRetryInfo),
 }
 
+/// Kind of side effect executed by a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RunKind {
+    /// The result is journaled, so it's replayed instead of executing the run again.
+    #[default]
+    AtLeastOnce,
+    /// The side effect is safe to repeat: the result is not journaled, and the run is executed again on replay.
+    Idempotent,
+}
+
 #[derive(Debug, Clone)]
 pub enum RunExitResult {
     Success(Bytes),
@@ -200,6 +499,13 @@ pub enum RunExitResult {
     },
 }
 
+impl RunExitResult {
+    /// Create a [`RunExitResult::Success`], e.g. from a `BytesMut` without copying it.
+    pub fn success(value: impl Into<Bytes>) -> Self {
+        RunExitResult::Success(value.into())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum NonEmptyValue {
     Success(Bytes),
@@ -221,6 +527,14 @@ pub enum GetInvocationIdTarget {
     SendEntry(SendHandle),
 }
 
+/// See [`VM::call_invocation_id`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CallInvocationIdResult {
+    Ready(String),
+    /// The invocation id is not available yet, await the handle as with [`VM::sys_get_call_invocation_id`].
+    Pending(AsyncResultHandle),
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum CancelInvocationTarget {
     InvocationId(String),
@@ -253,15 +567,83 @@ pub enum TakeOutputResult {
 
 pub type VMResult<T> = Result<T, Error>;
 
+/// Input header carrying the invocation deadline, in milliseconds since the Unix epoch. See [`VM::invocation_deadline`].
+pub const INVOCATION_DEADLINE_HEADER: &str = "x-restate-deadline-ms";
+
+/// Callback invoked with the journal indexes the invocation is waiting on, when the state machine suspends.
+///
+/// Shared by the clones of the [`CoreVM`].
+pub type SuspensionCallback = Arc<dyn Fn(&[u32]) + Send + Sync + 'static>;
+
+/// What to do when the invocation can't make progress without new input, see [`VMOptions::suspension_policy`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum SuspensionPolicy {
+    /// Write a `SuspensionMessage` and close the output.
+    #[default]
+    Default,
+    /// Fail the invocation with an error with code [`error::codes::WOULD_SUSPEND`] instead of suspending.
+    NeverSuspend,
+}
+
+#[derive(Clone)]
 pub struct VMOptions {
     /// If true, false when two concurrent async results are awaited at the same time. If false, just log it.
     pub fail_on_wait_concurrent_async_result: bool,
+    /// If set, invoked right before writing the `SuspensionMessage`, with the same entry indexes.
+    pub on_suspension: Option<SuspensionCallback>,
+    /// Whether the VM suspends when it needs new input to make progress, see [`SuspensionPolicy`].
+    pub suspension_policy: SuspensionPolicy,
+    /// High-water mark of the output buffer, in bytes. When reached, [`VM::is_output_buffer_full`] returns true,
+    /// and the host should drain the output with [`VM::take_output`] before invoking further syscalls.
+    pub max_output_buffer_bytes: Option<usize>,
+    /// Maximum number of bytes of journal entries the invocation can write in total, across all the syscalls.
+    /// Once crossed, the syscall fails and the invocation is failed with the non retryable [`error::codes::OUTPUT_TOO_LARGE`].
+    pub max_total_output_bytes: Option<usize>,
+    /// Maximum size in bytes of a single message received from the runtime, see [`DEFAULT_MAX_MESSAGE_SIZE`].
+    /// Bigger messages fail the invocation with the non retryable [`error::codes::PROTOCOL_VIOLATION`] before being buffered,
+    /// as retrying would receive the same message again.
+    pub max_message_size: usize,
+    /// If true, state gets and state keys are never resolved from the eager state sent by the runtime,
+    /// but always with a completion. Replaying entries resolved eagerly is still supported.
+    pub force_lazy_state: bool,
+    /// If true, [`VM::sys_end`] fails with the non retryable [`error::codes::UNAWAITED_ASYNC_RESULTS`]
+    /// when some async results were created but never taken, e.g. a call whose response was never awaited.
+    /// The gets issued ahead of time by [`VM::prefetch_state_keys`] are not checked until the SDK requests them.
+    pub strict_completion_check: bool,
+    /// If true, [`VM::sys_write_output`] fails when the invocation output was already written,
+    /// instead of writing a second output entry. Output entries replayed from the journal are not checked,
+    /// but they count as written output for the following calls.
+    pub strict_output_check: bool,
+    /// If true, accepts the experimental protocol version [`Version::maximum_experimental_version`].
+    /// The features gated behind it, like [`ProtocolFeature::ClearStatePrefix`], are otherwise unavailable.
+    pub enable_experimental_protocol: bool,
+    /// Headers appended to the response head after the `content-type`, see [`VM::get_response_head`].
+    /// A `content-type` header in this list is ignored, as it's determined by the negotiated protocol version.
+    pub extra_response_headers: Vec<Header>,
+    /// Maximum number of transitions retained by `CoreVM::transition_trace`, the oldest ones are discarded first.
+    /// Used only with the `trace_events` feature.
+    pub transition_trace_capacity: usize,
+    /// If true, each written frame is followed by the CRC32 of its payload, which the [`Decoder`] validates.
+    /// The runtime doesn't understand the checksum trailer: use it only to debug corrupted journals.
+    pub output_checksum: bool,
 }
 
 impl Default for VMOptions {
     fn default() -> Self {
         Self {
             fail_on_wait_concurrent_async_result: true,
+            on_suspension: None,
+            suspension_policy: SuspensionPolicy::Default,
+            max_output_buffer_bytes: None,
+            max_total_output_bytes: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            force_lazy_state: false,
+            strict_completion_check: false,
+            strict_output_check: false,
+            enable_experimental_protocol: false,
+            extra_response_headers: Vec::new(),
+            transition_trace_capacity: 64,
+            output_checksum: false,
         }
     }
 }
@@ -269,12 +651,29 @@ impl Default for VMOptions {
 pub trait VM: Sized {
     fn new(request_headers: impl HeaderMap, options: VMOptions) -> VMResult<Self>;
 
+    /// Prepares this VM for a new invocation, reusing its allocations where possible.
+    /// Behaves as a VM freshly created with [`VM::new`]. On error, the VM is left untouched.
+    fn reset(&mut self, request_headers: impl HeaderMap, options: VMOptions) -> VMResult<()>;
+
+    /// Returns the response status and headers: the `content-type` of the negotiated version first,
+    /// followed by [`VMOptions::extra_response_headers`].
     fn get_response_head(&self) -> ResponseHead;
 
+    /// Returns the protocol version negotiated from the request `content-type`.
+    fn negotiated_version(&self) -> Version;
+
+    /// Returns true if the negotiated protocol version supports the given feature.
+    ///
+    /// SDKs can use this to decide whether to expose an API at all, rather than failing with an unsupported feature error when invoking the syscall.
+    fn supports(&self, feature: ProtocolFeature) -> bool;
+
     // --- Input stream
 
     fn notify_input(&mut self, buffer: Bytes);
 
+    /// Like [`VM::notify_input`], but accepts any [`Buf`], e.g. a `BytesMut` or chained buffers, without requiring a contiguous [`Bytes`].
+    fn notify_input_buf(&mut self, buf: impl Buf);
+
     fn notify_input_closed(&mut self);
 
     // --- Errors
@@ -285,6 +684,18 @@ pub trait VM: Sized {
 
     fn take_output(&mut self) -> TakeOutputResult;
 
+    /// Advisory hint telling whether the output should be taken and flushed now,
+    /// because since the last [`VM::take_output`] the VM wrote entries the runtime should see promptly, or closed the output.
+    ///
+    /// State mutations and entries completed eagerly alone don't require a flush.
+    fn flush_hint(&self) -> bool;
+
+    /// Returns the number of bytes written by the state machine and not yet taken with [`VM::take_output`].
+    fn output_buffer_len(&self) -> usize;
+
+    /// Returns true if the output buffer reached [`VMOptions::max_output_buffer_bytes`].
+    fn is_output_buffer_full(&self) -> bool;
+
     // --- Execution start waiting point
 
     fn is_ready_to_execute(&self) -> VMResult<bool>;
@@ -293,26 +704,78 @@ pub trait VM: Sized {
 
     fn notify_await_point(&mut self, handle: AsyncResultHandle);
 
-    /// Ok(None) means the result is not ready.
+    /// Ok(None) means the result is not ready, or it was already taken.
+    ///
+    /// Fails with [`error::codes::INVALID_ASYNC_RESULT_HANDLE`] if the handle doesn't belong to a completable entry of this invocation.
     fn take_async_result(
         &mut self,
         handle: AsyncResultHandle,
     ) -> Result<Option<Value>, SuspendedOrVMError>;
 
+    /// Takes the results of the given handles that are ready, leaving the other ones untouched.
+    /// Duplicated handles and handles whose result was already taken are skipped.
+    fn take_ready_async_results(
+        &mut self,
+        handles: &[AsyncResultHandle],
+    ) -> Result<Vec<(AsyncResultHandle, Value)>, SuspendedOrVMError>;
+
+    /// Splits the given handles in `(ready, pending)`, without taking any result.
+    ///
+    /// The SDK can take the ready results, and await the pending ones with [`VM::notify_await_point`], suspending if needed.
+    fn partition_ready(
+        &self,
+        handles: &[AsyncResultHandle],
+    ) -> (Vec<AsyncResultHandle>, Vec<AsyncResultHandle>);
+
+    /// Returns true if at least one of the given handles has a result ready to be taken.
+    fn any_completed(&self, handles: &[AsyncResultHandle]) -> bool;
+
+    /// Returns true if all the given handles have a result ready to be taken.
+    fn all_completed(&self, handles: &[AsyncResultHandle]) -> bool;
+
+    /// Returns the length in bytes of the ready result of the handle without taking it,
+    /// that is the payload of a [`Value::Success`] or the message of a [`Value::Failure`].
+    ///
+    /// Returns `None` if the result is not ready, or it's a different variant.
+    fn ready_result_len(&self, handle: AsyncResultHandle) -> Option<usize>;
+
     // --- Syscall(s)
 
     fn sys_input(&mut self) -> VMResult<Input>;
 
     fn sys_state_get(&mut self, key: String) -> VMResult<AsyncResultHandle>;
 
+    /// Like [`VM::sys_state_get`], but records `name` in the journal entry, to identify it in logs and journal mismatch errors.
+    fn sys_state_get_with_name(&mut self, key: String, name: String)
+        -> VMResult<AsyncResultHandle>;
+
+    /// Hints the keys the handler is going to read. The next get state that needs to be resolved by the runtime
+    /// also issues the gets of the hinted keys not known locally, so the runtime can complete them all at once,
+    /// and the following [`VM::sys_state_get`] of those keys return the handles of the already issued gets.
+    ///
+    /// The hints must be given deterministically, as the issued gets are recorded in the journal.
+    fn prefetch_state_keys(&mut self, keys: Vec<String>);
+
     fn sys_state_get_keys(&mut self) -> VMResult<AsyncResultHandle>;
 
+    /// Like [`VM::sys_state_get_keys`], but records `name` in the journal entry.
+    fn sys_state_get_keys_with_name(&mut self, name: String) -> VMResult<AsyncResultHandle>;
+
     fn sys_state_set(&mut self, key: String, value: Bytes) -> VMResult<()>;
 
+    /// Like [`VM::sys_state_set`], but records `name` in the journal entry.
+    fn sys_state_set_with_name(&mut self, key: String, value: Bytes, name: String) -> VMResult<()>;
+
     fn sys_state_clear(&mut self, key: String) -> VMResult<()>;
 
+    /// Like [`VM::sys_state_clear`], but records `name` in the journal entry.
+    fn sys_state_clear_with_name(&mut self, key: String, name: String) -> VMResult<()>;
+
     fn sys_state_clear_all(&mut self) -> VMResult<()>;
 
+    /// Clear all the state keys starting with `prefix`. Requires [`ProtocolFeature::ClearStatePrefix`].
+    fn sys_state_clear_prefix(&mut self, prefix: String) -> VMResult<()>;
+
     /// Note: `now_since_unix_epoch` is only used for debugging purposes
     fn sys_sleep(
         &mut self,
@@ -320,8 +783,36 @@ pub trait VM: Sized {
         now_since_unix_epoch: Option<Duration>,
     ) -> VMResult<AsyncResultHandle>;
 
+    /// Like [`VM::sys_sleep`], computing the wake up time as `now_since_unix_epoch + duration`.
+    fn sys_sleep_for(
+        &mut self,
+        name: String,
+        duration: Duration,
+        now_since_unix_epoch: Duration,
+    ) -> VMResult<AsyncResultHandle>;
+
     fn sys_call(&mut self, target: Target, input: Bytes) -> VMResult<AsyncResultHandle>;
 
+    /// Like [`VM::sys_call`], scheduling the call execution at the given time. Requires [`ProtocolFeature::DelayedCall`] when the execution time is set.
+    fn sys_call_delayed(
+        &mut self,
+        target: Target,
+        input: Bytes,
+        execution_time_since_unix_epoch: Option<Duration>,
+    ) -> VMResult<AsyncResultHandle>;
+
+    /// Calls `target`, then sleeps for `timeout`, returning the call and the sleep handles in this order.
+    /// The SDK races the two handles to give up on the call after `timeout`.
+    ///
+    /// The call entry is always written before the sleep entry, so the journal is the same across SDKs.
+    fn sys_call_with_timeout(
+        &mut self,
+        target: Target,
+        input: Bytes,
+        timeout: Duration,
+        now_since_unix_epoch: Duration,
+    ) -> VMResult<(AsyncResultHandle, AsyncResultHandle)>;
+
     fn sys_send(
         &mut self,
         target: Target,
@@ -329,10 +820,33 @@ pub trait VM: Sized {
         execution_time_since_unix_epoch: Option<Duration>,
     ) -> VMResult<SendHandle>;
 
+    /// Like [`VM::sys_send`], additionally returning a handle completed with [`Value::Void`] once the runtime durably accepted the send.
+    ///
+    /// When the negotiated version doesn't support [`ProtocolFeature::SendAcceptance`], this behaves like [`VM::sys_send`] and no acceptance handle is returned.
+    fn sys_send_with_acceptance(
+        &mut self,
+        target: Target,
+        input: Bytes,
+        execution_time_since_unix_epoch: Option<Duration>,
+    ) -> VMResult<(SendHandle, Option<AsyncResultHandle>)>;
+
     fn sys_awakeable(&mut self) -> VMResult<(String, AsyncResultHandle)>;
 
     fn sys_complete_awakeable(&mut self, id: String, value: NonEmptyValue) -> VMResult<()>;
 
+    /// Like [`VM::sys_complete_awakeable`], attaching an idempotency key the runtime uses to deduplicate the completion.
+    /// The key must be non empty. Requires [`ProtocolFeature::IdempotencyKeyOnCompletion`].
+    fn sys_complete_awakeable_with_idempotency_key(
+        &mut self,
+        id: String,
+        value: NonEmptyValue,
+        idempotency_key: String,
+    ) -> VMResult<()>;
+
+    /// Like [`VM::sys_complete_awakeable`], completing the awakeable with a void success, received as [`Value::Void`].
+    /// Requires [`ProtocolFeature::CompleteAwakeableWithVoid`].
+    fn sys_complete_awakeable_void(&mut self, id: String) -> VMResult<()>;
+
     fn sys_get_promise(&mut self, key: String) -> VMResult<AsyncResultHandle>;
 
     fn sys_peek_promise(&mut self, key: String) -> VMResult<AsyncResultHandle>;
@@ -343,19 +857,67 @@ pub trait VM: Sized {
         value: NonEmptyValue,
     ) -> VMResult<AsyncResultHandle>;
 
+    /// Like [`VM::sys_complete_promise`], attaching an idempotency key the runtime uses to deduplicate the completion.
+    /// The key must be non empty. Requires [`ProtocolFeature::IdempotencyKeyOnCompletion`].
+    fn sys_complete_promise_with_idempotency_key(
+        &mut self,
+        key: String,
+        value: NonEmptyValue,
+        idempotency_key: String,
+    ) -> VMResult<AsyncResultHandle>;
+
+    /// Completes the promise only if it's not completed yet. The result is [`Value::PromiseCompleted`], telling whether this call won the race.
+    ///
+    /// Requires [`ProtocolFeature::CompletePromiseIfUnset`].
+    fn sys_complete_promise_if_unset(
+        &mut self,
+        key: String,
+        value: NonEmptyValue,
+    ) -> VMResult<AsyncResultHandle>;
+
     fn sys_run_enter(&mut self, name: String) -> VMResult<RunEnterResult>;
 
+    /// Like [`VM::sys_run_enter`], for the given [`RunKind`].
+    ///
+    /// [`RunKind::Idempotent`] runs don't write any entry and are executed every time, replay included,
+    /// so this returns [`RunEnterResult::NotExecuted`]. Complete them with [`VM::sys_run_exit_idempotent`].
+    fn sys_run_enter_with_kind(&mut self, name: String, kind: RunKind) -> VMResult<RunEnterResult>;
+
     fn sys_run_exit(
         &mut self,
         value: RunExitResult,
         retry_policy: RetryPolicy,
     ) -> VMResult<AsyncResultHandle>;
 
+    /// Like [`VM::sys_run_exit`] with a [`RunExitResult::Success`], but accepts the result as any [`Buf`],
+    /// e.g. chained buffers. Contiguous `Bytes` and `BytesMut` are not copied.
+    fn sys_run_exit_success_buf(
+        &mut self,
+        value: impl Buf,
+        retry_policy: RetryPolicy,
+    ) -> VMResult<AsyncResultHandle>;
+
+    /// Completes a [`RunKind::Idempotent`] run, returning its value without journaling it.
+    ///
+    /// Retryable failures follow the `retry_policy` like in [`VM::sys_run_exit`].
+    fn sys_run_exit_idempotent(
+        &mut self,
+        value: RunExitResult,
+        retry_policy: RetryPolicy,
+    ) -> VMResult<NonEmptyValue>;
+
     fn sys_get_call_invocation_id(
         &mut self,
         call: GetInvocationIdTarget,
     ) -> VMResult<AsyncResultHandle>;
 
+    /// Like [`VM::sys_get_call_invocation_id`], but returns the invocation id right away when it's already available,
+    /// e.g. because the entry was replayed with its result.
+    fn call_invocation_id(
+        &mut self,
+        call: GetInvocationIdTarget,
+    ) -> VMResult<CallInvocationIdResult>;
+
     fn sys_cancel_invocation(&mut self, target: CancelInvocationTarget) -> VMResult<()>;
 
     fn sys_attach_invocation(&mut self, target: AttachInvocationTarget) -> VMResult<()>;
@@ -366,12 +928,93 @@ pub trait VM: Sized {
 
     fn sys_end(&mut self) -> VMResult<()>;
 
+    /// Writes the output and ends the invocation, that is `sys_write_output` followed by `sys_end`.
+    /// If writing the output fails, the invocation is not ended.
+    fn sys_complete(&mut self, value: NonEmptyValue) -> VMResult<()>;
+
+    /// Completes the invocation with the standard cancellation failure, that is [`error::codes::CANCELLED`], and ends it.
+    fn sys_self_cancel(&mut self) -> VMResult<()>;
+
+    /// Runs a handler that only reads the input and writes the output: `sys_input`, then `f`, then `sys_complete`.
+    ///
+    /// On replay the output is matched against the journal as with `sys_write_output`.
+    fn run_simple(&mut self, f: impl FnOnce(Input) -> NonEmptyValue) -> VMResult<()>;
+
     /// Returns true if the state machine is in processing state
     fn is_processing(&self) -> bool;
 
     /// Returns true if the state machine is between a sys_run_enter and sys_run_exit
     fn is_inside_run(&self) -> bool;
 
+    /// Returns the handle the next completable syscall will be assigned, without advancing the journal.
+    ///
+    /// This is meaningful only right before invoking a completable syscall, as every other syscall advances the journal too.
+    fn peek_next_async_result_handle(&self) -> AsyncResultHandle;
+
+    /// Returns true if the entry backing the handle was read from the replayed journal, rather than newly written to the output.
+    fn was_replayed(&self, handle: AsyncResultHandle) -> bool;
+
+    /// Returns the handle for the given raw value, if it corresponds to an async result of this VM not taken yet.
+    fn resolve_handle(&self, raw: u32) -> Option<AsyncResultHandle>;
+
+    /// Returns true if the runtime durably stored the entry at the given journal index, either because it was replayed,
+    /// or because the runtime acknowledged it or a following entry with an `EntryAckMessage`.
+    ///
+    /// The runtime explicitly acknowledges only the entries requiring an ack, such as run entries.
+    /// Acknowledgements are tracked only while the state machine is replaying or processing.
+    fn is_entry_acked(&self, entry_index: u32) -> bool;
+
+    /// Returns the handles whose result was read from the replayed journal, rather than received as a completion, together with their entry type.
+    ///
+    /// The list is available only while the state machine is replaying or processing, and it's complete once replay is over.
+    fn replayed_resolutions(&self) -> Vec<(AsyncResultHandle, CommandType)>;
+
+    /// Returns the replayed journal commands not executed yet by the handler, together with their entry index, without consuming them.
+    ///
+    /// Returns `None` outside the replay, that is before the start message and once processing started.
+    /// Custom entries are not listed.
+    fn replay_commands(&self) -> Option<Vec<(CommandType, u32)>>;
+
+    /// Returns the message types defined by the negotiated protocol version.
+    fn supported_message_types(&self) -> &'static [MessageType];
+
+    /// Returns the journal progress of the invocation.
+    fn journal_stats(&self) -> JournalStats;
+
+    /// Returns the approximate time elapsed since the runtime stored the last journal entry, as reported in the start message.
+    ///
+    /// Returns `None` until the start message has been received.
+    fn time_since_last_stored_entry(&self) -> Option<Duration>;
+
+    /// Returns the retry info of the next entry, as reported to [`RunEnterResult::NotExecuted`].
+    ///
+    /// Only the first entry written after the replay inherits the retry info of the start message: for every other entry, and before the start message, this is zeroed.
+    fn current_retry_info(&self) -> EntryRetryInfo;
+
+    /// Returns the kind of the invoked service, inferred from the key in the start message.
+    ///
+    /// Returns `None` until the start message has been received.
+    fn invocation_kind(&self) -> Option<InvocationKind>;
+
+    /// Returns what the VM was waiting on when it suspended, or `None` if it didn't suspend.
+    fn last_suspension(&self) -> Option<SuspensionInfo>;
+
+    /// Returns the invocation deadline, as a duration since the Unix epoch.
+    ///
+    /// This is read by `sys_input` from the [`INVOCATION_DEADLINE_HEADER`] input header, containing the deadline in milliseconds since the Unix epoch.
+    /// Returns `None` before `sys_input`, or if the header is absent or malformed.
+    fn invocation_deadline(&self) -> Option<Duration>;
+
+    /// Returns the retry delay that will be sent to the runtime together with the error, if any.
+    ///
+    /// This is set when a `sys_run_exit` with a retryable failure is retried according to the given [`RetryPolicy`].
+    fn next_retry_delay(&self) -> Option<Duration>;
+
+    /// Returns the currently known state entries, sorted by key, including the `sys_state_set`/`sys_state_clear` executed so far.
+    ///
+    /// Returns `None` when the runtime sent only a partial state, as the snapshot wouldn't be authoritative.
+    fn eager_state_snapshot(&self) -> Option<Vec<(String, Bytes)>>;
+
     /// Returns false if the combinator can't be completed yet.
     fn sys_try_complete_combinator(
         &mut self,
@@ -12,12 +12,14 @@ use crate::headers::HeaderMap;
 use jsonwebtoken::{DecodingKey, Validation};
 use serde::Deserialize;
 use std::collections::HashSet;
+use std::time::Duration;
 
 const SIGNATURE_SCHEME_HEADER: &str = "x-restate-signature-scheme";
 const SIGNATURE_SCHEME_V1: &str = "v1";
 const SIGNATURE_SCHEME_UNSIGNED: &str = "unsigned";
 const SIGNATURE_JWT_V1_HEADER: &str = "x-restate-jwt-v1";
 const IDENTITY_V1_PREFIX: &str = "publickeyv1_";
+const DEFAULT_ALLOWED_CLOCK_SKEW: Duration = Duration::from_secs(30);
 
 #[derive(Debug, thiserror::Error)]
 pub enum KeyError {
@@ -48,7 +50,8 @@ pub enum VerifyError {
 
 pub struct IdentityVerifier {
     validation: Validation,
-    keys: Vec<DecodingKey>,
+    // Original public key string, together with the parsed key
+    keys: Vec<(String, DecodingKey)>,
 }
 
 impl Default for IdentityVerifier {
@@ -56,7 +59,7 @@ impl Default for IdentityVerifier {
         let mut validation = Validation::new(jsonwebtoken::Algorithm::EdDSA);
         validation.required_spec_claims =
             HashSet::from(["aud".into(), "exp".into(), "iat".into(), "nbf".into()]);
-        validation.leeway = 0;
+        validation.leeway = DEFAULT_ALLOWED_CLOCK_SKEW.as_secs();
         validation.reject_tokens_expiring_in_less_than = 0;
         validation.validate_exp = true;
         validation.validate_nbf = true;
@@ -82,13 +85,20 @@ impl IdentityVerifier {
     }
 
     pub fn with_key(mut self, key: &str) -> Result<Self, KeyError> {
-        self.keys.push(Self::parse_key(key)?);
+        self.keys.push((key.to_owned(), Self::parse_key(key)?));
         Ok(Self {
             validation: self.validation,
             keys: self.keys,
         })
     }
 
+    /// Tolerance applied to both the expiry and the not-before claims, to account for clock differences
+    /// between the runtime and the service host. Defaults to 30 seconds.
+    pub fn with_allowed_clock_skew(mut self, allowed_clock_skew: Duration) -> Self {
+        self.validation.leeway = allowed_clock_skew.as_secs();
+        self
+    }
+
     fn parse_key(key: &str) -> Result<DecodingKey, KeyError> {
         if !key.starts_with(IDENTITY_V1_PREFIX) {
             return Err(KeyError::MissingPrefix);
@@ -102,27 +112,32 @@ impl IdentityVerifier {
         Ok(DecodingKey::from_ed_der(&decoded_key))
     }
 
-    fn check_v1_keys(&self, jwt_token: &str, path: &str) -> Result<(), VerifyError> {
+    fn check_v1_keys(&self, jwt_token: &str, path: &str) -> Result<&str, VerifyError> {
         let mut validation = self.validation.clone();
         validation.set_audience(&[path]);
-        let mut res = Ok(());
-        for k in &self.keys {
-            if let Err(e) = jsonwebtoken::decode::<Claims>(jwt_token, k, &validation) {
-                res = Err(e);
-            } else {
-                return Ok(());
+        let mut last_err = None;
+        for (key, decoding_key) in &self.keys {
+            match jsonwebtoken::decode::<Claims>(jwt_token, decoding_key, &validation) {
+                Ok(_) => return Ok(key.as_str()),
+                Err(e) => last_err = Some(e),
             }
         }
-        res.map_err(VerifyError::InvalidJWT)
+        Err(VerifyError::InvalidJWT(last_err.expect(
+            "check_v1_keys is invoked only when at least one key is configured",
+        )))
     }
 
-    pub fn verify_identity<I>(&self, hm: &I, path: &str) -> Result<(), VerifyError>
+    /// Verify the request identity against the configured keys.
+    ///
+    /// Succeeds if any of the keys validates the signature, returning the matching key, e.g. for audit logging.
+    /// Returns `None` if no keys are configured.
+    pub fn verify_identity<I>(&self, hm: &I, path: &str) -> Result<Option<&str>, VerifyError>
     where
         I: HeaderMap,
         <I as HeaderMap>::Error: std::error::Error + Send + Sync + 'static,
     {
         if self.keys.is_empty() {
-            return Ok(());
+            return Ok(None);
         }
 
         let scheme_header = hm
@@ -138,6 +153,7 @@ impl IdentityVerifier {
                     .ok_or(VerifyError::MissingHeader(SIGNATURE_JWT_V1_HEADER))?;
 
                 self.check_v1_keys(jwt, Self::normalise_path(path))
+                    .map(Some)
             }
             SIGNATURE_SCHEME_UNSIGNED => Err(VerifyError::UnsignedRequest),
             scheme => Err(VerifyError::BadSchemeHeader(scheme.to_owned())),
@@ -191,7 +207,58 @@ mod tests {
         .into_iter()
         .collect();
 
-        verifier.verify_identity(&headers, "/invoke/foo").unwrap();
+        assert_eq!(
+            verifier.verify_identity(&headers, "/invoke/foo").unwrap(),
+            Some(identity_key.as_str())
+        );
+    }
+
+    #[test]
+    fn verify_with_second_key() {
+        let (jwt, identity_key) = mock_token_and_key();
+        let (_, other_identity_key) = mock_token_and_key();
+
+        let verifier = IdentityVerifier::new(&[&other_identity_key, &identity_key]).unwrap();
+
+        let headers: Vec<(String, String)> = [
+            (
+                SIGNATURE_SCHEME_HEADER.to_owned(),
+                SIGNATURE_SCHEME_V1.to_owned(),
+            ),
+            (SIGNATURE_JWT_V1_HEADER.to_owned(), jwt),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            verifier.verify_identity(&headers, "/invoke/foo").unwrap(),
+            Some(identity_key.as_str())
+        );
+    }
+
+    #[test]
+    fn verify_with_no_matching_key() {
+        let (jwt, _) = mock_token_and_key();
+        let (_, other_identity_key_1) = mock_token_and_key();
+        let (_, other_identity_key_2) = mock_token_and_key();
+
+        let verifier =
+            IdentityVerifier::new(&[&other_identity_key_1, &other_identity_key_2]).unwrap();
+
+        let headers: Vec<(String, String)> = [
+            (
+                SIGNATURE_SCHEME_HEADER.to_owned(),
+                SIGNATURE_SCHEME_V1.to_owned(),
+            ),
+            (SIGNATURE_JWT_V1_HEADER.to_owned(), jwt),
+        ]
+        .into_iter()
+        .collect();
+
+        assert!(matches!(
+            verifier.verify_identity(&headers, "/invoke/foo"),
+            Err(VerifyError::InvalidJWT(_))
+        ));
     }
 
     #[test]
@@ -238,7 +305,65 @@ mod tests {
         }
     }
 
+    #[test]
+    fn verify_expired_within_allowed_clock_skew() {
+        let unix_seconds = unix_seconds();
+        let (jwt, identity_key) = mock_token_and_key_expiring_at(unix_seconds - 5);
+
+        let verifier = IdentityVerifier::new(&[&identity_key])
+            .unwrap()
+            .with_allowed_clock_skew(Duration::from_secs(30));
+
+        let headers: Vec<(String, String)> = [
+            (
+                SIGNATURE_SCHEME_HEADER.to_owned(),
+                SIGNATURE_SCHEME_V1.to_owned(),
+            ),
+            (SIGNATURE_JWT_V1_HEADER.to_owned(), jwt),
+        ]
+        .into_iter()
+        .collect();
+
+        verifier.verify_identity(&headers, "/invoke/foo").unwrap();
+    }
+
+    #[test]
+    fn verify_expired_beyond_allowed_clock_skew() {
+        let unix_seconds = unix_seconds();
+        let (jwt, identity_key) = mock_token_and_key_expiring_at(unix_seconds - 40);
+
+        let verifier = IdentityVerifier::new(&[&identity_key])
+            .unwrap()
+            .with_allowed_clock_skew(Duration::from_secs(30));
+
+        let headers: Vec<(String, String)> = [
+            (
+                SIGNATURE_SCHEME_HEADER.to_owned(),
+                SIGNATURE_SCHEME_V1.to_owned(),
+            ),
+            (SIGNATURE_JWT_V1_HEADER.to_owned(), jwt),
+        ]
+        .into_iter()
+        .collect();
+
+        assert!(matches!(
+            verifier.verify_identity(&headers, "/invoke/foo"),
+            Err(VerifyError::InvalidJWT(_))
+        ));
+    }
+
+    fn unix_seconds() -> u64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("duration since Unix epoch should be well-defined")
+            .as_secs()
+    }
+
     fn mock_token_and_key() -> (String, String) {
+        mock_token_and_key_expiring_at(unix_seconds().saturating_add(60))
+    }
+
+    fn mock_token_and_key_expiring_at(exp: u64) -> (String, String) {
         let serialized_keypair = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
         let keypair = Ed25519KeyPair::from_pkcs8(serialized_keypair.as_ref()).unwrap();
 
@@ -254,15 +379,12 @@ mod tests {
             alg: jsonwebtoken::Algorithm::EdDSA,
             ..Default::default()
         };
-        let unix_seconds = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .expect("duration since Unix epoch should be well-defined")
-            .as_secs();
+        let unix_seconds = unix_seconds();
         let claims = Claims {
             aud: "/invoke/foo",
             nbf: unix_seconds.saturating_sub(60),
             iat: unix_seconds,
-            exp: unix_seconds.saturating_add(60),
+            exp,
         };
         let jwt = jsonwebtoken::encode(&header, &claims, &signing_key).unwrap();
 
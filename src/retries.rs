@@ -5,10 +5,14 @@ use std::time::Duration;
 /// This struct represents the policy to execute retries.
 #[derive(Debug, Clone, Default)]
 pub enum RetryPolicy {
-    /// # Infinite
+    /// # Runtime default
     ///
-    /// Infinite retry strategy.
+    /// Don't override the retry strategy, and defer to the runtime retry policy.
     #[default]
+    RuntimeDefault,
+    /// # Infinite
+    ///
+    /// Infinite retry strategy, retrying with the runtime retry interval.
     Infinite,
     /// # None
     ///
@@ -109,7 +113,7 @@ impl RetryPolicy {
 
     pub(crate) fn next_retry(&self, retry_info: EntryRetryInfo) -> NextRetry {
         match self {
-            RetryPolicy::Infinite => NextRetry::Retry(None),
+            RetryPolicy::RuntimeDefault | RetryPolicy::Infinite => NextRetry::Retry(None),
             RetryPolicy::None => NextRetry::DoNotRetry,
             RetryPolicy::FixedDelay {
                 interval,
@@ -17,6 +17,22 @@ use std::mem;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use bytes_utils::SegmentedBuf;
 
+/// Error returned when decoding protocol messages.
+///
+/// Converts into [`Error`](crate::Error) with the same code the VM uses when it fails to decode its input:
+/// malformed frames are protocol violations.
+///
+/// ```
+/// use restate_sdk_shared_core::error::codes;
+/// use restate_sdk_shared_core::{Decoder, Error, Version, DEFAULT_MAX_MESSAGE_SIZE};
+///
+/// let mut decoder = Decoder::new(Version::maximum_supported_version(), DEFAULT_MAX_MESSAGE_SIZE);
+/// // Header of the unknown message type 0x03FF
+/// decoder.push(vec![0x03, 0xFF, 0, 0, 0, 0, 0, 0].into());
+///
+/// let error = Error::from(decoder.consume_next().unwrap_err());
+/// assert_eq!(error.code(), u16::from(codes::PROTOCOL_VIOLATION));
+/// ```
 #[derive(Debug, thiserror::Error)]
 pub enum DecodingError {
     #[error("cannot decode protocol message type {0:?}. Reason: {1:?}")]
@@ -28,11 +44,31 @@ pub enum DecodingError {
     },
     #[error(transparent)]
     UnknownMessageType(#[from] UnknownMessageType),
+    #[error("message type {0:?} is not a command")]
+    NotACommand(MessageType),
+    #[error("message of type {ty:?} has size {size} bytes, exceeding the maximum message size of {limit} bytes")]
+    MessageTooLarge {
+        ty: MessageType,
+        size: usize,
+        limit: usize,
+    },
+    #[error("checksum mismatch for message type {ty:?}: the frame declares {expected:#010x}, but the payload has {actual:#010x}")]
+    ChecksumMismatch {
+        ty: MessageType,
+        expected: u32,
+        actual: u32,
+    },
 }
 
+/// Default value of the maximum message size accepted by the [`Decoder`], 64 MiB.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
 // --- Input protocol.message encoder
 
-pub struct Encoder {}
+#[derive(Clone)]
+pub struct Encoder {
+    checksum: bool,
+}
 
 impl Encoder {
     pub fn new(service_protocol_version: Version) -> Self {
@@ -42,23 +78,39 @@ impl Encoder {
             Version::minimum_supported_version(),
             Version::maximum_supported_version()
         );
-        Self {}
+        Self { checksum: false }
+    }
+
+    /// When enabled, each frame is followed by the CRC32 of its payload, and flagged as such in the header.
+    /// The [`Decoder`] validates the checksum of the flagged frames.
+    ///
+    /// Off by default, as the runtime doesn't understand the checksum trailer: use it only to debug corrupted journals.
+    pub fn with_checksum(mut self, enabled: bool) -> Self {
+        self.checksum = enabled;
+        self
     }
 
     /// Encodes a protocol message to bytes
+    #[cfg(test)]
     pub fn encode<M: WriteableRestateMessage>(&self, msg: &M) -> Bytes {
         let mut buf = BytesMut::with_capacity(self.encoded_len(msg));
-        self.encode_to_buf_mut(&mut buf, msg).expect(
+        self.encode_into(msg, &mut buf);
+        buf.freeze()
+    }
+
+    /// Encodes a protocol message appending it to the given buffer, growing it if needed.
+    pub fn encode_into<M: WriteableRestateMessage>(&self, msg: &M, buf: &mut BytesMut) {
+        buf.reserve(self.encoded_len(msg));
+        self.encode_to_buf_mut(buf, msg).expect(
             "Encoding messages should be infallible, \
             this error indicates a bug in the invoker code. \
             Please contact the Restate developers.",
         );
-        buf.freeze()
     }
 
-    /// Includes header len
+    /// Includes header len, and checksum len if enabled
     pub fn encoded_len<M: WriteableRestateMessage>(&self, msg: &M) -> usize {
-        8 + msg.encoded_len()
+        8 + msg.encoded_len() + if self.checksum { 4 } else { 0 }
     }
 
     pub fn encode_to_buf_mut<M: WriteableRestateMessage>(
@@ -67,6 +119,13 @@ impl Encoder {
         msg: &M,
     ) -> Result<(), prost::EncodeError> {
         let header = msg.generate_header(false);
+        if self.checksum {
+            let payload = msg.encode_to_vec();
+            buf.put_u64(header.with_checksum().into());
+            buf.put_slice(&payload);
+            buf.put_u32(crc32(&payload));
+            return Ok(());
+        }
         buf.put_u64(header.into());
         // Note:
         // prost::EncodeError can be triggered only by a buffer smaller than required,
@@ -76,6 +135,33 @@ impl Encoder {
     }
 }
 
+// CRC-32 (IEEE 802.3), the same variant used by zlib and gzip.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, b| {
+        CRC32_TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
 // --- Input protocol.message decoder
 
 #[derive(Debug, Clone, PartialEq)]
@@ -86,6 +172,15 @@ impl RawMessage {
         self.0.message_type()
     }
 
+    pub(crate) fn header(&self) -> MessageHeader {
+        self.0
+    }
+
+    /// Message payload, without the header.
+    pub fn payload(&self) -> &Bytes {
+        &self.1
+    }
+
     pub fn decode_to<M: RestateMessage>(self) -> Result<M, DecodingError> {
         if self.0.message_type() != M::ty() {
             return Err(DecodingError::UnexpectedMessageType {
@@ -97,14 +192,49 @@ impl RawMessage {
     }
 }
 
+/// Command payload, without the protocol framing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedCommand {
+    pub ty: MessageType,
+    pub bytes: Bytes,
+}
+
+impl DecodedCommand {
+    pub fn decode_to<M: RestateMessage>(self) -> Result<M, DecodingError> {
+        if self.ty != M::ty() {
+            return Err(DecodingError::UnexpectedMessageType {
+                expected: M::ty(),
+                actual: self.ty,
+            });
+        }
+        M::decode(self.bytes).map_err(|e| DecodingError::DecodeMessage(self.ty, e))
+    }
+}
+
+/// Wraps the payload of a command message, checking `ty` is a command type.
+pub fn decode_command(ty: MessageType, bytes: &[u8]) -> Result<DecodedCommand, DecodingError> {
+    if !ty.is_entry() {
+        return Err(DecodingError::NotACommand(ty));
+    }
+    Ok(DecodedCommand {
+        ty,
+        bytes: Bytes::copy_from_slice(bytes),
+    })
+}
+
 /// Stateful decoder to decode [`RestateMessage`]
+#[derive(Clone)]
 pub struct Decoder {
     buf: SegmentedBuf<Bytes>,
     state: DecoderState,
+    max_message_size: usize,
+    // Set after a decoding error, as the stream can't be resynchronized anymore
+    poisoned: bool,
 }
 
 impl Decoder {
-    pub fn new(service_protocol_version: Version) -> Self {
+    /// Creates a new decoder. Messages whose payload is larger than `max_message_size` fail with [`DecodingError::MessageTooLarge`].
+    pub fn new(service_protocol_version: Version, max_message_size: usize) -> Self {
         assert!(
             service_protocol_version >= Version::minimum_supported_version(),
             "Decoder only supports service protocol version {:?} <= x <= {:?}",
@@ -114,16 +244,33 @@ impl Decoder {
         Self {
             buf: SegmentedBuf::new(),
             state: DecoderState::WaitingHeader,
+            max_message_size,
+            poisoned: false,
         }
     }
 
-    /// Concatenate a new chunk in the internal buffer.
+    /// Discards the buffered input and the poisoning, to decode a new stream.
+    pub(crate) fn reset(&mut self, max_message_size: usize) {
+        self.buf = SegmentedBuf::new();
+        self.state = DecoderState::WaitingHeader;
+        self.max_message_size = max_message_size;
+        self.poisoned = false;
+    }
+
+    /// Concatenate a new chunk in the internal buffer. The chunk is discarded if the decoder is poisoned.
     pub fn push(&mut self, buf: Bytes) {
-        self.buf.push(buf)
+        if !self.poisoned {
+            self.buf.push(buf)
+        }
     }
 
     /// Try to consume the next protocol.message in the internal buffer.
+    ///
+    /// After returning an error, the decoder is poisoned: the buffered input is dropped, and it won't return any other message.
     pub fn consume_next(&mut self) -> Result<Option<RawMessage>, DecodingError> {
+        if self.poisoned {
+            return Ok(None);
+        }
         loop {
             let remaining = self.buf.remaining();
 
@@ -131,14 +278,37 @@ impl Decoder {
                 return Ok(None);
             }
 
-            if let Some(res) = self.state.decode(&mut self.buf)? {
-                return Ok(Some(res));
+            match self.state.decode(&mut self.buf, self.max_message_size) {
+                Ok(Some(res)) => return Ok(Some(res)),
+                Ok(None) => {}
+                Err(e) => {
+                    self.poisoned = true;
+                    self.buf = SegmentedBuf::new();
+                    return Err(e);
+                }
             }
         }
     }
+
+    /// Consume all the messages available in the internal buffer.
+    ///
+    /// The iterator stops when more input is needed, or after returning the first error.
+    pub fn drain(&mut self) -> impl Iterator<Item = Result<RawMessage, DecodingError>> + '_ {
+        std::iter::from_fn(move || self.consume_next().transpose())
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Number of bytes buffered and not yet decoded.
+    #[cfg(test)]
+    pub fn buffered_len(&self) -> usize {
+        self.buf.remaining()
+    }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 enum DecoderState {
     #[default]
     WaitingHeader,
@@ -149,21 +319,47 @@ impl DecoderState {
     fn needs_bytes(&self) -> usize {
         match self {
             DecoderState::WaitingHeader => 8,
+            DecoderState::WaitingPayload(h) if h.has_checksum() => {
+                (h.frame_length() as usize).saturating_add(4)
+            }
             DecoderState::WaitingPayload(h) => h.frame_length() as usize,
         }
     }
 
-    fn decode(&mut self, mut buf: impl Buf) -> Result<Option<RawMessage>, DecodingError> {
+    fn decode(
+        &mut self,
+        mut buf: impl Buf,
+        max_message_size: usize,
+    ) -> Result<Option<RawMessage>, DecodingError> {
         let mut res = None;
 
         *self = match mem::take(self) {
             DecoderState::WaitingHeader => {
                 let header: MessageHeader = buf.get_u64().try_into()?;
+                // Check before waiting for the payload, to avoid buffering it
+                if header.frame_length() as usize > max_message_size {
+                    return Err(DecodingError::MessageTooLarge {
+                        ty: header.message_type(),
+                        size: header.frame_length() as usize,
+                        limit: max_message_size,
+                    });
+                }
                 DecoderState::WaitingPayload(header)
             }
             DecoderState::WaitingPayload(h) => {
-                let msg = RawMessage(h, buf.copy_to_bytes(h.frame_length() as usize));
-                res = Some(msg);
+                let payload = buf.copy_to_bytes(h.frame_length() as usize);
+                if h.has_checksum() {
+                    let expected = buf.get_u32();
+                    let actual = crc32(&payload);
+                    if expected != actual {
+                        return Err(DecodingError::ChecksumMismatch {
+                            ty: h.message_type(),
+                            expected,
+                            actual,
+                        });
+                    }
+                }
+                res = Some(RawMessage(h, payload));
                 DecoderState::WaitingHeader
             }
         };
@@ -177,16 +373,13 @@ mod tests {
 
     use super::*;
 
-    impl RawMessage {
-        pub fn header(&self) -> MessageHeader {
-            self.0
-        }
-    }
-
     #[test]
     fn fill_decoder_with_several_messages() {
         let encoder = Encoder::new(Version::maximum_supported_version());
-        let mut decoder = Decoder::new(Version::maximum_supported_version());
+        let mut decoder = Decoder::new(
+            Version::maximum_supported_version(),
+            DEFAULT_MAX_MESSAGE_SIZE,
+        );
 
         let expected_msg_0 = messages::StartMessage {
             id: "key".into(),
@@ -249,6 +442,201 @@ mod tests {
         assert!(decoder.consume_next().unwrap().is_none());
     }
 
+    #[test]
+    fn decode_command_payload() {
+        let expected_msg = messages::InputEntryMessage {
+            value: Bytes::from_static("input".as_bytes()),
+            ..messages::InputEntryMessage::default()
+        };
+        let payload = prost::Message::encode_to_vec(&expected_msg);
+
+        let cmd = decode_command(MessageType::InputEntry, &payload).unwrap();
+        assert_eq!(cmd.ty, MessageType::InputEntry);
+        assert_eq!(
+            cmd.decode_to::<messages::InputEntryMessage>().unwrap(),
+            expected_msg
+        );
+
+        assert!(matches!(
+            decode_command(MessageType::Start, &payload),
+            Err(DecodingError::NotACommand(MessageType::Start))
+        ));
+    }
+
+    #[test]
+    fn encode_into_is_equivalent_to_encode() {
+        let encoder = Encoder::new(Version::maximum_supported_version());
+
+        let msgs: Vec<_> = (0..10_000)
+            .map(|i| messages::SetStateEntryMessage {
+                key: Bytes::from(format!("key-{i}")),
+                value: Bytes::from(format!("value-{i}")),
+                ..Default::default()
+            })
+            .collect();
+
+        let mut expected = BytesMut::new();
+        for msg in &msgs {
+            expected.put(encoder.encode(msg));
+        }
+
+        let mut buf = BytesMut::new();
+        for msg in &msgs {
+            encoder.encode_into(msg, &mut buf);
+        }
+
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn drain_decoder() {
+        let encoder = Encoder::new(Version::maximum_supported_version());
+        let mut decoder = Decoder::new(
+            Version::maximum_supported_version(),
+            DEFAULT_MAX_MESSAGE_SIZE,
+        );
+
+        let mut buf = BytesMut::new();
+        encoder.encode_into(
+            &messages::StartMessage {
+                known_entries: 2,
+                ..Default::default()
+            },
+            &mut buf,
+        );
+        encoder.encode_into(&messages::InputEntryMessage::default(), &mut buf);
+        decoder.push(buf.freeze());
+        // Leave a partial header in the decoder
+        decoder.push(Bytes::from_static(&[0x03, 0xFF]));
+
+        let tys: Vec<_> = decoder.drain().map(|res| res.unwrap().ty()).collect();
+        assert_eq!(tys, vec![MessageType::Start, MessageType::InputEntry]);
+        assert_eq!(decoder.drain().count(), 0);
+
+        // Complete the header with an unknown message type, the iterator stops after the error
+        decoder.push(Bytes::from_static(&[0, 0, 0, 0, 0, 0]));
+        decoder.push(encoder.encode(&messages::InputEntryMessage::default()));
+        let mut drain = decoder.drain();
+        assert!(matches!(
+            drain.next(),
+            Some(Err(DecodingError::UnknownMessageType(_)))
+        ));
+        assert!(drain.next().is_none());
+    }
+
+    #[test]
+    fn decoder_rejects_oversized_message() {
+        let mut decoder = Decoder::new(Version::maximum_supported_version(), 1024);
+
+        // InputEntry header advertising a payload of u32::MAX bytes
+        decoder.push(Bytes::from_static(&[
+            0x04, 0x00, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF,
+        ]));
+        decoder.push(Bytes::from(vec![0; 2048]));
+
+        assert!(matches!(
+            decoder.consume_next(),
+            Err(DecodingError::MessageTooLarge {
+                ty: MessageType::InputEntry,
+                size: 4294967295,
+                limit: 1024
+            })
+        ));
+        assert_eq!(decoder.buffered_len(), 0);
+    }
+
+    #[test]
+    fn decoder_is_poisoned_after_error() {
+        let encoder = Encoder::new(Version::maximum_supported_version());
+        let mut decoder = Decoder::new(
+            Version::maximum_supported_version(),
+            DEFAULT_MAX_MESSAGE_SIZE,
+        );
+
+        // Unknown message type 0x03FF
+        decoder.push(Bytes::from_static(&[0x03, 0xFF, 0, 0, 0, 0, 0, 0]));
+        assert!(matches!(
+            decoder.consume_next(),
+            Err(DecodingError::UnknownMessageType(_))
+        ));
+        assert!(decoder.is_poisoned());
+
+        // Further input is discarded
+        decoder.push(encoder.encode(&messages::InputEntryMessage::default()));
+        assert_eq!(decoder.buffered_len(), 0);
+        assert!(decoder.consume_next().unwrap().is_none());
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn checksummed_and_plain_frames_decode() {
+        let encoder = Encoder::new(Version::maximum_supported_version()).with_checksum(true);
+        let plain_encoder = Encoder::new(Version::maximum_supported_version());
+        let mut decoder = Decoder::new(
+            Version::maximum_supported_version(),
+            DEFAULT_MAX_MESSAGE_SIZE,
+        );
+
+        let msg = messages::InputEntryMessage {
+            value: Bytes::from_static(b"input"),
+            ..messages::InputEntryMessage::default()
+        };
+        let encoded = encoder.encode(&msg);
+        assert_eq!(encoded.len(), encoder.encoded_len(&msg));
+
+        decoder.push(encoded);
+        decoder.push(plain_encoder.encode(&msg));
+
+        let checksummed = decoder.consume_next().unwrap().unwrap();
+        assert!(checksummed.header().has_checksum());
+        assert_eq!(
+            checksummed
+                .decode_to::<messages::InputEntryMessage>()
+                .unwrap(),
+            msg
+        );
+        let plain = decoder.consume_next().unwrap().unwrap();
+        assert!(!plain.header().has_checksum());
+        assert_eq!(
+            plain.decode_to::<messages::InputEntryMessage>().unwrap(),
+            msg
+        );
+        assert!(decoder.consume_next().unwrap().is_none());
+    }
+
+    #[test]
+    fn decoder_detects_corrupted_checksummed_frame() {
+        let encoder = Encoder::new(Version::maximum_supported_version()).with_checksum(true);
+        let mut decoder = Decoder::new(
+            Version::maximum_supported_version(),
+            DEFAULT_MAX_MESSAGE_SIZE,
+        );
+
+        let mut frame = BytesMut::from(
+            &encoder.encode(&messages::InputEntryMessage {
+                value: Bytes::from_static(b"some input value"),
+                ..messages::InputEntryMessage::default()
+            })[..],
+        );
+        // Flip a byte in the middle of the payload, still a valid protobuf message
+        let mid = 8 + (frame.len() - 12) / 2;
+        frame[mid] ^= 0x01;
+        decoder.push(frame.freeze());
+
+        assert!(matches!(
+            decoder.consume_next(),
+            Err(DecodingError::ChecksumMismatch {
+                ty: MessageType::InputEntry,
+                ..
+            })
+        ));
+        assert!(decoder.is_poisoned());
+    }
+
     #[test]
     fn fill_decoder_with_partial_header() {
         partial_decoding_test(4)
@@ -261,7 +649,10 @@ mod tests {
 
     fn partial_decoding_test(split_index: usize) {
         let encoder = Encoder::new(Version::maximum_supported_version());
-        let mut decoder = Decoder::new(Version::maximum_supported_version());
+        let mut decoder = Decoder::new(
+            Version::maximum_supported_version(),
+            DEFAULT_MAX_MESSAGE_SIZE,
+        );
 
         let expected_msg = messages::InputEntryMessage {
             value: Bytes::from_static("input".as_bytes()),
@@ -240,6 +240,17 @@ pub mod get_state_keys_entry_message {
         Failure(super::Failure),
     }
 }
+/// Completable: No
+/// Fallible: No
+/// Type: 0xF000 + 0
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ClearStatePrefixEntryMessage {
+    #[prost(bytes = "bytes", tag = "1")]
+    pub prefix: ::prost::bytes::Bytes,
+    /// Entry name
+    #[prost(string, tag = "12")]
+    pub name: ::prost::alloc::string::String,
+}
 /// Completable: Yes
 /// Fallible: No
 /// Type: 0x0800 + 8
@@ -297,6 +308,10 @@ pub mod peek_promise_entry_message {
 pub struct CompletePromiseEntryMessage {
     #[prost(string, tag = "1")]
     pub key: ::prost::alloc::string::String,
+    /// Used by the runtime to deduplicate completions. If present, it must be non empty.
+    /// Supported from protocol V4.
+    #[prost(string, optional, tag = "4")]
+    pub idempotency_key: ::core::option::Option<::prost::alloc::string::String>,
     /// Entry name
     #[prost(string, tag = "12")]
     pub name: ::prost::alloc::string::String,
@@ -330,6 +345,52 @@ pub mod complete_promise_entry_message {
 }
 /// Completable: Yes
 /// Fallible: No
+/// Type: 0xF000 + 1
+/// Completes the promise only if it wasn't completed yet.
+/// The CompletionMessage for this entry carries a value of a single byte: 0x01 if this entry completed the promise, 0x00 if the promise was already completed.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CompletePromiseIfUnsetEntryMessage {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    /// Entry name
+    #[prost(string, tag = "12")]
+    pub name: ::prost::alloc::string::String,
+    /// The value to use to complete the promise
+    #[prost(
+        oneof = "complete_promise_if_unset_entry_message::Completion",
+        tags = "2, 3"
+    )]
+    pub completion:
+        ::core::option::Option<complete_promise_if_unset_entry_message::Completion>,
+    #[prost(
+        oneof = "complete_promise_if_unset_entry_message::Result",
+        tags = "14, 15"
+    )]
+    pub result: ::core::option::Option<complete_promise_if_unset_entry_message::Result>,
+}
+/// Nested message and enum types in `CompletePromiseIfUnsetEntryMessage`.
+pub mod complete_promise_if_unset_entry_message {
+    /// The value to use to complete the promise
+    #[allow(clippy::enum_variant_names)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Completion {
+        #[prost(bytes, tag = "2")]
+        CompletionValue(::prost::bytes::Bytes),
+        #[prost(message, tag = "3")]
+        CompletionFailure(super::Failure),
+    }
+    #[allow(clippy::enum_variant_names)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Result {
+        /// True if this entry completed the promise, false if the promise was already completed
+        #[prost(bool, tag = "14")]
+        Value(bool),
+        #[prost(message, tag = "15")]
+        Failure(super::Failure),
+    }
+}
+/// Completable: Yes
+/// Fallible: No
 /// Type: 0x0C00 + 0
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SleepEntryMessage {
@@ -373,6 +434,13 @@ pub struct CallEntryMessage {
     /// If present, it must be non empty.
     #[prost(string, optional, tag = "6")]
     pub idempotency_key: ::core::option::Option<::prost::alloc::string::String>,
+    /// Time when this Invoke should be executed.
+    /// The time is set as duration since UNIX Epoch.
+    /// If this value is not set, equal to 0, or past in time,
+    /// the runtime will execute this Invoke as soon as possible.
+    /// Supported from protocol V4.
+    #[prost(uint64, tag = "7")]
+    pub invoke_time: u64,
     /// Entry name
     #[prost(string, tag = "12")]
     pub name: ::prost::alloc::string::String,
@@ -390,7 +458,7 @@ pub mod call_entry_message {
         Failure(super::Failure),
     }
 }
-/// Completable: No
+/// Completable: Only when await_acceptance is true
 /// Fallible: Yes
 /// Type: 0x0C00 + 2
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -415,9 +483,30 @@ pub struct OneWayCallEntryMessage {
     /// If present, it must be non empty.
     #[prost(string, optional, tag = "7")]
     pub idempotency_key: ::core::option::Option<::prost::alloc::string::String>,
+    /// If true, the runtime completes this entry once it durably accepted the invocation.
+    /// Supported from protocol V4.
+    #[prost(bool, tag = "8")]
+    pub await_acceptance: bool,
     /// Entry name
     #[prost(string, tag = "12")]
     pub name: ::prost::alloc::string::String,
+    /// Set only when await_acceptance is true.
+    /// Supported from protocol V4.
+    #[prost(oneof = "one_way_call_entry_message::Result", tags = "13, 15")]
+    pub result: ::core::option::Option<one_way_call_entry_message::Result>,
+}
+/// Nested message and enum types in `OneWayCallEntryMessage`.
+pub mod one_way_call_entry_message {
+    /// Set only when await_acceptance is true.
+    /// Supported from protocol V4.
+    #[allow(clippy::enum_variant_names)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Result {
+        #[prost(message, tag = "13")]
+        Empty(super::Empty),
+        #[prost(message, tag = "15")]
+        Failure(super::Failure),
+    }
 }
 /// Completable: Yes
 /// Fallible: No
@@ -428,7 +517,7 @@ pub struct AwakeableEntryMessage {
     /// Entry name
     #[prost(string, tag = "12")]
     pub name: ::prost::alloc::string::String,
-    #[prost(oneof = "awakeable_entry_message::Result", tags = "14, 15")]
+    #[prost(oneof = "awakeable_entry_message::Result", tags = "13, 14, 15")]
     pub result: ::core::option::Option<awakeable_entry_message::Result>,
 }
 /// Nested message and enum types in `AwakeableEntryMessage`.
@@ -436,6 +525,9 @@ pub mod awakeable_entry_message {
     #[allow(clippy::enum_variant_names)]
     #[derive(Clone, PartialEq, ::prost::Oneof)]
     pub enum Result {
+        /// Supported from protocol V4.
+        #[prost(message, tag = "13")]
+        Empty(super::Empty),
         #[prost(bytes, tag = "14")]
         Value(::prost::bytes::Bytes),
         #[prost(message, tag = "15")]
@@ -450,10 +542,14 @@ pub struct CompleteAwakeableEntryMessage {
     /// Identifier of the awakeable. See the spec for more details.
     #[prost(string, tag = "1")]
     pub id: ::prost::alloc::string::String,
+    /// Used by the runtime to deduplicate completions. If present, it must be non empty.
+    /// Supported from protocol V4.
+    #[prost(string, optional, tag = "2")]
+    pub idempotency_key: ::core::option::Option<::prost::alloc::string::String>,
     /// Entry name
     #[prost(string, tag = "12")]
     pub name: ::prost::alloc::string::String,
-    #[prost(oneof = "complete_awakeable_entry_message::Result", tags = "14, 15")]
+    #[prost(oneof = "complete_awakeable_entry_message::Result", tags = "13, 14, 15")]
     pub result: ::core::option::Option<complete_awakeable_entry_message::Result>,
 }
 /// Nested message and enum types in `CompleteAwakeableEntryMessage`.
@@ -461,6 +557,9 @@ pub mod complete_awakeable_entry_message {
     #[allow(clippy::enum_variant_names)]
     #[derive(Clone, PartialEq, ::prost::Oneof)]
     pub enum Result {
+        /// Supported from protocol V4.
+        #[prost(message, tag = "13")]
+        Empty(super::Empty),
         #[prost(bytes, tag = "14")]
         Value(::prost::bytes::Bytes),
         #[prost(message, tag = "15")]
@@ -675,6 +774,10 @@ pub enum ServiceProtocolVersion {
     /// * New entry to attach to existing invocation: AttachInvocationEntryMessage
     /// * New entry to get output of existing invocation: GetInvocationOutputEntryMessage
     V3 = 3,
+    /// Added
+    /// * New entry to clear all the state keys matching a prefix: ClearStatePrefixEntryMessage
+    /// * New entry to complete a promise only if it's not completed yet: CompletePromiseIfUnsetEntryMessage
+    V4 = 4,
 }
 impl ServiceProtocolVersion {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -687,6 +790,7 @@ impl ServiceProtocolVersion {
             Self::V1 => "V1",
             Self::V2 => "V2",
             Self::V3 => "V3",
+            Self::V4 => "V4",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -696,6 +800,7 @@ impl ServiceProtocolVersion {
             "V1" => Some(Self::V1),
             "V2" => Some(Self::V2),
             "V3" => Some(Self::V3),
+            "V4" => Some(Self::V4),
             _ => None,
         }
     }
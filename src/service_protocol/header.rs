@@ -8,8 +8,12 @@
 // the Business Source License, use of this software will be governed
 // by the Apache License, Version 2.0.
 
+use crate::service_protocol::Version;
+use crate::CommandType;
+
 const CUSTOM_ENTRY_MASK: u16 = 0xFC00;
 const COMPLETED_MASK: u64 = 0x0001_0000_0000;
+const CHECKSUM_MASK: u64 = 0x0002_0000_0000;
 const REQUIRES_ACK_MASK: u64 = 0x8000_0000_0000;
 
 type MessageTypeId = u16;
@@ -124,11 +128,143 @@ gen_message_type_enum!(
     GetCallInvocationId Entry = 0x0C07,
     AttachInvocation Entry = 0x0C08,
     GetInvocationOutput Entry = 0x0C09,
+    // Entries of the experimental protocol version, in a range not assigned upstream
+    ClearStatePrefix Entry = 0xF000,
+    CompletePromiseIfUnset Entry = 0xF001,
     Combinator Entry = 0xFC02,
 );
 
+// Ordered by minimum required version, so the types supported by a version are a prefix of this list
+static MESSAGE_TYPES: [MessageType; 29] = [
+    MessageType::Start,
+    MessageType::Completion,
+    MessageType::Suspension,
+    MessageType::Error,
+    MessageType::End,
+    MessageType::EntryAck,
+    MessageType::InputEntry,
+    MessageType::OutputEntry,
+    MessageType::GetStateEntry,
+    MessageType::SetStateEntry,
+    MessageType::ClearStateEntry,
+    MessageType::GetStateKeysEntry,
+    MessageType::ClearAllStateEntry,
+    MessageType::GetPromiseEntry,
+    MessageType::PeekPromiseEntry,
+    MessageType::CompletePromiseEntry,
+    MessageType::SleepEntry,
+    MessageType::CallEntry,
+    MessageType::OneWayCallEntry,
+    MessageType::AwakeableEntry,
+    MessageType::CompleteAwakeableEntry,
+    MessageType::RunEntry,
+    MessageType::CombinatorEntry,
+    // V3
+    MessageType::CancelInvocationEntry,
+    MessageType::GetCallInvocationIdEntry,
+    MessageType::AttachInvocationEntry,
+    MessageType::GetInvocationOutputEntry,
+    // V4
+    MessageType::ClearStatePrefixEntry,
+    MessageType::CompletePromiseIfUnsetEntry,
+];
+
 impl MessageType {
-    fn has_completed_flag(&self) -> bool {
+    /// Minimum protocol version defining this message type.
+    pub const fn minimum_required_version(&self) -> Version {
+        match self {
+            MessageType::CancelInvocationEntry
+            | MessageType::GetCallInvocationIdEntry
+            | MessageType::AttachInvocationEntry
+            | MessageType::GetInvocationOutputEntry => Version::V3,
+            MessageType::ClearStatePrefixEntry | MessageType::CompletePromiseIfUnsetEntry => {
+                Version::V4
+            }
+            _ => Version::V1,
+        }
+    }
+
+    /// Returns the message types defined by the given protocol version. Custom entries are supported by all versions, and are not listed.
+    pub fn supported_by(version: Version) -> &'static [MessageType] {
+        let len = MESSAGE_TYPES
+            .iter()
+            .take_while(|ty| ty.minimum_required_version() <= version)
+            .count();
+        &MESSAGE_TYPES[..len]
+    }
+}
+
+impl TryFrom<MessageType> for CommandType {
+    type Error = MessageType;
+
+    fn try_from(value: MessageType) -> Result<Self, Self::Error> {
+        match value {
+            MessageType::InputEntry => Ok(CommandType::Input),
+            MessageType::OutputEntry => Ok(CommandType::Output),
+            MessageType::GetStateEntry => Ok(CommandType::GetState),
+            MessageType::GetStateKeysEntry => Ok(CommandType::GetStateKeys),
+            MessageType::SetStateEntry => Ok(CommandType::SetState),
+            MessageType::ClearStateEntry => Ok(CommandType::ClearState),
+            MessageType::ClearAllStateEntry => Ok(CommandType::ClearAllState),
+            MessageType::ClearStatePrefixEntry => Ok(CommandType::ClearStatePrefix),
+            MessageType::GetPromiseEntry => Ok(CommandType::GetPromise),
+            MessageType::PeekPromiseEntry => Ok(CommandType::PeekPromise),
+            MessageType::CompletePromiseEntry => Ok(CommandType::CompletePromise),
+            MessageType::CompletePromiseIfUnsetEntry => Ok(CommandType::CompletePromiseIfUnset),
+            MessageType::SleepEntry => Ok(CommandType::Sleep),
+            MessageType::CallEntry => Ok(CommandType::Call),
+            MessageType::OneWayCallEntry => Ok(CommandType::OneWayCall),
+            MessageType::AwakeableEntry => Ok(CommandType::Awakeable),
+            MessageType::CompleteAwakeableEntry => Ok(CommandType::CompleteAwakeable),
+            MessageType::RunEntry => Ok(CommandType::Run),
+            MessageType::CancelInvocationEntry => Ok(CommandType::CancelInvocation),
+            MessageType::GetCallInvocationIdEntry => Ok(CommandType::GetCallInvocationId),
+            MessageType::AttachInvocationEntry => Ok(CommandType::AttachInvocation),
+            MessageType::GetInvocationOutputEntry => Ok(CommandType::GetInvocationOutput),
+            MessageType::CombinatorEntry => Ok(CommandType::Combinator),
+            mt => Err(mt),
+        }
+    }
+}
+
+impl From<CommandType> for MessageType {
+    fn from(value: CommandType) -> Self {
+        match value {
+            CommandType::Input => MessageType::InputEntry,
+            CommandType::Output => MessageType::OutputEntry,
+            CommandType::GetState => MessageType::GetStateEntry,
+            CommandType::GetStateKeys => MessageType::GetStateKeysEntry,
+            CommandType::SetState => MessageType::SetStateEntry,
+            CommandType::ClearState => MessageType::ClearStateEntry,
+            CommandType::ClearAllState => MessageType::ClearAllStateEntry,
+            CommandType::ClearStatePrefix => MessageType::ClearStatePrefixEntry,
+            CommandType::GetPromise => MessageType::GetPromiseEntry,
+            CommandType::PeekPromise => MessageType::PeekPromiseEntry,
+            CommandType::CompletePromise => MessageType::CompletePromiseEntry,
+            CommandType::CompletePromiseIfUnset => MessageType::CompletePromiseIfUnsetEntry,
+            CommandType::Sleep => MessageType::SleepEntry,
+            CommandType::Call => MessageType::CallEntry,
+            CommandType::OneWayCall => MessageType::OneWayCallEntry,
+            CommandType::Awakeable => MessageType::AwakeableEntry,
+            CommandType::CompleteAwakeable => MessageType::CompleteAwakeableEntry,
+            CommandType::Run => MessageType::RunEntry,
+            CommandType::CancelInvocation => MessageType::CancelInvocationEntry,
+            CommandType::GetCallInvocationId => MessageType::GetCallInvocationIdEntry,
+            CommandType::AttachInvocation => MessageType::AttachInvocationEntry,
+            CommandType::GetInvocationOutput => MessageType::GetInvocationOutputEntry,
+            CommandType::Combinator => MessageType::CombinatorEntry,
+        }
+    }
+}
+
+impl From<CommandType> for u16 {
+    fn from(value: CommandType) -> Self {
+        MessageType::from(value).into()
+    }
+}
+
+impl MessageType {
+    pub(crate) fn has_completed_flag(&self) -> bool {
         matches!(
             self,
             MessageType::GetStateEntry
@@ -139,6 +275,7 @@ impl MessageType {
                 | MessageType::GetPromiseEntry
                 | MessageType::PeekPromiseEntry
                 | MessageType::CompletePromiseEntry
+                | MessageType::CompletePromiseIfUnsetEntry
                 | MessageType::GetCallInvocationIdEntry
                 | MessageType::AttachInvocationEntry
                 | MessageType::GetInvocationOutputEntry
@@ -156,6 +293,8 @@ pub struct MessageHeader {
     completed_flag: Option<bool>,
     /// All Entry messages may have requires ack flag.
     requires_ack_flag: Option<bool>,
+    /// Any message may be followed by a CRC32 of its payload.
+    checksum_flag: bool,
 }
 
 impl MessageHeader {
@@ -173,6 +312,7 @@ impl MessageHeader {
             length,
             completed_flag,
             requires_ack_flag: None,
+            checksum_flag: false,
         }
     }
 
@@ -190,6 +330,7 @@ impl MessageHeader {
             length,
             completed_flag,
             requires_ack_flag,
+            checksum_flag: false,
         }
     }
 
@@ -205,6 +346,7 @@ impl MessageHeader {
             length,
             completed_flag,
             requires_ack_flag,
+            checksum_flag: false,
         }
     }
 
@@ -223,6 +365,19 @@ impl MessageHeader {
         self.requires_ack_flag
     }
 
+    #[inline]
+    pub fn has_checksum(&self) -> bool {
+        self.checksum_flag
+    }
+
+    /// Marks the frame as followed by the CRC32 of its payload.
+    #[inline]
+    pub(crate) fn with_checksum(mut self) -> Self {
+        self.checksum_flag = true;
+        self
+    }
+
+    /// Length of the payload, excluding the header and the checksum trailer.
     #[inline]
     pub fn frame_length(&self) -> u32 {
         self.length
@@ -252,12 +407,9 @@ impl TryFrom<u64> for MessageHeader {
         let requires_ack_flag = read_flag_if!(ty.is_entry(), value, REQUIRES_ACK_MASK);
         let length = value as u32;
 
-        Ok(MessageHeader::_new(
-            ty,
-            completed_flag,
-            requires_ack_flag,
-            length,
-        ))
+        let mut header = MessageHeader::_new(ty, completed_flag, requires_ack_flag, length);
+        header.checksum_flag = (value & CHECKSUM_MASK) != 0;
+        Ok(header)
     }
 }
 
@@ -282,6 +434,7 @@ impl From<MessageHeader> for u64 {
             &mut res,
             REQUIRES_ACK_MASK
         );
+        write_flag!(Some(message_header.checksum_flag), &mut res, CHECKSUM_MASK);
 
         res
     }
@@ -409,4 +562,41 @@ mod tests {
         10341,
         requires_ack: true
     );
+
+    #[test]
+    fn checksum_flag_roundtrip() {
+        let header =
+            MessageHeader::new_completable_entry(GetStateEntry, true, 10341).with_checksum();
+        let serialized: u64 = header.into();
+        assert_eq!(serialized & CHECKSUM_MASK, CHECKSUM_MASK);
+
+        let deserialized: MessageHeader = serialized.try_into().unwrap();
+        assert!(deserialized.has_checksum());
+        assert_eq!(deserialized.completed(), Some(true));
+        assert_eq!(deserialized.frame_length(), 10341);
+
+        let without_checksum: MessageHeader = (serialized & !CHECKSUM_MASK).try_into().unwrap();
+        assert!(!without_checksum.has_checksum());
+        assert_eq!(without_checksum.completed(), Some(true));
+    }
+
+    #[test]
+    fn command_type_message_type_roundtrip() {
+        for ty in MESSAGE_TYPES {
+            match CommandType::try_from(ty) {
+                Ok(command_type) => assert_eq!(MessageType::from(command_type), ty),
+                Err(_) => assert!(!ty.is_entry()),
+            }
+        }
+        assert_eq!(
+            CommandType::try_from(MessageType::Completion),
+            Err(MessageType::Completion)
+        );
+        assert_eq!(u16::from(CommandType::GetStateKeys), 0x0804);
+        assert_eq!(u16::from(CommandType::ClearStatePrefix), 0xF000);
+        assert_eq!(
+            MessageType::try_from(0xF001).unwrap(),
+            MessageType::CompletePromiseIfUnsetEntry
+        );
+    }
 }
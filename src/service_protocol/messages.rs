@@ -3,10 +3,10 @@ use std::borrow::Cow;
 use crate::service_protocol::messages::get_state_keys_entry_message::StateKeys;
 use crate::service_protocol::{MessageHeader, MessageType};
 use crate::vm::errors::{
-    DecodeGetCallInvocationIdUtf8, DecodeStateKeysProst, DecodeStateKeysUtf8,
-    EmptyGetCallInvocationId, EmptyStateKeys,
+    DecodeGetCallInvocationIdUtf8, DecodePromiseCompleted, DecodeStateKeysProst,
+    DecodeStateKeysUtf8, EmptyGetCallInvocationId, EmptyPromiseCompleted, EmptyStateKeys,
 };
-use crate::{Error, NonEmptyValue, Value};
+use crate::{CommandType, Error, NonEmptyValue, Value};
 use paste::paste;
 use prost::Message;
 
@@ -32,13 +32,16 @@ pub trait CompletableEntryMessage: RestateMessage + EntryMessage + EntryMessageH
     fn is_completed(&self) -> bool;
     fn into_completion(self) -> Result<Option<Value>, Error>;
     fn completion_parsing_hint() -> CompletionParsingHint;
+    fn command_type() -> CommandType;
 }
 
 impl<M: CompletableEntryMessage> WriteableRestateMessage for M {
     fn generate_header(&self, _never_ack: bool) -> MessageHeader {
         MessageHeader::new_entry_header(
             Self::ty(),
-            Some(self.is_completed()),
+            // Entries completable only on demand, like one way calls awaiting acceptance,
+            // carry their completion in the body only.
+            Self::ty().has_completed_flag().then(|| self.is_completed()),
             self.encoded_len() as u32,
         )
     }
@@ -58,10 +61,10 @@ macro_rules! impl_message_traits {
         impl_message_traits!($name: entry);
         impl_message_traits!($name: entry_header_eq);
     };
-    ($name:ident: completable_entry) => {
+    ($name:ident: completable_entry($command:ident)) => {
         impl_message_traits!($name: message);
         impl_message_traits!($name: entry);
-        impl_message_traits!($name: completable);
+        impl_message_traits!($name: completable($command));
     };
     ($name:ident: message) => {
          impl RestateMessage for paste! { [<$name Message>] } {
@@ -73,7 +76,7 @@ macro_rules! impl_message_traits {
     ($name:ident: writeable) => {
         impl WriteableRestateMessage for paste! { [<$name Message>] } {}
     };
-    ($name:ident: completable) => {
+    ($name:ident: completable($command:ident)) => {
         impl CompletableEntryMessage for paste! { [<$name Message>] } {
             fn is_completed(&self) -> bool {
                 self.result.is_some()
@@ -86,6 +89,10 @@ macro_rules! impl_message_traits {
             fn completion_parsing_hint() -> CompletionParsingHint {
                 CompletionParsingHint::EmptyOrSuccessOrValue
             }
+
+            fn command_type() -> CommandType {
+                CommandType::$command
+            }
         }
     };
     ($name:ident: entry) => {
@@ -124,7 +131,7 @@ impl EntryMessageHeaderEq for InputEntryMessage {
 
 impl_message_traits!(OutputEntry: non_completable_entry);
 
-impl_message_traits!(GetStateEntry: completable_entry);
+impl_message_traits!(GetStateEntry: completable_entry(GetState));
 impl EntryMessageHeaderEq for GetStateEntryMessage {
     fn header_eq(&self, other: &Self) -> bool {
         self.key.eq(&other.key)
@@ -145,6 +152,10 @@ impl CompletableEntryMessage for GetStateKeysEntryMessage {
     fn completion_parsing_hint() -> CompletionParsingHint {
         CompletionParsingHint::StateKeys
     }
+
+    fn command_type() -> CommandType {
+        CommandType::GetStateKeys
+    }
 }
 impl EntryMessageHeaderEq for GetStateKeysEntryMessage {
     fn header_eq(&self, _: &Self) -> bool {
@@ -152,46 +163,64 @@ impl EntryMessageHeaderEq for GetStateKeysEntryMessage {
     }
 }
 
-impl_message_traits!(SetStateEntry: non_completable_entry);
+impl_message_traits!(SetStateEntry: message);
+impl_message_traits!(SetStateEntry: writeable);
+impl_message_traits!(SetStateEntry: entry);
+impl EntryMessageHeaderEq for SetStateEntryMessage {
+    fn header_eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.value == other.value
+    }
+}
 
-impl_message_traits!(ClearStateEntry: non_completable_entry);
+impl_message_traits!(ClearStateEntry: message);
+impl_message_traits!(ClearStateEntry: writeable);
+impl_message_traits!(ClearStateEntry: entry);
+impl EntryMessageHeaderEq for ClearStateEntryMessage {
+    fn header_eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
 
 impl_message_traits!(ClearAllStateEntry: non_completable_entry);
 
-impl_message_traits!(SleepEntry: completable_entry);
+impl_message_traits!(ClearStatePrefixEntry: non_completable_entry);
+
+impl_message_traits!(SleepEntry: completable_entry(Sleep));
 impl EntryMessageHeaderEq for SleepEntryMessage {
     fn header_eq(&self, other: &Self) -> bool {
         self.name == other.name
     }
 }
 
-impl_message_traits!(CallEntry: completable_entry);
+impl_message_traits!(CallEntry: completable_entry(Call));
 impl EntryMessageHeaderEq for CallEntryMessage {
     fn header_eq(&self, other: &Self) -> bool {
         self.service_name == other.service_name
             && self.handler_name == other.handler_name
             && self.key == other.key
-            && self.headers == other.headers
+            && headers_eq(&self.headers, &other.headers)
+            && self.invoke_time == other.invoke_time
             && self.parameter == other.parameter
             && self.name == other.name
     }
 }
 
 impl_message_traits!(OneWayCallEntry: message);
-impl_message_traits!(OneWayCallEntry: writeable);
 impl_message_traits!(OneWayCallEntry: entry);
+impl_message_traits!(OneWayCallEntry: completable(OneWayCall));
 impl EntryMessageHeaderEq for OneWayCallEntryMessage {
     fn header_eq(&self, other: &Self) -> bool {
         self.service_name == other.service_name
             && self.handler_name == other.handler_name
             && self.key == other.key
-            && self.headers == other.headers
+            && headers_eq(&self.headers, &other.headers)
             && self.parameter == other.parameter
+            && self.await_acceptance == other.await_acceptance
             && self.name == other.name
     }
 }
 
-impl_message_traits!(AwakeableEntry: completable_entry);
+impl_message_traits!(AwakeableEntry: completable_entry(Awakeable));
 impl EntryMessageHeaderEq for AwakeableEntryMessage {
     fn header_eq(&self, other: &Self) -> bool {
         self.name == other.name
@@ -200,22 +229,50 @@ impl EntryMessageHeaderEq for AwakeableEntryMessage {
 
 impl_message_traits!(CompleteAwakeableEntry: non_completable_entry);
 
-impl_message_traits!(GetPromiseEntry: completable_entry);
+impl_message_traits!(GetPromiseEntry: completable_entry(GetPromise));
 impl EntryMessageHeaderEq for GetPromiseEntryMessage {
     fn header_eq(&self, other: &Self) -> bool {
         self.key == other.key && self.name == other.name
     }
 }
 
-impl_message_traits!(PeekPromiseEntry: completable_entry);
+impl_message_traits!(PeekPromiseEntry: completable_entry(PeekPromise));
 impl EntryMessageHeaderEq for PeekPromiseEntryMessage {
     fn header_eq(&self, other: &Self) -> bool {
         self.key == other.key && self.name == other.name
     }
 }
 
-impl_message_traits!(CompletePromiseEntry: completable_entry);
+impl_message_traits!(CompletePromiseEntry: completable_entry(CompletePromise));
 impl EntryMessageHeaderEq for CompletePromiseEntryMessage {
+    fn header_eq(&self, other: &Self) -> bool {
+        self.key == other.key
+            && self.idempotency_key == other.idempotency_key
+            && self.completion == other.completion
+            && self.name == other.name
+    }
+}
+
+impl_message_traits!(CompletePromiseIfUnsetEntry: message);
+impl_message_traits!(CompletePromiseIfUnsetEntry: entry);
+impl CompletableEntryMessage for CompletePromiseIfUnsetEntryMessage {
+    fn is_completed(&self) -> bool {
+        self.result.is_some()
+    }
+
+    fn into_completion(self) -> Result<Option<Value>, Error> {
+        self.result.map(TryInto::try_into).transpose()
+    }
+
+    fn completion_parsing_hint() -> CompletionParsingHint {
+        CompletionParsingHint::PromiseCompleted
+    }
+
+    fn command_type() -> CommandType {
+        CommandType::CompletePromiseIfUnset
+    }
+}
+impl EntryMessageHeaderEq for CompletePromiseIfUnsetEntryMessage {
     fn header_eq(&self, other: &Self) -> bool {
         self.key == other.key && self.completion == other.completion && self.name == other.name
     }
@@ -255,6 +312,10 @@ impl CompletableEntryMessage for GetCallInvocationIdEntryMessage {
     fn completion_parsing_hint() -> CompletionParsingHint {
         CompletionParsingHint::GetCompletionId
     }
+
+    fn command_type() -> CommandType {
+        CommandType::GetCallInvocationId
+    }
 }
 impl EntryMessageHeaderEq for GetCallInvocationIdEntryMessage {
     fn header_eq(&self, other: &Self) -> bool {
@@ -262,14 +323,14 @@ impl EntryMessageHeaderEq for GetCallInvocationIdEntryMessage {
     }
 }
 
-impl_message_traits!(AttachInvocationEntry: completable_entry);
+impl_message_traits!(AttachInvocationEntry: completable_entry(AttachInvocation));
 impl EntryMessageHeaderEq for AttachInvocationEntryMessage {
     fn header_eq(&self, other: &Self) -> bool {
         self.target == other.target && self.name == other.name
     }
 }
 
-impl_message_traits!(GetInvocationOutputEntry: completable_entry);
+impl_message_traits!(GetInvocationOutputEntry: completable_entry(GetInvocationOutput));
 impl EntryMessageHeaderEq for GetInvocationOutputEntryMessage {
     fn header_eq(&self, other: &Self) -> bool {
         self.target == other.target && self.name == other.name
@@ -338,6 +399,17 @@ impl TryFrom<sleep_entry_message::Result> for Value {
     }
 }
 
+impl TryFrom<one_way_call_entry_message::Result> for Value {
+    type Error = Error;
+
+    fn try_from(value: one_way_call_entry_message::Result) -> Result<Self, Self::Error> {
+        Ok(match value {
+            one_way_call_entry_message::Result::Empty(_) => Value::Void,
+            one_way_call_entry_message::Result::Failure(f) => Value::Failure(f.into()),
+        })
+    }
+}
+
 impl TryFrom<call_entry_message::Result> for Value {
     type Error = Error;
 
@@ -354,6 +426,7 @@ impl TryFrom<awakeable_entry_message::Result> for Value {
 
     fn try_from(value: awakeable_entry_message::Result) -> Result<Self, Self::Error> {
         Ok(match value {
+            awakeable_entry_message::Result::Empty(_) => Value::Void,
             awakeable_entry_message::Result::Value(b) => Value::Success(b),
             awakeable_entry_message::Result::Failure(f) => Value::Failure(f.into()),
         })
@@ -394,6 +467,19 @@ impl TryFrom<complete_promise_entry_message::Result> for Value {
     }
 }
 
+impl TryFrom<complete_promise_if_unset_entry_message::Result> for Value {
+    type Error = Error;
+
+    fn try_from(
+        value: complete_promise_if_unset_entry_message::Result,
+    ) -> Result<Self, Self::Error> {
+        Ok(match value {
+            complete_promise_if_unset_entry_message::Result::Value(b) => Value::PromiseCompleted(b),
+            complete_promise_if_unset_entry_message::Result::Failure(f) => Value::Failure(f.into()),
+        })
+    }
+}
+
 impl From<run_entry_message::Result> for NonEmptyValue {
     fn from(value: run_entry_message::Result) -> Self {
         match value {
@@ -475,12 +561,41 @@ impl From<crate::Header> for Header {
     }
 }
 
+/// Converts the headers to journal, deduplicating them by key (the last value wins) and sorting them by key.
+pub(crate) fn headers_to_journal(headers: Vec<crate::Header>) -> Vec<Header> {
+    let mut headers: Vec<Header> = headers.into_iter().rev().map(Header::from).collect();
+    // Stable sort, so the last value of each key comes first and is kept by dedup
+    headers.sort_by(|a, b| a.key.cmp(&b.key));
+    headers.dedup_by(|a, b| a.key == b.key);
+    headers
+}
+
+// Headers comparison is order-insensitive, as the order is not semantically relevant.
+// Both sides are normalised the same way as headers_to_journal, as the replayed entry
+// might have been written by an SDK which didn't deduplicate the headers.
+fn headers_eq(this: &[Header], other: &[Header]) -> bool {
+    normalize_headers(this) == normalize_headers(other)
+}
+
+fn normalize_headers(headers: &[Header]) -> Vec<(&str, &str)> {
+    let mut headers: Vec<_> = headers
+        .iter()
+        .rev()
+        .map(|h| (h.key.as_str(), h.value.as_str()))
+        .collect();
+    // Stable sort, so the last value of each key comes first and is kept by dedup
+    headers.sort_by(|a, b| a.0.cmp(b.0));
+    headers.dedup_by(|a, b| a.0 == b.0);
+    headers
+}
+
 // --- Completion parsing
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum CompletionParsingHint {
     StateKeys,
     GetCompletionId,
+    PromiseCompleted,
     /// The normal case
     EmptyOrSuccessOrValue,
 }
@@ -510,6 +625,15 @@ impl CompletionParsingHint {
                 )),
                 completion_message::Result::Failure(f) => Ok(Value::Failure(f.into())),
             },
+            CompletionParsingHint::PromiseCompleted => match result {
+                completion_message::Result::Empty(_) => Err(EmptyPromiseCompleted.into()),
+                completion_message::Result::Value(b) => match b.as_ref() {
+                    [0] => Ok(Value::PromiseCompleted(false)),
+                    [1] => Ok(Value::PromiseCompleted(true)),
+                    _ => Err(DecodePromiseCompleted(b).into()),
+                },
+                completion_message::Result::Failure(f) => Ok(Value::Failure(f.into())),
+            },
             CompletionParsingHint::EmptyOrSuccessOrValue => Ok(match result {
                 completion_message::Result::Empty(_) => Value::Void,
                 completion_message::Result::Value(b) => Value::Success(b),
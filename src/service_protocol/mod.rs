@@ -13,6 +13,9 @@ mod header;
 pub mod messages;
 mod version;
 
-pub use encoding::{Decoder, DecodingError, Encoder, RawMessage};
+pub use encoding::{
+    decode_command, DecodedCommand, Decoder, DecodingError, Encoder, RawMessage,
+    DEFAULT_MAX_MESSAGE_SIZE,
+};
 pub use header::{MessageHeader, MessageType};
-pub use version::{UnsupportedVersionError, Version};
+pub use version::{ProtocolFeature, UnsupportedVersionError, Version};
@@ -1,3 +1,5 @@
+use crate::service_protocol::messages::ServiceProtocolVersion;
+use crate::CommandType;
 use std::fmt;
 use std::str::FromStr;
 
@@ -6,11 +8,15 @@ pub enum Version {
     V1 = 1,
     V2 = 2,
     V3 = 3,
+    V4 = 4,
 }
 
+const CONTENT_TYPE_PREFIX: &str = "application/vnd.restate.invocation.v";
 const CONTENT_TYPE_V1: &str = "application/vnd.restate.invocation.v1";
 const CONTENT_TYPE_V2: &str = "application/vnd.restate.invocation.v2";
 const CONTENT_TYPE_V3: &str = "application/vnd.restate.invocation.v3";
+// Not the upstream v4: the experimental version has its own media type, so it can't be negotiated by mistake
+const CONTENT_TYPE_V4: &str = "application/vnd.restate.invocation.v4-experimental";
 
 impl Version {
     pub const fn content_type(&self) -> &'static str {
@@ -18,6 +24,7 @@ impl Version {
             Version::V1 => CONTENT_TYPE_V1,
             Version::V2 => CONTENT_TYPE_V2,
             Version::V3 => CONTENT_TYPE_V3,
+            Version::V4 => CONTENT_TYPE_V4,
         }
     }
 
@@ -28,6 +35,57 @@ impl Version {
     pub const fn maximum_supported_version() -> Self {
         Version::V3
     }
+
+    /// Highest version accepted when [`crate::VMOptions::enable_experimental_protocol`] is set.
+    /// Its wire format isn't released upstream yet, and it might still change.
+    pub const fn maximum_experimental_version() -> Self {
+        Version::V4
+    }
+
+    /// Returns all the supported versions, from [`Version::minimum_supported_version`] to [`Version::maximum_supported_version`].
+    pub fn all_supported() -> impl Iterator<Item = Version> {
+        [Version::V1, Version::V2, Version::V3, Version::V4]
+            .into_iter()
+            .filter(|v| {
+                *v >= Version::minimum_supported_version()
+                    && *v <= Version::maximum_supported_version()
+            })
+    }
+
+    /// Returns the content types of all the supported versions, e.g. to advertise them in the discovery manifest.
+    pub fn content_types() -> Vec<&'static str> {
+        Version::all_supported().map(|v| v.content_type()).collect()
+    }
+
+    /// Picks the highest supported version among the content types listed in the comma separated `accept` header.
+    /// Media types with `q=0` are not acceptable and are skipped, other parameters and quality values are ignored.
+    pub fn negotiate(accept: &str) -> Option<Version> {
+        accept
+            .split(',')
+            .filter_map(|media_type| {
+                let mut parts = media_type.split(';');
+                let version = parts.next().unwrap_or_default().trim();
+                let not_acceptable = parts.any(|param| {
+                    param.split_once('=').is_some_and(|(name, value)| {
+                        name.trim().eq_ignore_ascii_case("q")
+                            && value.trim().parse::<f32>() == Ok(0.0)
+                    })
+                });
+                if not_acceptable {
+                    return None;
+                }
+                version.parse::<Version>().ok()
+            })
+            .filter(|v| Version::all_supported().any(|supported| supported == *v))
+            .max()
+    }
+}
+
+impl Version {
+    /// Returns true if this version supports the given feature.
+    pub fn supports(&self, feature: ProtocolFeature) -> bool {
+        *self >= feature.minimum_required_version()
+    }
 }
 
 impl fmt::Display for Version {
@@ -36,9 +94,78 @@ impl fmt::Display for Version {
     }
 }
 
+/// Features that depend on the negotiated protocol [`Version`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtocolFeature {
+    IdempotencyKeyOnCall,
+    IdempotencyKeyOnOneWayCall,
+    GetCallInvocationId,
+    CancelInvocation,
+    AttachInvocation,
+    GetInvocationOutput,
+    ClearStatePrefix,
+    CompletePromiseIfUnset,
+    DelayedCall,
+    CompleteAwakeableWithVoid,
+    IdempotencyKeyOnCompletion,
+    SendAcceptance,
+}
+
+impl ProtocolFeature {
+    pub const fn minimum_required_version(&self) -> Version {
+        match self {
+            ProtocolFeature::IdempotencyKeyOnCall
+            | ProtocolFeature::IdempotencyKeyOnOneWayCall
+            | ProtocolFeature::GetCallInvocationId
+            | ProtocolFeature::CancelInvocation
+            | ProtocolFeature::AttachInvocation
+            | ProtocolFeature::GetInvocationOutput => Version::V3,
+            ProtocolFeature::ClearStatePrefix
+            | ProtocolFeature::CompletePromiseIfUnset
+            | ProtocolFeature::DelayedCall
+            | ProtocolFeature::CompleteAwakeableWithVoid
+            | ProtocolFeature::IdempotencyKeyOnCompletion
+            | ProtocolFeature::SendAcceptance => Version::V4,
+        }
+    }
+
+    const fn description(&self) -> &'static str {
+        match self {
+            ProtocolFeature::IdempotencyKeyOnCall => "attach idempotency key to call",
+            ProtocolFeature::IdempotencyKeyOnOneWayCall => "attach idempotency key to one way call",
+            ProtocolFeature::GetCallInvocationId => CommandType::GetCallInvocationId.display_name(),
+            ProtocolFeature::CancelInvocation => CommandType::CancelInvocation.display_name(),
+            ProtocolFeature::AttachInvocation => CommandType::AttachInvocation.display_name(),
+            ProtocolFeature::GetInvocationOutput => CommandType::GetInvocationOutput.display_name(),
+            ProtocolFeature::ClearStatePrefix => CommandType::ClearStatePrefix.display_name(),
+            ProtocolFeature::CompletePromiseIfUnset => {
+                CommandType::CompletePromiseIfUnset.display_name()
+            }
+            ProtocolFeature::DelayedCall => "delayed call",
+            ProtocolFeature::CompleteAwakeableWithVoid => "complete awakeable with void",
+            ProtocolFeature::IdempotencyKeyOnCompletion => {
+                "attach idempotency key to awakeable or promise completion"
+            }
+            ProtocolFeature::SendAcceptance => "await one way call acceptance",
+        }
+    }
+}
+
+impl fmt::Display for ProtocolFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
-#[error("unsupported version '{0}'")]
-pub struct UnsupportedVersionError(String);
+pub enum UnsupportedVersionError {
+    /// The value is not a syntactically valid media type.
+    #[error("malformed content type '{0}', expected '{CONTENT_TYPE_PREFIX}<version>'")]
+    Malformed(String),
+    /// A valid media type, but not one of the supported protocol versions.
+    #[error("unsupported version '{0}'")]
+    Unsupported(String),
+}
 
 impl FromStr for Version {
     type Err = UnsupportedVersionError;
@@ -48,7 +175,42 @@ impl FromStr for Version {
             CONTENT_TYPE_V1 => Ok(Version::V1),
             CONTENT_TYPE_V2 => Ok(Version::V2),
             CONTENT_TYPE_V3 => Ok(Version::V3),
-            s => Err(UnsupportedVersionError(s.to_owned())),
+            CONTENT_TYPE_V4 => Ok(Version::V4),
+            s if is_well_formed_media_type(s) => {
+                Err(UnsupportedVersionError::Unsupported(s.to_owned()))
+            }
+            s => Err(UnsupportedVersionError::Malformed(s.to_owned())),
+        }
+    }
+}
+
+// Checks the `type/subtype` syntax of RFC 9110, ignoring the parameters
+fn is_well_formed_media_type(s: &str) -> bool {
+    fn is_token(s: &str) -> bool {
+        !s.is_empty()
+            && s.bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+    }
+
+    let media_type = s.split(';').next().unwrap_or_default().trim();
+    media_type
+        .split_once('/')
+        .is_some_and(|(ty, subtype)| is_token(ty) && is_token(subtype))
+}
+
+/// Converts the numeric value of [`ServiceProtocolVersion`], rejecting `Unspecified` and unknown values.
+impl TryFrom<i32> for Version {
+    type Error = UnsupportedVersionError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match ServiceProtocolVersion::try_from(value) {
+            Ok(ServiceProtocolVersion::V1) => Ok(Version::V1),
+            Ok(ServiceProtocolVersion::V2) => Ok(Version::V2),
+            Ok(ServiceProtocolVersion::V3) => Ok(Version::V3),
+            Ok(ServiceProtocolVersion::V4) => Ok(Version::V4),
+            Ok(ServiceProtocolVersion::Unspecified) | Err(_) => {
+                Err(UnsupportedVersionError::Unsupported(value.to_string()))
+            }
         }
     }
 }
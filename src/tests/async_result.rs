@@ -106,6 +106,494 @@ fn dont_await_call_dont_notify_input_closed() {
     assert_eq!(output.next(), None);
 }
 
+#[test]
+fn peek_next_async_result_handle() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let peeked = vm.peek_next_async_result_handle();
+            // Peeking doesn't advance the journal
+            assert_eq!(vm.peek_next_async_result_handle(), peeked);
+
+            let handle = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Francesco"))
+                .unwrap();
+            assert_eq!(handle, peeked);
+            assert_eq!(u32::from(handle), 1);
+
+            vm.sys_end().unwrap()
+        });
+
+    assert_that!(
+        output.next_decoded::<CallEntryMessage>().unwrap(),
+        pat!(CallEntryMessage {
+            service_name: eq("Greeter"),
+            handler_name: eq("greeter")
+        })
+    );
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn take_async_result_of_unknown_handle() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            // Handle 10 was never returned by any syscall
+            let_assert!(
+                Err(SuspendedOrVMError::VM(err)) =
+                    vm.take_async_result(AsyncResultHandle::from(10))
+            );
+            assert_eq!(
+                err.code_enum(),
+                Some(error::ErrorCode::InvalidAsyncResultHandle)
+            );
+            assert!(!err.is_retryable());
+            assert!(err.to_string().contains("handle 10"));
+        });
+
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(vm::errors::InvalidAsyncResultHandleError { handle: 10 }.into())
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn take_async_result_already_taken() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .input(CompletionMessage {
+            entry_index: 1,
+            result: Some(completion_message::Result::Value(Bytes::from_static(
+                b"Hello Till",
+            ))),
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let h = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Till"))
+                .unwrap();
+            vm.notify_await_point(h);
+            let_assert!(Ok(Some(Value::Success(_))) = vm.take_async_result(h));
+
+            // Taking it again returns no value, without failing the invocation
+            assert_eq!(vm.take_async_result(h).unwrap(), None);
+
+            vm.sys_end().unwrap();
+        });
+
+    assert_that!(
+        output.next_decoded::<CallEntryMessage>().unwrap(),
+        pat!(CallEntryMessage {
+            service_name: eq("Greeter"),
+            handler_name: eq("greeter")
+        })
+    );
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn await_non_completable_entry() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            vm.sys_state_set("my-key".to_owned(), Bytes::from_static(b"123"))
+                .unwrap();
+            // The set state entry has index 1, but it has no result to await
+            vm.notify_await_point(AsyncResultHandle::from(1));
+            let_assert!(
+                Err(SuspendedOrVMError::VM(err)) = vm.take_async_result(AsyncResultHandle::from(1))
+            );
+            assert_eq!(
+                err.code_enum(),
+                Some(error::ErrorCode::InvalidAsyncResultHandle)
+            );
+        });
+
+    assert!(output.next_decoded::<SetStateEntryMessage>().is_some());
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(vm::errors::InvalidAsyncResultHandleError { handle: 1 }.into())
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn resolve_handle() {
+    VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .input(CompletionMessage {
+            entry_index: 1,
+            result: Some(completion_message::Result::Value(Bytes::from_static(
+                b"Hello Till",
+            ))),
+        })
+        .run(|vm| {
+            assert_eq!(vm.resolve_handle(0), None);
+            vm.sys_input().unwrap();
+
+            let h = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Till"))
+                .unwrap();
+            vm.sys_state_set("STATE".to_owned(), Bytes::new()).unwrap();
+
+            assert_eq!(vm.resolve_handle(u32::from(h)), Some(h));
+            // The input entry and the set state entry have no async result
+            assert_eq!(vm.resolve_handle(0), None);
+            assert_eq!(vm.resolve_handle(2), None);
+            // Never created
+            assert_eq!(vm.resolve_handle(10), None);
+
+            vm.notify_await_point(h);
+            vm.take_async_result(h).unwrap().unwrap();
+            // Already taken
+            assert_eq!(vm.resolve_handle(u32::from(h)), None);
+
+            vm.sys_end().unwrap();
+        });
+}
+
+#[test]
+fn partition_ready_then_suspend_on_pending() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .input(CompletionMessage {
+            entry_index: 2,
+            result: Some(completion_message::Result::Value(Bytes::from_static(
+                b"Igal",
+            ))),
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let h1 = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Till"))
+                .unwrap();
+            let h2 = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Igal"))
+                .unwrap();
+            let h3 = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Francesco"))
+                .unwrap();
+
+            assert!(vm.any_completed(&[h1, h2, h3]));
+            assert!(!vm.any_completed(&[h1, h3]));
+            assert!(vm.all_completed(&[h2]));
+            assert!(!vm.all_completed(&[h1, h2]));
+
+            let (ready, pending) = vm.partition_ready(&[h1, h2, h3]);
+            assert_eq!(ready, vec![h2]);
+            assert_eq!(pending, vec![h1, h3]);
+
+            // Partitioning doesn't consume the results
+            assert_eq!(vm.partition_ready(&[h1, h2, h3]), (ready, pending));
+            assert_eq!(
+                vm.take_async_result(h2).unwrap(),
+                Some(Value::Success(Bytes::from_static(b"Igal")))
+            );
+
+            vm.notify_await_point(h1);
+            assert_that!(
+                vm.take_async_result(h1),
+                err(pat!(SuspendedOrVMError::Suspended(_)))
+            );
+        });
+
+    assert_eq!(
+        output.next_decoded::<CallEntryMessage>().unwrap().parameter,
+        Bytes::from_static(b"Till")
+    );
+    assert_eq!(
+        output.next_decoded::<CallEntryMessage>().unwrap().parameter,
+        Bytes::from_static(b"Igal")
+    );
+    assert_eq!(
+        output.next_decoded::<CallEntryMessage>().unwrap().parameter,
+        Bytes::from_static(b"Francesco")
+    );
+    assert_eq!(
+        output.next_decoded::<SuspensionMessage>().unwrap(),
+        SuspensionMessage {
+            entry_indexes: vec![1],
+        }
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn take_ready_async_results() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .input(CompletionMessage {
+            entry_index: 1,
+            result: Some(completion_message::Result::Value(Bytes::from_static(
+                b"Till",
+            ))),
+        })
+        .input(CompletionMessage {
+            entry_index: 3,
+            result: Some(completion_message::Result::Value(Bytes::from_static(
+                b"Francesco",
+            ))),
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let h1 = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Till"))
+                .unwrap();
+            let h2 = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Igal"))
+                .unwrap();
+            let h3 = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Francesco"))
+                .unwrap();
+
+            assert_eq!(
+                vm.take_ready_async_results(&[h1, h2, h3]).unwrap(),
+                vec![
+                    (h1, Value::Success(Bytes::from_static(b"Till"))),
+                    (h3, Value::Success(Bytes::from_static(b"Francesco")))
+                ]
+            );
+
+            // Taken results are consumed, the others are still pending
+            assert_eq!(vm.take_ready_async_results(&[h1, h2, h3]).unwrap(), vec![]);
+            assert_eq!(vm.journal_stats().pending_async_results, 1);
+
+            vm.sys_end().unwrap()
+        });
+
+    assert_eq!(
+        output.next_decoded::<CallEntryMessage>().unwrap().parameter,
+        Bytes::from_static(b"Till")
+    );
+    assert_eq!(
+        output.next_decoded::<CallEntryMessage>().unwrap().parameter,
+        Bytes::from_static(b"Igal")
+    );
+    assert_eq!(
+        output.next_decoded::<CallEntryMessage>().unwrap().parameter,
+        Bytes::from_static(b"Francesco")
+    );
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn take_ready_async_results_skips_duplicated_and_taken_handles() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .input(CompletionMessage {
+            entry_index: 1,
+            result: Some(completion_message::Result::Value(Bytes::from_static(
+                b"Till",
+            ))),
+        })
+        .input(CompletionMessage {
+            entry_index: 2,
+            result: Some(completion_message::Result::Value(Bytes::from_static(
+                b"Francesco",
+            ))),
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let h1 = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Till"))
+                .unwrap();
+            let h2 = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Francesco"))
+                .unwrap();
+            let_assert!(Ok(Some(_)) = vm.take_async_result(h1));
+
+            assert_eq!(
+                vm.take_ready_async_results(&[h1, h2, h2]).unwrap(),
+                vec![(h2, Value::Success(Bytes::from_static(b"Francesco")))]
+            );
+            assert_eq!(vm.journal_stats().pending_async_results, 0);
+
+            vm.sys_end().unwrap()
+        });
+
+    assert!(output.next_decoded::<CallEntryMessage>().is_some());
+    assert!(output.next_decoded::<CallEntryMessage>().is_some());
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn journal_stats() {
+    let mut output = VMTestCase::new()
+        .input(start_message(3))
+        .input(input_entry_message(b"my-data"))
+        .input(GetStateEntryMessage {
+            key: Bytes::from_static(b"STATE"),
+            result: Some(get_state_entry_message::Result::Value(Bytes::from_static(
+                b"Francesco",
+            ))),
+            ..Default::default()
+        })
+        .input(CallEntryMessage {
+            service_name: "Greeter".to_owned(),
+            handler_name: "greeter".to_owned(),
+            parameter: Bytes::from_static(b"Francesco"),
+            ..Default::default()
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+            assert_eq!(
+                vm.journal_stats(),
+                JournalStats {
+                    entry_index: 0,
+                    pending_async_results: 0
+                }
+            );
+
+            let get_state_handle = vm.sys_state_get("STATE".to_owned()).unwrap();
+            vm.sys_call(greeter_target(), Bytes::from_static(b"Francesco"))
+                .unwrap();
+            assert_eq!(
+                vm.journal_stats(),
+                JournalStats {
+                    entry_index: 2,
+                    pending_async_results: 2
+                }
+            );
+
+            vm.take_async_result(get_state_handle).unwrap();
+            assert_eq!(
+                vm.journal_stats(),
+                JournalStats {
+                    entry_index: 2,
+                    pending_async_results: 1
+                }
+            );
+
+            vm.sys_end().unwrap()
+        });
+
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn replayed_resolutions() {
+    let mut output = VMTestCase::new()
+        .input(start_message(4))
+        .input(input_entry_message(b"my-data"))
+        .input(GetStateEntryMessage {
+            key: Bytes::from_static(b"STATE"),
+            result: Some(get_state_entry_message::Result::Value(Bytes::from_static(
+                b"Francesco",
+            ))),
+            ..Default::default()
+        })
+        .input(SleepEntryMessage {
+            wake_up_time: 1721123699086,
+            result: Some(sleep_entry_message::Result::Empty(Empty::default())),
+            ..Default::default()
+        })
+        .input(CallEntryMessage {
+            service_name: "Greeter".to_owned(),
+            handler_name: "greeter".to_owned(),
+            parameter: Bytes::from_static(b"Francesco"),
+            ..Default::default()
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let get_state_handle = vm.sys_state_get("STATE".to_owned()).unwrap();
+            let sleep_handle = vm
+                .sys_sleep(Duration::from_millis(1721123699086), None)
+                .unwrap();
+            vm.sys_call(greeter_target(), Bytes::from_static(b"Francesco"))
+                .unwrap();
+
+            // The call entry was replayed without a result, so it's not listed
+            assert_eq!(
+                vm.replayed_resolutions(),
+                vec![
+                    (get_state_handle, CommandType::GetState),
+                    (sleep_handle, CommandType::Sleep)
+                ]
+            );
+
+            vm.sys_end().unwrap()
+        });
+
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn eager_state_while_processing_is_not_a_replayed_resolution() {
+    let mut output = VMTestCase::new()
+        .input(StartMessage {
+            id: Bytes::from_static(b"123"),
+            debug_id: "123".to_string(),
+            known_entries: 1,
+            state_map: vec![start_message::StateEntry {
+                key: Bytes::from_static(b"STATE"),
+                value: Bytes::from_static(b"Francesco"),
+            }],
+            ..Default::default()
+        })
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let h = vm.sys_state_get("STATE".to_owned()).unwrap();
+            let_assert!(Ok(Some(Value::Success(_))) = vm.take_async_result(h));
+            assert_eq!(vm.replayed_resolutions(), vec![]);
+
+            vm.sys_end().unwrap()
+        });
+
+    assert!(output.next_decoded::<GetStateEntryMessage>().is_some());
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
 mod notify_await_point {
     use super::*;
 
@@ -515,3 +1003,427 @@ mod reverse_await_order {
         assert_eq!(output.next(), None);
     }
 }
+
+#[test]
+fn replay_commands() {
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    assert_eq!(vm.replay_commands(), None);
+
+    vm.notify_input(encoder.encode(&start_message(3)));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+    // Still waiting for the replay entries
+    assert_eq!(vm.replay_commands(), Some(vec![(CommandType::Input, 0)]));
+
+    vm.notify_input(encoder.encode(&GetStateEntryMessage {
+        key: Bytes::from_static(b"STATE"),
+        result: Some(get_state_entry_message::Result::Value(Bytes::from_static(
+            b"Francesco",
+        ))),
+        ..Default::default()
+    }));
+    vm.notify_input(encoder.encode(&CallEntryMessage {
+        service_name: "Greeter".to_owned(),
+        handler_name: "greeter".to_owned(),
+        parameter: Bytes::from_static(b"Francesco"),
+        ..Default::default()
+    }));
+    vm.notify_input_closed();
+    assert_eq!(
+        vm.replay_commands(),
+        Some(vec![
+            (CommandType::Input, 0),
+            (CommandType::GetState, 1),
+            (CommandType::Call, 2)
+        ])
+    );
+
+    vm.sys_input().unwrap();
+    vm.sys_state_get("STATE".to_owned()).unwrap();
+    assert_eq!(vm.replay_commands(), Some(vec![(CommandType::Call, 2)]));
+
+    vm.sys_call(greeter_target(), Bytes::from_static(b"Francesco"))
+        .unwrap();
+    // Replay is over
+    assert_eq!(vm.replay_commands(), None);
+}
+
+#[test]
+fn complete_awakeable_with_void() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+            vm.sys_complete_awakeable_void("prom_1abc".to_owned())
+                .unwrap();
+            vm.sys_end().unwrap();
+        });
+
+    assert_eq!(
+        output
+            .next_decoded::<CompleteAwakeableEntryMessage>()
+            .unwrap(),
+        CompleteAwakeableEntryMessage {
+            id: "prom_1abc".to_owned(),
+            result: Some(complete_awakeable_entry_message::Result::Empty(
+                Empty::default()
+            )),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn complete_awakeable_with_void_unsupported_on_v3() {
+    let mut output = VMTestCase::with_version(Version::V3)
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+            assert_that!(
+                vm.sys_complete_awakeable_void("prom_1abc".to_owned()),
+                err(eq_vm_error(
+                    vm::errors::UnsupportedFeatureForNegotiatedVersion::new(
+                        ProtocolFeature::CompleteAwakeableWithVoid,
+                        Version::V3
+                    )
+                    .into()
+                ))
+            );
+        });
+
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(
+            vm::errors::UnsupportedFeatureForNegotiatedVersion::new(
+                ProtocolFeature::CompleteAwakeableWithVoid,
+                Version::V3
+            )
+            .into()
+        )
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn complete_awakeable_with_idempotency_key() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+            vm.sys_complete_awakeable_with_idempotency_key(
+                "prom_1abc".to_owned(),
+                NonEmptyValue::Success(Bytes::from_static(b"123")),
+                "my-idempotency-key".to_owned(),
+            )
+            .unwrap();
+            vm.sys_end().unwrap();
+        });
+
+    assert_eq!(
+        output
+            .next_decoded::<CompleteAwakeableEntryMessage>()
+            .unwrap(),
+        CompleteAwakeableEntryMessage {
+            id: "prom_1abc".to_owned(),
+            idempotency_key: Some("my-idempotency-key".to_owned()),
+            result: Some(complete_awakeable_entry_message::Result::Value(
+                Bytes::from_static(b"123")
+            )),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn complete_awakeable_with_empty_idempotency_key() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+            assert_that!(
+                vm.sys_complete_awakeable_with_idempotency_key(
+                    "prom_1abc".to_owned(),
+                    NonEmptyValue::Success(Bytes::from_static(b"123")),
+                    String::new(),
+                ),
+                err(eq_vm_error(vm::errors::EMPTY_COMPLETION_IDEMPOTENCY_KEY))
+            );
+        });
+
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(vm::errors::EMPTY_COMPLETION_IDEMPOTENCY_KEY)
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn complete_awakeable_with_idempotency_key_unsupported_on_v3() {
+    let mut output = VMTestCase::with_version(Version::V3)
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+            assert_that!(
+                vm.sys_complete_awakeable_with_idempotency_key(
+                    "prom_1abc".to_owned(),
+                    NonEmptyValue::Success(Bytes::from_static(b"123")),
+                    "my-idempotency-key".to_owned(),
+                ),
+                err(eq_vm_error(
+                    vm::errors::UnsupportedFeatureForNegotiatedVersion::new(
+                        ProtocolFeature::IdempotencyKeyOnCompletion,
+                        Version::V3
+                    )
+                    .into()
+                ))
+            );
+        });
+
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(
+            vm::errors::UnsupportedFeatureForNegotiatedVersion::new(
+                ProtocolFeature::IdempotencyKeyOnCompletion,
+                Version::V3
+            )
+            .into()
+        )
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn replay_awakeable_completed_with_void_or_empty_value() {
+    VMTestCase::new()
+        .input(start_message(3))
+        .input(input_entry_message(b"my-data"))
+        .input(AwakeableEntryMessage {
+            result: Some(awakeable_entry_message::Result::Empty(Empty::default())),
+            ..Default::default()
+        })
+        .input(AwakeableEntryMessage {
+            result: Some(awakeable_entry_message::Result::Value(Bytes::new())),
+            ..Default::default()
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let (_, void_handle) = vm.sys_awakeable().unwrap();
+            let (_, empty_handle) = vm.sys_awakeable().unwrap();
+
+            vm.notify_await_point(void_handle);
+            assert_eq!(
+                vm.take_async_result(void_handle).unwrap(),
+                Some(Value::Void)
+            );
+            vm.notify_await_point(empty_handle);
+            assert_eq!(
+                vm.take_async_result(empty_handle).unwrap(),
+                Some(Value::Success(Bytes::new()))
+            );
+
+            vm.sys_end().unwrap();
+        });
+}
+
+#[test]
+fn strict_completion_check_fails_on_unawaited_call() {
+    let mut output = VMTestCase::with_options(VMOptions {
+        strict_completion_check: true,
+        ..VMOptions::default()
+    })
+    .input(start_message(1))
+    .input(input_entry_message(b"my-data"))
+    .run(|vm| {
+        vm.sys_input().unwrap();
+
+        let h1 = vm
+            .sys_call(greeter_target(), Bytes::from_static(b"Till"))
+            .unwrap();
+        let h2 = vm
+            .sys_call(greeter_target(), Bytes::from_static(b"Igal"))
+            .unwrap();
+        assert_eq!((u32::from(h1), u32::from(h2)), (1, 2));
+
+        assert_that!(
+            vm.sys_end(),
+            err(eq_vm_error(
+                vm::errors::UnawaitedAsyncResultsError {
+                    handles: vec![1, 2]
+                }
+                .into()
+            ))
+        );
+    });
+
+    assert_eq!(
+        output.next_decoded::<CallEntryMessage>().unwrap().parameter,
+        Bytes::from_static(b"Till")
+    );
+    assert_eq!(
+        output.next_decoded::<CallEntryMessage>().unwrap().parameter,
+        Bytes::from_static(b"Igal")
+    );
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(
+            vm::errors::UnawaitedAsyncResultsError {
+                handles: vec![1, 2]
+            }
+            .into()
+        )
+    );
+    assert_eq!(output.next(), None);
+
+    let error = Error::from(vm::errors::UnawaitedAsyncResultsError {
+        handles: vec![1, 2],
+    });
+    assert_eq!(
+        error.code_enum(),
+        Some(error::ErrorCode::UnawaitedAsyncResults)
+    );
+    assert!(!error.is_retryable());
+}
+
+#[test]
+fn strict_completion_check_succeeds_when_all_results_are_taken() {
+    let mut output = VMTestCase::with_options(VMOptions {
+        strict_completion_check: true,
+        ..VMOptions::default()
+    })
+    .input(start_message(1))
+    .input(input_entry_message(b"my-data"))
+    .input(CompletionMessage {
+        entry_index: 1,
+        result: Some(completion_message::Result::Value(Bytes::from_static(
+            b"Hello Till",
+        ))),
+    })
+    .run(|vm| {
+        vm.sys_input().unwrap();
+
+        let h = vm
+            .sys_call(greeter_target(), Bytes::from_static(b"Till"))
+            .unwrap();
+        vm.notify_await_point(h);
+        assert_eq!(
+            vm.take_async_result(h).unwrap(),
+            Some(Value::Success(Bytes::from_static(b"Hello Till")))
+        );
+
+        vm.sys_end().unwrap();
+    });
+
+    let _ = output.next_decoded::<CallEntryMessage>().unwrap();
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn was_replayed() {
+    let mut output = VMTestCase::new()
+        .input(start_message(2))
+        .input(input_entry_message(b"my-data"))
+        .input(CallEntryMessage {
+            service_name: "Greeter".to_owned(),
+            handler_name: "greeter".to_owned(),
+            parameter: Bytes::from_static(b"Till"),
+            ..Default::default()
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let replayed = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Till"))
+                .unwrap();
+            assert!(vm.was_replayed(replayed));
+
+            let created = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Francesco"))
+                .unwrap();
+            assert!(!vm.was_replayed(created));
+
+            vm.sys_end().unwrap();
+        });
+
+    assert_eq!(
+        output.next_decoded::<CallEntryMessage>().unwrap().parameter,
+        Bytes::from_static(b"Francesco")
+    );
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn ready_result_len() {
+    VMTestCase::new()
+        .input(start_message(2))
+        .input(input_entry_message(b"my-data"))
+        .input(SleepEntryMessage {
+            result: Some(sleep_entry_message::Result::Empty(Empty::default())),
+            ..Default::default()
+        })
+        .input(CompletionMessage {
+            entry_index: 2,
+            result: Some(completion_message::Result::Value(Bytes::from_static(
+                b"Hello Till",
+            ))),
+        })
+        .input(CompletionMessage {
+            entry_index: 4,
+            result: Some(completion_message::Result::Failure(Failure {
+                code: 500,
+                message: "my-failure".to_owned(),
+            })),
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let sleep = vm.sys_sleep(Duration::from_secs(1), None).unwrap();
+            let completed_call = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Till"))
+                .unwrap();
+            let pending_call = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Francesco"))
+                .unwrap();
+            let failed_call = vm
+                .sys_call(greeter_target(), Bytes::from_static(b"Igal"))
+                .unwrap();
+
+            assert_eq!(vm.ready_result_len(sleep), None);
+            assert_eq!(vm.ready_result_len(completed_call), Some(10));
+            assert_eq!(vm.ready_result_len(pending_call), None);
+            assert_eq!(vm.ready_result_len(failed_call), Some(10));
+
+            // The result is not consumed
+            vm.notify_await_point(completed_call);
+            assert_eq!(
+                vm.take_async_result(completed_call).unwrap(),
+                Some(Value::Success(Bytes::from_static(b"Hello Till")))
+            );
+            assert_eq!(vm.ready_result_len(completed_call), None);
+
+            vm.sys_end().unwrap();
+        });
+}
@@ -181,3 +181,604 @@ fn send_then_get_invocation_id_then_cancel_invocation() {
     );
     assert_eq!(output.next(), None);
 }
+
+#[test]
+fn delayed_call() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .input(CompletionMessage {
+            entry_index: 1,
+            result: Some(completion_message::Result::Value(Bytes::from_static(
+                b"Hello",
+            ))),
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let call_handle = vm
+                .sys_call_delayed(
+                    Target {
+                        service: "MySvc".to_string(),
+                        handler: "MyHandler".to_string(),
+                        key: None,
+                        idempotency_key: None,
+                        headers: Vec::new(),
+                    },
+                    Bytes::new(),
+                    Some(Duration::from_millis(1721123699086)),
+                )
+                .unwrap();
+            vm.notify_await_point(call_handle);
+            let_assert!(Some(Value::Success(result)) = vm.take_async_result(call_handle).unwrap());
+            assert_eq!(result, Bytes::from_static(b"Hello"));
+
+            vm.sys_end().unwrap();
+        });
+
+    assert_that!(
+        output.next_decoded::<CallEntryMessage>().unwrap(),
+        pat!(CallEntryMessage {
+            service_name: eq("MySvc"),
+            handler_name: eq("MyHandler"),
+            invoke_time: eq(1721123699086)
+        })
+    );
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn delayed_call_unsupported_on_v3() {
+    let mut output = VMTestCase::with_version(Version::V3)
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            assert_that!(
+                vm.sys_call_delayed(
+                    Target {
+                        service: "MySvc".to_string(),
+                        handler: "MyHandler".to_string(),
+                        key: None,
+                        idempotency_key: None,
+                        headers: Vec::new(),
+                    },
+                    Bytes::new(),
+                    Some(Duration::from_millis(1721123699086)),
+                ),
+                err(eq_vm_error(
+                    vm::errors::UnsupportedFeatureForNegotiatedVersion::new(
+                        ProtocolFeature::DelayedCall,
+                        Version::V3
+                    )
+                    .into()
+                ))
+            );
+        });
+
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(
+            vm::errors::UnsupportedFeatureForNegotiatedVersion::new(
+                ProtocolFeature::DelayedCall,
+                Version::V3
+            )
+            .into()
+        )
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn call_invocation_id_ready_or_pending() {
+    let mut output = VMTestCase::new()
+        .input(start_message(3))
+        .input(input_entry_message(b"my-data"))
+        .input(CallEntryMessage {
+            service_name: "MySvc".to_owned(),
+            handler_name: "MyHandler".to_owned(),
+            ..Default::default()
+        })
+        .input(GetCallInvocationIdEntryMessage {
+            call_entry_index: 1,
+            result: Some(get_call_invocation_id_entry_message::Result::Value(
+                "my-id".to_owned(),
+            )),
+            ..Default::default()
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let target = || Target {
+                service: "MySvc".to_string(),
+                handler: "MyHandler".to_string(),
+                key: None,
+                idempotency_key: None,
+                headers: Vec::new(),
+            };
+
+            let replayed_call = vm.sys_call(target(), Bytes::new()).unwrap();
+            assert_eq!(
+                vm.call_invocation_id(GetInvocationIdTarget::CallEntry(replayed_call))
+                    .unwrap(),
+                CallInvocationIdResult::Ready("my-id".to_owned())
+            );
+
+            let new_call = vm.sys_call(target(), Bytes::new()).unwrap();
+            assert_eq!(
+                vm.call_invocation_id(GetInvocationIdTarget::CallEntry(new_call))
+                    .unwrap(),
+                CallInvocationIdResult::Pending(AsyncResultHandle(4))
+            );
+
+            vm.sys_end().unwrap();
+        });
+
+    output.next_decoded::<CallEntryMessage>().unwrap();
+    assert_eq!(
+        output
+            .next_decoded::<GetCallInvocationIdEntryMessage>()
+            .unwrap(),
+        GetCallInvocationIdEntryMessage {
+            call_entry_index: 3,
+            ..Default::default()
+        }
+    );
+    output.next_decoded::<EndMessage>().unwrap();
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn call_with_empty_service_name() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            assert_that!(
+                vm.sys_call(
+                    Target {
+                        service: "".to_string(),
+                        handler: "MyHandler".to_string(),
+                        key: None,
+                        idempotency_key: None,
+                        headers: Vec::new(),
+                    },
+                    Bytes::new(),
+                ),
+                err(eq_vm_error(vm::errors::EMPTY_SERVICE_NAME))
+            );
+        });
+
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(vm::errors::EMPTY_SERVICE_NAME)
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn send_with_empty_handler_name() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            assert_that!(
+                vm.sys_send(
+                    Target {
+                        service: "MySvc".to_string(),
+                        handler: "".to_string(),
+                        key: None,
+                        idempotency_key: None,
+                        headers: Vec::new(),
+                    },
+                    Bytes::new(),
+                    None,
+                ),
+                err(eq_vm_error(vm::errors::EMPTY_HANDLER_NAME))
+            );
+        });
+
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(vm::errors::EMPTY_HANDLER_NAME)
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn target_validate() {
+    let target = || Target {
+        service: "MySvc".to_string(),
+        handler: "MyHandler".to_string(),
+        key: Some("my-key".to_string()),
+        idempotency_key: Some("my-idempotency-key".to_string()),
+        headers: Vec::new(),
+    };
+    assert!(target().validate().is_ok());
+
+    assert_eq!(
+        Target {
+            idempotency_key: Some("".to_string()),
+            ..target()
+        }
+        .validate()
+        .unwrap_err()
+        .message(),
+        vm::errors::EMPTY_IDEMPOTENCY_KEY.message()
+    );
+}
+
+fn target_with_headers() -> Target {
+    Target {
+        service: "MySvc".to_string(),
+        handler: "MyHandler".to_string(),
+        key: None,
+        idempotency_key: None,
+        headers: Vec::new(),
+    }
+    .with_header("x-b", "1")
+    .with_header("x-a", "2")
+    .with_headers([(Cow::Borrowed("x-b"), Cow::Borrowed("3"))])
+}
+
+#[test]
+fn call_headers_are_deduplicated_and_sorted() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+            vm.sys_call(target_with_headers(), Bytes::new()).unwrap();
+            vm.sys_end().unwrap();
+        });
+
+    assert_eq!(
+        output.next_decoded::<CallEntryMessage>().unwrap().headers,
+        vec![
+            messages::Header {
+                key: "x-a".to_owned(),
+                value: "2".to_owned()
+            },
+            messages::Header {
+                key: "x-b".to_owned(),
+                value: "3".to_owned()
+            }
+        ]
+    );
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn call_headers_replay_is_order_insensitive() {
+    let mut output = VMTestCase::new()
+        .input(start_message(2))
+        .input(input_entry_message(b"my-data"))
+        .input(CallEntryMessage {
+            service_name: "MySvc".to_string(),
+            handler_name: "MyHandler".to_string(),
+            headers: vec![
+                messages::Header {
+                    key: "x-b".to_owned(),
+                    value: "3".to_owned(),
+                },
+                messages::Header {
+                    key: "x-a".to_owned(),
+                    value: "2".to_owned(),
+                },
+            ],
+            result: Some(call_entry_message::Result::Value(Bytes::from_static(
+                b"Hello",
+            ))),
+            ..Default::default()
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let call_handle = vm.sys_call(target_with_headers(), Bytes::new()).unwrap();
+            vm.notify_await_point(call_handle);
+            let_assert!(Some(Value::Success(result)) = vm.take_async_result(call_handle).unwrap());
+            assert_eq!(result, Bytes::from_static(b"Hello"));
+
+            vm.sys_end().unwrap();
+        });
+
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn call_headers_replay_normalizes_duplicated_replayed_headers() {
+    let mut output = VMTestCase::new()
+        .input(start_message(2))
+        .input(input_entry_message(b"my-data"))
+        .input(CallEntryMessage {
+            service_name: "MySvc".to_string(),
+            handler_name: "MyHandler".to_string(),
+            headers: vec![
+                messages::Header {
+                    key: "x-b".to_owned(),
+                    value: "1".to_owned(),
+                },
+                messages::Header {
+                    key: "x-a".to_owned(),
+                    value: "2".to_owned(),
+                },
+                messages::Header {
+                    key: "x-b".to_owned(),
+                    value: "3".to_owned(),
+                },
+            ],
+            result: Some(call_entry_message::Result::Value(Bytes::from_static(
+                b"Hello",
+            ))),
+            ..Default::default()
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let call_handle = vm.sys_call(target_with_headers(), Bytes::new()).unwrap();
+            vm.notify_await_point(call_handle);
+            let_assert!(Some(Value::Success(result)) = vm.take_async_result(call_handle).unwrap());
+            assert_eq!(result, Bytes::from_static(b"Hello"));
+
+            vm.sys_end().unwrap();
+        });
+
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn call_with_timeout_sleep_completes_first() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .input(CompletionMessage {
+            entry_index: 2,
+            result: Some(completion_message::Result::Empty(Empty::default())),
+        })
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let (call_handle, sleep_handle) = vm
+                .sys_call_with_timeout(
+                    Target {
+                        service: "greeter".to_owned(),
+                        handler: "greet".to_owned(),
+                        key: None,
+                        idempotency_key: None,
+                        headers: Vec::new(),
+                    },
+                    Bytes::from_static(b"Till"),
+                    Duration::from_secs(1),
+                    Duration::from_millis(1721123699086),
+                )
+                .unwrap();
+            assert_eq!(u32::from(call_handle), 1);
+            assert_eq!(u32::from(sleep_handle), 2);
+
+            vm.notify_await_point(sleep_handle);
+            let_assert!(Ok(Some(Value::Void)) = vm.take_async_result(sleep_handle));
+            let_assert!(Ok(None) = vm.take_async_result(call_handle));
+
+            vm.sys_write_output(NonEmptyValue::Failure(TerminalFailure {
+                code: 408,
+                message: "timeout".to_owned(),
+            }))
+            .unwrap();
+            vm.sys_end().unwrap();
+        });
+
+    assert_eq!(
+        output.next_decoded::<CallEntryMessage>().unwrap(),
+        CallEntryMessage {
+            service_name: "greeter".to_owned(),
+            handler_name: "greet".to_owned(),
+            parameter: Bytes::from_static(b"Till"),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        output.next_decoded::<SleepEntryMessage>().unwrap(),
+        SleepEntryMessage {
+            wake_up_time: 1721123700086,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        output.next_decoded::<OutputEntryMessage>().unwrap(),
+        OutputEntryMessage {
+            result: Some(output_entry_message::Result::Failure(Failure {
+                code: 408,
+                message: "timeout".to_owned(),
+            })),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn call_with_timeout_overflowing_duration() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            assert_that!(
+                vm.sys_call_with_timeout(
+                    Target {
+                        service: "greeter".to_owned(),
+                        handler: "greet".to_owned(),
+                        key: None,
+                        idempotency_key: None,
+                        headers: Vec::new(),
+                    },
+                    Bytes::from_static(b"Till"),
+                    Duration::from_secs(u64::MAX / 1000),
+                    Duration::from_millis(1721123699086)
+                ),
+                err(eq_vm_error(vm::errors::SLEEP_DURATION_OVERFLOW))
+            );
+        });
+
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(vm::errors::SLEEP_DURATION_OVERFLOW)
+    );
+    assert_eq!(output.next(), None);
+}
+
+mod send_with_acceptance {
+    use super::*;
+
+    use test_log::test;
+
+    fn greeter_target() -> Target {
+        Target {
+            service: "greeter".to_owned(),
+            handler: "greet".to_owned(),
+            key: None,
+            idempotency_key: None,
+            headers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn completes_when_accepted() {
+        let mut output = VMTestCase::new()
+            .input(start_message(1))
+            .input(input_entry_message(b"my-data"))
+            .input(CompletionMessage {
+                entry_index: 1,
+                result: Some(completion_message::Result::Empty(Empty::default())),
+            })
+            .run(|vm| {
+                vm.sys_input().unwrap();
+
+                let (send_handle, acceptance_handle) = vm
+                    .sys_send_with_acceptance(greeter_target(), Bytes::from_static(b"Till"), None)
+                    .unwrap();
+                let_assert!(Some(acceptance_handle) = acceptance_handle);
+                assert_eq!(u32::from(send_handle), 1);
+
+                vm.notify_await_point(acceptance_handle);
+                let_assert!(Ok(Some(Value::Void)) = vm.take_async_result(acceptance_handle));
+
+                vm.sys_end().unwrap();
+            });
+
+        assert_eq!(
+            output.next_decoded::<OneWayCallEntryMessage>().unwrap(),
+            OneWayCallEntryMessage {
+                service_name: "greeter".to_owned(),
+                handler_name: "greet".to_owned(),
+                parameter: Bytes::from_static(b"Till"),
+                await_acceptance: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn replayed_acceptance() {
+        let mut output = VMTestCase::new()
+            .input(start_message(2))
+            .input(input_entry_message(b"my-data"))
+            .input(OneWayCallEntryMessage {
+                service_name: "greeter".to_owned(),
+                handler_name: "greet".to_owned(),
+                parameter: Bytes::from_static(b"Till"),
+                await_acceptance: true,
+                result: Some(one_way_call_entry_message::Result::Failure(Failure {
+                    code: 409,
+                    message: "conflict".to_owned(),
+                })),
+                ..Default::default()
+            })
+            .run(|vm| {
+                vm.sys_input().unwrap();
+
+                let (_, acceptance_handle) = vm
+                    .sys_send_with_acceptance(greeter_target(), Bytes::from_static(b"Till"), None)
+                    .unwrap();
+                let_assert!(Some(acceptance_handle) = acceptance_handle);
+
+                vm.notify_await_point(acceptance_handle);
+                let_assert!(
+                    Ok(Some(Value::Failure(failure))) = vm.take_async_result(acceptance_handle)
+                );
+                assert_eq!(failure.code, 409);
+
+                vm.sys_end().unwrap();
+            });
+
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn falls_back_to_send_on_older_versions() {
+        let mut output = VMTestCase::with_version(Version::V3)
+            .input(start_message(1))
+            .input(input_entry_message(b"my-data"))
+            .run(|vm| {
+                vm.sys_input().unwrap();
+
+                let (send_handle, acceptance_handle) = vm
+                    .sys_send_with_acceptance(greeter_target(), Bytes::from_static(b"Till"), None)
+                    .unwrap();
+                assert_eq!(u32::from(send_handle), 1);
+                assert!(acceptance_handle.is_none());
+
+                vm.sys_end().unwrap();
+            });
+
+        assert_eq!(
+            output.next_decoded::<OneWayCallEntryMessage>().unwrap(),
+            OneWayCallEntryMessage {
+                service_name: "greeter".to_owned(),
+                handler_name: "greet".to_owned(),
+                parameter: Bytes::from_static(b"Till"),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+}
@@ -1,15 +1,205 @@
 use super::*;
 
 use crate::service_protocol::messages::{
-    ErrorMessage, GetStateEntryMessage, InputEntryMessage, OneWayCallEntryMessage, StartMessage,
+    complete_awakeable_entry_message, complete_promise_entry_message,
+    CompleteAwakeableEntryMessage, CompletePromiseEntryMessage, ErrorMessage, GetStateEntryMessage,
+    InputEntryMessage, OneWayCallEntryMessage, StartMessage,
 };
+use assert2::let_assert;
+use bytes::BufMut;
 use std::fmt;
 use test_log::test;
 
+#[test]
+fn message_type_unsupported_by_negotiated_version() {
+    let mut vm = CoreVM::mock_init(Version::V3);
+    let encoder = Encoder::new(Version::V3);
+
+    vm.notify_input(encoder.encode(&start_message(2)));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+    vm.notify_input(encoder.encode(&messages::ClearStatePrefixEntryMessage {
+        prefix: Bytes::from_static(b"my-"),
+        ..Default::default()
+    }));
+
+    let expected_error: Error = vm::errors::UnsupportedMessageTypeForNegotiatedVersion::new(
+        MessageType::ClearStatePrefixEntry,
+        Version::V3,
+    )
+    .into();
+    assert_that!(
+        vm.is_ready_to_execute(),
+        err(eq_vm_error(expected_error.clone()))
+    );
+
+    let mut output = OutputIterator::collect_vm(&mut vm);
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(expected_error)
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn input_after_decoding_error_is_discarded() {
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+
+    vm.notify_input(encoder.encode(&start_message(2)));
+    // Unknown message type 0x03FF
+    vm.notify_input(Bytes::from_static(&[0x03, 0xFF, 0, 0, 0, 0, 0, 0]));
+    let_assert!(Err(expected_error) = vm.is_ready_to_execute());
+
+    // More input after the bad frame is dropped, and the error persists
+    for _ in 0..10 {
+        vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+    }
+    assert_that!(
+        vm.is_ready_to_execute(),
+        err(eq_vm_error(expected_error.clone()))
+    );
+
+    let mut output = OutputIterator::collect_vm(&mut vm);
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(expected_error)
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn decoding_error_converts_to_the_vm_error() {
+    const UNKNOWN_MESSAGE_TYPE: &[u8] = &[0x03, 0xFF, 0, 0, 0, 0, 0, 0];
+
+    let mut decoder = Decoder::new(
+        Version::maximum_experimental_version(),
+        DEFAULT_MAX_MESSAGE_SIZE,
+    );
+    decoder.push(Bytes::from_static(UNKNOWN_MESSAGE_TYPE));
+    let_assert!(Err(decoding_error) = decoder.consume_next());
+    let expected_error = Error::from(decoding_error);
+    assert_eq!(
+        expected_error.code_enum(),
+        Some(error::ErrorCode::ProtocolViolation)
+    );
+
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    vm.notify_input(
+        Encoder::new(Version::maximum_experimental_version()).encode(&start_message(1)),
+    );
+    vm.notify_input(Bytes::from_static(UNKNOWN_MESSAGE_TYPE));
+    assert_that!(
+        vm.is_ready_to_execute(),
+        err(eq_vm_error(expected_error.clone()))
+    );
+
+    let mut output = OutputIterator::collect_vm(&mut vm);
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(expected_error)
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn message_exceeding_max_message_size() {
+    let mut vm = CoreVM::mock_init_with_options(
+        Version::maximum_experimental_version(),
+        VMOptions {
+            max_message_size: 1024,
+            ..VMOptions::default()
+        },
+    );
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+
+    vm.notify_input(encoder.encode(&start_message(1)));
+    vm.notify_input(encoder.encode(&InputEntryMessage {
+        value: Bytes::from(vec![0; 2048]),
+        ..InputEntryMessage::default()
+    }));
+
+    let_assert!(Err(err) = vm.is_ready_to_execute());
+    assert!(err
+        .message()
+        .contains("exceeding the maximum message size of 1024 bytes"));
+    assert_eq!(err.code_enum(), Some(error::ErrorCode::ProtocolViolation));
+    assert!(!err.is_retryable());
+
+    let mut output = OutputIterator::collect_vm(&mut vm);
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(err)
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn duplicate_completion() {
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+
+    let completion = messages::CompletionMessage {
+        entry_index: 1,
+        result: Some(messages::completion_message::Result::Value(
+            Bytes::from_static(b"Francesco"),
+        )),
+    };
+    vm.notify_input(encoder.encode(&start_message(1)));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+    vm.notify_input(encoder.encode(&completion));
+    vm.notify_input(encoder.encode(&completion));
+
+    let expected_error: Error = vm::errors::DuplicateCompletionError { index: 1 }.into();
+    let_assert!(Err(err) = vm.is_ready_to_execute());
+    assert_eq!(err.code_enum(), Some(error::ErrorCode::ProtocolViolation));
+    assert_that!(err, eq_vm_error(expected_error.clone()));
+
+    let mut output = OutputIterator::collect_vm(&mut vm);
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(expected_error)
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn duplicate_completion_of_ready_result() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run_without_closing_input(|vm, encoder| {
+            vm.sys_input().unwrap();
+            let handle = vm.sys_state_get("Personaggio".to_owned()).unwrap();
+
+            let completion = messages::CompletionMessage {
+                entry_index: 1,
+                result: Some(messages::completion_message::Result::Value(
+                    Bytes::from_static(b"Francesco"),
+                )),
+            };
+            vm.notify_input(encoder.encode(&completion));
+            vm.notify_input(encoder.encode(&completion));
+
+            assert_that!(
+                vm.take_async_result(handle),
+                err(pat!(SuspendedOrVMError::VM(eq_vm_error(
+                    vm::errors::DuplicateCompletionError { index: 1 }.into()
+                ))))
+            );
+        });
+
+    assert!(output.next_decoded::<GetStateEntryMessage>().is_some());
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(vm::errors::DuplicateCompletionError { index: 1 }.into())
+    );
+    assert_eq!(output.next(), None);
+}
+
 #[test]
 fn got_closed_stream_before_end_of_replay() {
-    let mut vm = CoreVM::mock_init(Version::maximum_supported_version());
-    let encoder = Encoder::new(Version::maximum_supported_version());
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    let encoder = Encoder::new(Version::maximum_experimental_version());
 
     vm.notify_input(encoder.encode(&StartMessage {
         id: Bytes::from_static(b"123"),
@@ -85,6 +275,95 @@ fn one_way_call_entry_mismatch() {
     );
 }
 
+#[test]
+fn one_way_call_acceptance_mismatch() {
+    test_entry_mismatch(
+        OneWayCallEntryMessage {
+            service_name: "greeter".to_owned(),
+            handler_name: "greet".to_owned(),
+            parameter: Bytes::from_static(b"123"),
+            ..Default::default()
+        },
+        OneWayCallEntryMessage {
+            service_name: "greeter".to_owned(),
+            handler_name: "greet".to_owned(),
+            parameter: Bytes::from_static(b"123"),
+            await_acceptance: true,
+            ..Default::default()
+        },
+        |vm| {
+            vm.sys_send_with_acceptance(
+                Target {
+                    service: "greeter".to_owned(),
+                    handler: "greet".to_owned(),
+                    key: None,
+                    idempotency_key: None,
+                    headers: Vec::new(),
+                },
+                Bytes::from_static(b"123"),
+                None,
+            )
+        },
+    );
+}
+
+#[test]
+fn complete_promise_idempotency_key_mismatch() {
+    test_entry_mismatch(
+        CompletePromiseEntryMessage {
+            key: "my-prom".to_owned(),
+            idempotency_key: Some("key-1".to_owned()),
+            completion: Some(complete_promise_entry_message::Completion::CompletionValue(
+                Bytes::from_static(b"123"),
+            )),
+            ..Default::default()
+        },
+        CompletePromiseEntryMessage {
+            key: "my-prom".to_owned(),
+            idempotency_key: Some("key-2".to_owned()),
+            completion: Some(complete_promise_entry_message::Completion::CompletionValue(
+                Bytes::from_static(b"123"),
+            )),
+            ..Default::default()
+        },
+        |vm| {
+            vm.sys_complete_promise_with_idempotency_key(
+                "my-prom".to_owned(),
+                NonEmptyValue::Success(Bytes::from_static(b"123")),
+                "key-2".to_owned(),
+            )
+        },
+    );
+}
+
+#[test]
+fn complete_awakeable_idempotency_key_mismatch() {
+    test_entry_mismatch(
+        CompleteAwakeableEntryMessage {
+            id: "prom_1abc".to_owned(),
+            idempotency_key: Some("key-1".to_owned()),
+            result: Some(complete_awakeable_entry_message::Result::Value(
+                Bytes::from_static(b"123"),
+            )),
+            ..Default::default()
+        },
+        CompleteAwakeableEntryMessage {
+            id: "prom_1abc".to_owned(),
+            idempotency_key: None,
+            result: Some(complete_awakeable_entry_message::Result::Value(
+                Bytes::from_static(b"123"),
+            )),
+            ..Default::default()
+        },
+        |vm| {
+            vm.sys_complete_awakeable(
+                "prom_1abc".to_owned(),
+                NonEmptyValue::Success(Bytes::from_static(b"123")),
+            )
+        },
+    );
+}
+
 fn test_entry_mismatch<M: WriteableRestateMessage + Clone, T: fmt::Debug>(
     expected: M,
     actual: M,
@@ -107,12 +386,16 @@ fn test_entry_mismatch<M: WriteableRestateMessage + Clone, T: fmt::Debug>(
         .run(|vm| {
             vm.sys_input().unwrap();
 
+            let result = user_code(vm);
             assert_that!(
-                user_code(vm),
+                result,
                 err(eq_vm_error(
                     vm::errors::EntryMismatchError::new(expected.clone(), actual.clone(),).into()
                 ))
             );
+            let err = result.unwrap_err();
+            assert_eq!(err.code_enum(), Some(error::ErrorCode::JournalMismatch));
+            assert!(!err.is_retryable());
         });
 
     assert_that!(
@@ -121,3 +404,142 @@ fn test_entry_mismatch<M: WriteableRestateMessage + Clone, T: fmt::Debug>(
     );
     assert_eq!(output.next(), None);
 }
+
+#[test]
+fn error_code_enum() {
+    assert_eq!(
+        Error::internal("my-error").code_enum(),
+        Some(error::ErrorCode::Internal)
+    );
+    assert_eq!(Error::new(418u16, "I'm a teapot").code_enum(), None);
+    assert_eq!(u16::from(error::ErrorCode::ProtocolViolation), 571);
+    for code in [
+        400u16, 409, 415, 500, 570, 571, 572, 573, 574, 575, 576, 577,
+    ] {
+        assert_eq!(error::ErrorCode::from_code(code).map(u16::from), Some(code));
+    }
+}
+
+#[test]
+fn error_is_retryable() {
+    assert!(Error::internal("my-error").is_retryable());
+    assert!(Error::new(418u16, "I'm a teapot").is_retryable());
+    assert!(!Error::new(error::codes::PROTOCOL_VIOLATION, "bad").is_retryable());
+    assert!(!Error::new(error::codes::UNSUPPORTED_MEDIA_TYPE, "bad").is_retryable());
+    assert!(
+        !Error::from(vm::errors::UnsupportedFeatureForNegotiatedVersion::new(
+            ProtocolFeature::CancelInvocation,
+            Version::V2
+        ))
+        .is_retryable()
+    );
+}
+
+/// Small deterministic PRNG (xorshift64*), to generate random protocol streams without extra dependencies.
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next_u64() as u8).collect()
+    }
+
+    /// Either random bytes, or a frame with a plausible header and a random payload.
+    fn chunk(&mut self) -> Bytes {
+        if self.below(4) == 0 {
+            let len = self.below(64);
+            return self.bytes(len).into();
+        }
+
+        let types = MessageType::supported_by(Version::maximum_experimental_version());
+        let ty = if self.below(8) == 0 {
+            self.next_u64() as u16
+        } else {
+            u16::from(types[self.below(types.len())])
+        };
+        let payload_len = self.below(48);
+        // Sometimes lie about the payload length
+        let declared_len = if self.below(8) == 0 {
+            self.below(64) as u32
+        } else {
+            payload_len as u32
+        };
+        // Random flags in the reserved bits
+        let flags = (self.next_u64() as u16 as u64) << 32;
+        let header = ((ty as u64) << 48) | flags | declared_len as u64;
+
+        let mut frame = BytesMut::new();
+        frame.put_u64(header);
+        frame.put_slice(&self.bytes(payload_len));
+        if self.below(2) == 0 {
+            frame.put_u32(self.next_u64() as u32);
+        }
+        frame.freeze()
+    }
+}
+
+#[test]
+fn decoder_never_panics_on_random_input() {
+    for seed in 1..=2000 {
+        let mut rng = Xorshift(seed);
+        let mut decoder = Decoder::new(Version::maximum_experimental_version(), 1024);
+
+        for _ in 0..rng.below(16) {
+            let chunk = rng.chunk();
+            // Split the chunk at a random point, to exercise partial frames
+            let at = rng.below(chunk.len() + 1);
+            decoder.push(chunk.slice(..at));
+            decoder.push(chunk.slice(at..));
+
+            loop {
+                match decoder.consume_next() {
+                    Ok(Some(msg)) => {
+                        let _ = decode_command(msg.ty(), msg.payload());
+                    }
+                    Ok(None) => break,
+                    Err(_) => {
+                        assert!(decoder.is_poisoned());
+                        assert!(decoder.consume_next().unwrap().is_none());
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn vm_never_panics_on_random_input() {
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+
+    for seed in 1..=2000 {
+        let mut rng = Xorshift(seed);
+        let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+
+        vm.notify_input(encoder.encode(&start_message(rng.below(4) as u32 + 1)));
+        if rng.below(2) == 0 {
+            vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+        }
+        for _ in 0..rng.below(8) {
+            vm.notify_input(rng.chunk());
+        }
+        vm.notify_input_closed();
+
+        if let Ok(true) = vm.is_ready_to_execute() {
+            let _ = vm.sys_input();
+            let _ = vm.sys_state_get("my-key".to_owned());
+            let _ = vm.sys_end();
+        }
+        while let TakeOutputResult::Buffer(_) = vm.take_output() {}
+    }
+}
@@ -190,3 +190,293 @@ fn completed_with_eager_state() {
     );
     assert_eq!(output.next(), None);
 }
+
+#[test]
+fn force_lazy_state_with_complete_eager_state() {
+    let mut output = VMTestCase::with_options(VMOptions {
+        force_lazy_state: true,
+        ..VMOptions::default()
+    })
+    .input(StartMessage {
+        id: Bytes::from_static(b"123"),
+        debug_id: "123".to_string(),
+        known_entries: 1,
+        state_map: vec![StateEntry {
+            key: Bytes::from_static(b"Personaggio"),
+            value: Bytes::from_static(b"Francesco"),
+        }],
+        partial_state: false,
+        ..Default::default()
+    })
+    .input(input_entry_message(b"my-data"))
+    .run(|vm| {
+        vm.sys_input().unwrap();
+
+        vm.sys_state_get_keys().unwrap();
+        let handle = vm.sys_state_get("Personaggio".to_owned()).unwrap();
+
+        vm.notify_await_point(handle);
+        assert_that!(
+            vm.take_async_result(handle),
+            err(pat!(SuspendedOrVMError::Suspended(_)))
+        );
+    });
+
+    assert_eq!(
+        output
+            .next_decoded::<messages::GetStateKeysEntryMessage>()
+            .unwrap(),
+        messages::GetStateKeysEntryMessage::default()
+    );
+    assert_eq!(
+        output.next_decoded::<GetStateEntryMessage>().unwrap(),
+        GetStateEntryMessage {
+            key: Bytes::from_static(b"Personaggio"),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        output.next_decoded::<SuspensionMessage>().unwrap(),
+        SuspensionMessage {
+            entry_indexes: vec![2],
+        }
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn force_lazy_state_replays_eager_entry() {
+    let mut output = VMTestCase::with_options(VMOptions {
+        force_lazy_state: true,
+        ..VMOptions::default()
+    })
+    .input(start_message(2))
+    .input(input_entry_message(b"my-data"))
+    .input(GetStateEntryMessage {
+        key: Bytes::from_static(b"Personaggio"),
+        result: Some(get_state_entry_message::Result::Value(Bytes::from_static(
+            b"Francesco",
+        ))),
+        ..Default::default()
+    })
+    .run(|vm| {
+        vm.sys_input().unwrap();
+
+        let handle = vm.sys_state_get("Personaggio".to_owned()).unwrap();
+        vm.notify_await_point(handle);
+        let_assert!(Some(Value::Success(b)) = vm.take_async_result(handle).unwrap());
+        assert_eq!(b, b"Francesco".to_vec());
+
+        vm.sys_end().unwrap();
+    });
+
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+fn prefetching_handler(vm: &mut CoreVM) {
+    vm.sys_input().unwrap();
+    vm.prefetch_state_keys(vec!["b".to_owned(), "c".to_owned()]);
+
+    let mut values = Vec::new();
+    for key in ["a", "b", "c"] {
+        let handle = vm.sys_state_get(key.to_owned()).unwrap();
+        vm.notify_await_point(handle);
+        match vm.take_async_result(handle) {
+            Ok(Some(Value::Success(b))) => values.extend_from_slice(&b),
+            Err(SuspendedOrVMError::Suspended(_)) => return,
+            r => panic!("Unexpected result {r:?}"),
+        }
+    }
+
+    vm.sys_write_output(NonEmptyValue::Success(values.into()))
+        .unwrap();
+    vm.sys_end().unwrap();
+}
+
+#[test]
+fn prefetch_state_keys_issues_hinted_gets_together() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(prefetching_handler);
+
+    for key in ["a", "b", "c"] {
+        assert_eq!(
+            output.next_decoded::<GetStateEntryMessage>().unwrap(),
+            GetStateEntryMessage {
+                key: Bytes::copy_from_slice(key.as_bytes()),
+                ..Default::default()
+            }
+        );
+    }
+    assert_eq!(
+        output.next_decoded::<SuspensionMessage>().unwrap(),
+        SuspensionMessage {
+            entry_indexes: vec![1],
+        }
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn prefetch_state_keys_replay_without_further_suspensions() {
+    let completed_get = |key: &'static [u8], value: &'static [u8]| GetStateEntryMessage {
+        key: Bytes::from_static(key),
+        result: Some(get_state_entry_message::Result::Value(Bytes::from_static(
+            value,
+        ))),
+        ..Default::default()
+    };
+
+    let mut output = VMTestCase::new()
+        .input(start_message(4))
+        .input(input_entry_message(b"my-data"))
+        .input(completed_get(b"a", b"1"))
+        .input(completed_get(b"b", b"2"))
+        .input(completed_get(b"c", b"3"))
+        .run(prefetching_handler);
+
+    assert_eq!(
+        output.next_decoded::<OutputEntryMessage>().unwrap(),
+        OutputEntryMessage {
+            result: Some(output_entry_message::Result::Value(Bytes::from_static(
+                b"123"
+            ))),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn prefetched_state_is_discarded_when_set() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+            vm.prefetch_state_keys(vec!["b".to_owned()]);
+
+            let _ = vm.sys_state_get("a".to_owned()).unwrap();
+            vm.sys_state_set("b".to_owned(), Bytes::from_static(b"Till"))
+                .unwrap();
+
+            // Resolved with the local value, rather than with the prefetched get
+            let handle = vm.sys_state_get("b".to_owned()).unwrap();
+            assert_eq!(u32::from(handle), 4);
+            assert_eq!(
+                vm.take_async_result(handle).unwrap(),
+                Some(Value::Success(Bytes::from_static(b"Till")))
+            );
+            vm.sys_end().unwrap();
+        });
+
+    assert_eq!(
+        output.next_decoded::<GetStateEntryMessage>().unwrap().key,
+        Bytes::from_static(b"a")
+    );
+    assert_eq!(
+        output.next_decoded::<GetStateEntryMessage>().unwrap().key,
+        Bytes::from_static(b"b")
+    );
+    let _ = output
+        .next_decoded::<messages::SetStateEntryMessage>()
+        .unwrap();
+    assert_eq!(
+        output.next_decoded::<GetStateEntryMessage>().unwrap(),
+        GetStateEntryMessage {
+            key: Bytes::from_static(b"b"),
+            result: Some(get_state_entry_message::Result::Value(Bytes::from_static(
+                b"Till"
+            ))),
+            ..Default::default()
+        }
+    );
+    output.next_decoded::<EndMessage>().unwrap();
+    assert_eq!(output.next(), None);
+}
+
+fn completed_a(vm: &mut CoreVM) {
+    let handle = vm.sys_state_get("a".to_owned()).unwrap();
+    vm.notify_await_point(handle);
+    assert_eq!(
+        vm.take_async_result(handle).unwrap(),
+        Some(Value::Success(Bytes::from_static(b"1")))
+    );
+}
+
+#[test]
+fn strict_completion_check_ignores_prefetched_state_never_read() {
+    let mut output = VMTestCase::with_options(VMOptions {
+        strict_completion_check: true,
+        ..VMOptions::default()
+    })
+    .input(start_message(1))
+    .input(input_entry_message(b"my-data"))
+    .input(CompletionMessage {
+        entry_index: 1,
+        result: Some(completion_message::Result::Value(Bytes::from_static(b"1"))),
+    })
+    .run(|vm| {
+        vm.sys_input().unwrap();
+        vm.prefetch_state_keys(vec!["b".to_owned()]);
+        completed_a(vm);
+        vm.sys_end().unwrap();
+    });
+
+    assert_eq!(
+        output.next_decoded::<GetStateEntryMessage>().unwrap().key,
+        Bytes::from_static(b"a")
+    );
+    assert_eq!(
+        output.next_decoded::<GetStateEntryMessage>().unwrap().key,
+        Bytes::from_static(b"b")
+    );
+    output.next_decoded::<EndMessage>().unwrap();
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn strict_completion_check_ignores_discarded_prefetched_state() {
+    let mut output = VMTestCase::with_options(VMOptions {
+        strict_completion_check: true,
+        ..VMOptions::default()
+    })
+    .input(start_message(1))
+    .input(input_entry_message(b"my-data"))
+    .input(CompletionMessage {
+        entry_index: 1,
+        result: Some(completion_message::Result::Value(Bytes::from_static(b"1"))),
+    })
+    .run(|vm| {
+        vm.sys_input().unwrap();
+        vm.prefetch_state_keys(vec!["b".to_owned(), "c".to_owned()]);
+        completed_a(vm);
+        vm.sys_state_clear("b".to_owned()).unwrap();
+        vm.sys_state_clear_all().unwrap();
+        vm.sys_end().unwrap();
+    });
+
+    for key in [b"a", b"b", b"c"] {
+        assert_eq!(
+            output.next_decoded::<GetStateEntryMessage>().unwrap().key,
+            Bytes::from_static(key)
+        );
+    }
+    let _ = output
+        .next_decoded::<messages::ClearStateEntryMessage>()
+        .unwrap();
+    let _ = output
+        .next_decoded::<messages::ClearAllStateEntryMessage>()
+        .unwrap();
+    output.next_decoded::<EndMessage>().unwrap();
+    assert_eq!(output.next(), None);
+}
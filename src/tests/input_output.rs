@@ -121,3 +121,371 @@ fn replay_output_too() {
     );
     assert_eq!(output.next(), None);
 }
+
+#[test]
+fn self_cancel() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+            vm.sys_self_cancel().unwrap();
+
+            assert!(!vm.is_processing());
+        });
+
+    assert_that!(
+        output.next_decoded::<OutputEntryMessage>().unwrap(),
+        is_output_with_failure(409, vm::errors::CANCELLED_MESSAGE)
+    );
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn complete_matches_write_output_then_end() {
+    let run = |complete: fn(&mut CoreVM, NonEmptyValue)| -> Vec<(MessageType, Bytes)> {
+        VMTestCase::new()
+            .input(start_message(1))
+            .input(input_entry_message(b"my-data"))
+            .run(|vm| {
+                vm.sys_input().unwrap();
+                complete(
+                    vm,
+                    NonEmptyValue::Failure(TerminalFailure::new(500, "my-failure")),
+                );
+                assert!(!vm.is_processing());
+            })
+            .map(|msg| (msg.ty(), msg.payload().clone()))
+            .collect()
+    };
+
+    let output = run(|vm, value| vm.sys_complete(value).unwrap());
+    assert_eq!(
+        output,
+        run(|vm, value| {
+            vm.sys_write_output(value).unwrap();
+            vm.sys_end().unwrap();
+        })
+    );
+    assert_eq!(
+        output.iter().map(|(ty, _)| *ty).collect::<Vec<_>>(),
+        vec![MessageType::OutputEntry, MessageType::End]
+    );
+}
+
+#[test]
+fn time_since_last_stored_entry() {
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    assert_eq!(vm.time_since_last_stored_entry(), None);
+
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+    vm.notify_input(encoder.encode(&StartMessage {
+        duration_since_last_stored_entry: 1500,
+        ..start_message(1)
+    }));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+    vm.notify_input_closed();
+    assert!(vm.is_ready_to_execute().unwrap());
+
+    assert_eq!(
+        vm.time_since_last_stored_entry(),
+        Some(Duration::from_millis(1500))
+    );
+}
+
+#[test]
+fn current_retry_info() {
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    assert_eq!(vm.current_retry_info(), EntryRetryInfo::default());
+
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+    vm.notify_input(encoder.encode(&StartMessage {
+        retry_count_since_last_stored_entry: 5,
+        duration_since_last_stored_entry: 1500,
+        ..start_message(2)
+    }));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+    vm.notify_input(encoder.encode(&messages::SetStateEntryMessage {
+        key: Bytes::from_static(b"STATE"),
+        value: Bytes::from_static(b"Till"),
+        ..Default::default()
+    }));
+    vm.notify_input_closed();
+    vm.sys_input().unwrap();
+    // Still replaying
+    assert_eq!(vm.current_retry_info(), EntryRetryInfo::default());
+
+    vm.sys_state_set("STATE".to_owned(), Bytes::from_static(b"Till"))
+        .unwrap();
+    assert_eq!(
+        vm.current_retry_info(),
+        EntryRetryInfo {
+            retry_count: 5,
+            retry_loop_duration: Duration::from_millis(1500),
+        }
+    );
+
+    // Only the first entry after the replay is being retried
+    vm.sys_state_set("STATE".to_owned(), Bytes::from_static(b"Francesco"))
+        .unwrap();
+    assert_eq!(vm.current_retry_info(), EntryRetryInfo::default());
+}
+
+#[test]
+fn invocation_kind() {
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    assert_eq!(vm.invocation_kind(), None);
+    vm.notify_input(encoder.encode(&start_message(1)));
+    assert_eq!(vm.invocation_kind(), Some(InvocationKind::Unknown));
+
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    vm.notify_input(encoder.encode(&StartMessage {
+        key: "my-key".to_owned(),
+        ..start_message(1)
+    }));
+    assert_eq!(vm.invocation_kind(), Some(InvocationKind::Keyed));
+}
+
+#[test]
+fn invocation_deadline() {
+    VMTestCase::new()
+        .input(start_message(1))
+        .input(InputEntryMessage {
+            headers: vec![service_protocol::messages::Header {
+                key: INVOCATION_DEADLINE_HEADER.to_owned(),
+                value: "1730000000000".to_owned(),
+            }],
+            value: Bytes::from_static(b"my-data"),
+            ..InputEntryMessage::default()
+        })
+        .run(|vm| {
+            assert_eq!(vm.invocation_deadline(), None);
+            vm.sys_input().unwrap();
+            assert_eq!(
+                vm.invocation_deadline(),
+                Some(Duration::from_millis(1730000000000))
+            );
+            vm.sys_end().unwrap();
+        });
+}
+
+#[test]
+fn invocation_deadline_without_header() {
+    VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+            assert_eq!(vm.invocation_deadline(), None);
+            vm.sys_end().unwrap();
+        });
+}
+
+fn greet(input: Input) -> NonEmptyValue {
+    let mut out = b"Hello ".to_vec();
+    out.extend_from_slice(&input.input);
+    NonEmptyValue::Success(out.into())
+}
+
+#[test]
+fn run_simple() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"Francesco"))
+        .run(|vm| vm.run_simple(greet).unwrap());
+
+    assert_eq!(
+        output.next_decoded::<OutputEntryMessage>().unwrap(),
+        OutputEntryMessage {
+            result: Some(output_entry_message::Result::Value(Bytes::from_static(
+                b"Hello Francesco"
+            ))),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn run_simple_on_replay() {
+    let mut output = VMTestCase::new()
+        .input(start_message(2))
+        .input(input_entry_message(b"Francesco"))
+        .input(OutputEntryMessage {
+            result: Some(output_entry_message::Result::Value(Bytes::from_static(
+                b"Hello Francesco",
+            ))),
+            ..Default::default()
+        })
+        .run(|vm| vm.run_simple(greet).unwrap());
+
+    // The output entry was replayed, so only End is written
+    assert_eq!(
+        output.next_decoded::<EndMessage>().unwrap(),
+        EndMessage::default()
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn max_total_output_bytes() {
+    let mut output = VMTestCase::with_options(VMOptions {
+        max_total_output_bytes: Some(100),
+        ..VMOptions::default()
+    })
+    .input(start_message(1))
+    .input(input_entry_message(b"my-data"))
+    .run(|vm| {
+        vm.sys_input().unwrap();
+
+        // Each set state entry is 8 bytes of header + 40 bytes of payload
+        let value = Bytes::from(vec![0; 32]);
+        vm.sys_state_set("key1".to_owned(), value.clone()).unwrap();
+        vm.sys_state_set("key2".to_owned(), value.clone()).unwrap();
+        let_assert!(Err(err) = vm.sys_state_set("key3".to_owned(), value));
+        assert_eq!(
+            err.message(),
+            vm::errors::OutputTooLargeError {
+                total: 144,
+                max: 100
+            }
+            .to_string()
+        );
+        assert_eq!(err.code_enum(), Some(error::ErrorCode::OutputTooLarge));
+        assert!(!err.is_retryable());
+    });
+
+    assert!(output
+        .next_decoded::<messages::SetStateEntryMessage>()
+        .is_some());
+    assert!(output
+        .next_decoded::<messages::SetStateEntryMessage>()
+        .is_some());
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(
+            vm::errors::OutputTooLargeError {
+                total: 144,
+                max: 100
+            }
+            .into()
+        )
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn consume_input_twice() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+            let_assert!(Err(err) = vm.sys_input());
+            assert_that!(err, eq_vm_error(vm::errors::INPUT_ALREADY_CONSUMED));
+            assert!(!err.is_retryable());
+        });
+
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(vm::errors::INPUT_ALREADY_CONSUMED)
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn input_attributes() {
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+    vm.notify_input(encoder.encode(&start_message(1)));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+
+    let input = vm.sys_input().unwrap();
+    assert!(input.attributes.is_empty());
+    assert_eq!(input.attribute("region"), None);
+
+    let input = Input {
+        attributes: vec![("region".to_owned(), "eu".to_owned())],
+        ..input
+    };
+    assert_eq!(input.attribute("region"), Some("eu"));
+    assert_eq!(input.attribute("priority"), None);
+}
+
+#[test]
+fn strict_output_check_rejects_second_output() {
+    let mut output = VMTestCase::with_options(VMOptions {
+        strict_output_check: true,
+        ..VMOptions::default()
+    })
+    .input(start_message(1))
+    .input(input_entry_message(b"my-data"))
+    .run(|vm| {
+        vm.sys_input().unwrap();
+
+        vm.sys_write_output(NonEmptyValue::Success(Bytes::from_static(b"first")))
+            .unwrap();
+        assert_that!(
+            vm.sys_write_output(NonEmptyValue::Success(Bytes::from_static(b"second"))),
+            err(eq_vm_error(vm::errors::OUTPUT_ALREADY_WRITTEN))
+        );
+    });
+
+    assert_eq!(
+        output.next_decoded::<OutputEntryMessage>().unwrap(),
+        OutputEntryMessage {
+            result: Some(output_entry_message::Result::Value(Bytes::from_static(
+                b"first"
+            ))),
+            ..OutputEntryMessage::default()
+        }
+    );
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(vm::errors::OUTPUT_ALREADY_WRITTEN)
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn strict_output_check_ignores_replayed_output() {
+    let mut output = VMTestCase::with_options(VMOptions {
+        strict_output_check: true,
+        ..VMOptions::default()
+    })
+    .input(start_message(2))
+    .input(input_entry_message(b"my-data"))
+    .input(OutputEntryMessage {
+        result: Some(output_entry_message::Result::Value(Bytes::from_static(
+            b"first",
+        ))),
+        ..OutputEntryMessage::default()
+    })
+    .run(|vm| {
+        vm.sys_input().unwrap();
+
+        // The replayed output entry is not checked, but it counts as written output
+        vm.sys_write_output(NonEmptyValue::Success(Bytes::from_static(b"first")))
+            .unwrap();
+        assert_that!(
+            vm.sys_write_output(NonEmptyValue::Success(Bytes::from_static(b"second"))),
+            err(eq_vm_error(vm::errors::OUTPUT_ALREADY_WRITTEN))
+        );
+    });
+
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(vm::errors::OUTPUT_ALREADY_WRITTEN)
+    );
+    assert_eq!(output.next(), None);
+}
@@ -0,0 +1,145 @@
+use super::*;
+
+use crate::service_protocol::messages::*;
+use test_log::test;
+
+struct JournalBuilder {
+    encoder: Encoder,
+    decoder: Decoder,
+}
+
+impl JournalBuilder {
+    fn new(version: Version) -> Self {
+        Self {
+            encoder: Encoder::new(version),
+            decoder: Decoder::new(version, DEFAULT_MAX_MESSAGE_SIZE),
+        }
+    }
+
+    fn add<M: WriteableRestateMessage>(mut self, msg: M) -> Self {
+        self.decoder.push(self.encoder.encode(&msg));
+        self
+    }
+
+    fn build(mut self) -> Vec<RawMessage> {
+        self.decoder.drain().map(Result::unwrap).collect()
+    }
+}
+
+fn get_state(key: &'static str) -> GetStateEntryMessage {
+    GetStateEntryMessage {
+        key: Bytes::from_static(key.as_bytes()),
+        ..Default::default()
+    }
+}
+
+fn completion(entry_index: u32) -> CompletionMessage {
+    CompletionMessage {
+        entry_index,
+        result: Some(completion_message::Result::Empty(Empty::default())),
+    }
+}
+
+#[test]
+fn valid_journal() {
+    let journal = JournalBuilder::new(Version::maximum_experimental_version())
+        .add(input_entry_message(b"my-data"))
+        .add(get_state("key-1"))
+        .add(SetStateEntryMessage {
+            key: Bytes::from_static(b"key-2"),
+            value: Bytes::from_static(b"value"),
+            ..Default::default()
+        })
+        .add(get_state("key-2"))
+        .add(completion(3))
+        .add(completion(1))
+        .build();
+
+    assert!(validate_journal(Version::maximum_experimental_version(), &journal).is_ok());
+}
+
+#[test]
+fn first_entry_is_not_input() {
+    let journal = JournalBuilder::new(Version::maximum_experimental_version())
+        .add(get_state("key"))
+        .build();
+
+    assert_that!(
+        validate_journal(Version::maximum_experimental_version(), &journal),
+        err(eq_vm_error(
+            vm::errors::InvalidJournalError::new(
+                0,
+                MessageType::GetStateEntry,
+                "the first entry must be the input"
+            )
+            .into()
+        ))
+    );
+}
+
+#[test]
+fn completion_of_non_completable_entry() {
+    let journal = JournalBuilder::new(Version::maximum_experimental_version())
+        .add(input_entry_message(b"my-data"))
+        .add(ClearAllStateEntryMessage::default())
+        .add(completion(1))
+        .build();
+
+    assert_that!(
+        validate_journal(Version::maximum_experimental_version(), &journal),
+        err(eq_vm_error(
+            vm::errors::InvalidJournalError::new(
+                2,
+                MessageType::Completion,
+                "entry 1 doesn't exist or is not completable"
+            )
+            .into()
+        ))
+    );
+}
+
+#[test]
+fn entry_completed_twice() {
+    let journal = JournalBuilder::new(Version::maximum_experimental_version())
+        .add(input_entry_message(b"my-data"))
+        .add(GetStateEntryMessage {
+            result: Some(get_state_entry_message::Result::Empty(Empty::default())),
+            ..get_state("key")
+        })
+        .add(completion(1))
+        .build();
+
+    assert_that!(
+        validate_journal(Version::maximum_experimental_version(), &journal),
+        err(eq_vm_error(
+            vm::errors::InvalidJournalError::new(
+                2,
+                MessageType::Completion,
+                "entry 1 was already completed"
+            )
+            .into()
+        ))
+    );
+}
+
+#[test]
+fn message_type_unsupported_by_version() {
+    let journal = JournalBuilder::new(Version::V4)
+        .add(input_entry_message(b"my-data"))
+        .add(ClearStatePrefixEntryMessage {
+            prefix: Bytes::from_static(b"my-"),
+            ..Default::default()
+        })
+        .build();
+
+    assert_that!(
+        validate_journal(Version::V3, &journal),
+        err(eq_vm_error(
+            vm::errors::UnsupportedMessageTypeForNegotiatedVersion::new(
+                MessageType::ClearStatePrefixEntry,
+                Version::V3
+            )
+            .into()
+        ))
+    );
+}
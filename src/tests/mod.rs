@@ -3,6 +3,7 @@ mod calls;
 mod failures;
 mod get_state;
 mod input_output;
+mod journal_validation;
 mod promise;
 mod run;
 mod sleep;
@@ -16,7 +17,8 @@ use crate::service_protocol::messages::{
     RestateMessage, RunEntryMessage, StartMessage, SuspensionMessage, WriteableRestateMessage,
 };
 use crate::service_protocol::{messages, Decoder, Encoder, RawMessage, Version};
-use bytes::Bytes;
+use assert2::let_assert;
+use bytes::{Buf, Bytes, BytesMut};
 use googletest::prelude::*;
 use std::result::Result;
 use test_log::test;
@@ -25,9 +27,16 @@ use test_log::test;
 
 impl CoreVM {
     fn mock_init(version: Version) -> CoreVM {
+        Self::mock_init_with_options(version, VMOptions::default())
+    }
+
+    fn mock_init_with_options(version: Version, options: VMOptions) -> CoreVM {
         let vm = CoreVM::new(
             vec![("content-type".to_owned(), version.to_string())],
-            VMOptions::default(),
+            VMOptions {
+                enable_experimental_protocol: true,
+                ..options
+            },
         )
         .unwrap();
 
@@ -50,9 +59,20 @@ struct VMTestCase {
 
 impl VMTestCase {
     fn new() -> Self {
+        Self::with_version(Version::maximum_experimental_version())
+    }
+
+    fn with_version(version: Version) -> Self {
         Self {
-            encoder: Encoder::new(Version::maximum_supported_version()),
-            vm: CoreVM::mock_init(Version::maximum_supported_version()),
+            encoder: Encoder::new(version),
+            vm: CoreVM::mock_init(version),
+        }
+    }
+
+    fn with_options(options: VMOptions) -> Self {
+        Self {
+            encoder: Encoder::new(Version::maximum_experimental_version()),
+            vm: CoreVM::mock_init_with_options(Version::maximum_experimental_version(), options),
         }
     }
 
@@ -86,7 +106,10 @@ struct OutputIterator(Decoder);
 
 impl OutputIterator {
     fn collect_vm(vm: &mut impl VM) -> Self {
-        let mut decoder = Decoder::new(Version::maximum_supported_version());
+        let mut decoder = Decoder::new(
+            Version::maximum_experimental_version(),
+            DEFAULT_MAX_MESSAGE_SIZE,
+        );
         while let TakeOutputResult::Buffer(b) = vm.take_output() {
             decoder.push(b);
         }
@@ -209,16 +232,787 @@ pub fn input_entry_message(b: impl AsRef<[u8]>) -> InputEntryMessage {
     }
 }
 
+#[test]
+fn notify_input_buf_with_message_split_across_calls() {
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+
+    let mut input = BytesMut::new();
+    encoder.encode_into(&start_message(1), &mut input);
+    encoder.encode_into(&input_entry_message(b"my-data"), &mut input);
+    let second_half = input.split_off(input.len() - 5);
+    let (first, second) = input.split_at(input.len() / 2);
+
+    // The first call provides a chained buffer, the second one a BytesMut
+    vm.notify_input_buf(Bytes::copy_from_slice(first).chain(Bytes::copy_from_slice(second)));
+    assert!(!vm.is_ready_to_execute().unwrap());
+    vm.notify_input_buf(second_half);
+    vm.notify_input_closed();
+    assert!(vm.is_ready_to_execute().unwrap());
+
+    let_assert!(Input { input, .. } = vm.sys_input().unwrap());
+    assert_eq!(input, Bytes::from_static(b"my-data"));
+}
+
 #[test]
 fn take_output_on_newly_initialized_vm() {
-    let mut vm = CoreVM::mock_init(Version::maximum_supported_version());
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
     assert_that!(
         vm.take_output(),
         eq(TakeOutputResult::Buffer(Bytes::default()))
     );
 }
 
+#[test]
+fn take_output_large_buffer() {
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+    vm.notify_input(encoder.encode(&start_message(1)));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+    vm.notify_input_closed();
+    vm.sys_input().unwrap();
+    let _ = vm.take_output();
+
+    let value = Bytes::from(vec![7u8; 4 * 1024 * 1024]);
+    vm.sys_write_output(NonEmptyValue::Success(value.clone()))
+        .unwrap();
+
+    let_assert!(TakeOutputResult::Buffer(buffer) = vm.take_output());
+    let mut decoder = Decoder::new(
+        Version::maximum_experimental_version(),
+        DEFAULT_MAX_MESSAGE_SIZE,
+    );
+    decoder.push(buffer);
+    let msg = decoder.consume_next().unwrap().unwrap();
+    assert_that!(
+        msg.decode_to::<OutputEntryMessage>().unwrap(),
+        pat!(OutputEntryMessage {
+            result: some(eq(output_entry_message::Result::Value(value)))
+        })
+    );
+    assert_eq!(vm.output_buffer_len(), 0);
+
+    // Still open, nothing left to drain
+    assert_eq!(vm.take_output(), TakeOutputResult::Buffer(Bytes::default()));
+
+    vm.sys_end().unwrap();
+    let_assert!(TakeOutputResult::Buffer(_) = vm.take_output());
+    assert_eq!(vm.take_output(), TakeOutputResult::EOF);
+}
+
+#[test]
+fn output_buffer_high_water_mark() {
+    let mut vm = CoreVM::mock_init_with_options(
+        Version::maximum_experimental_version(),
+        VMOptions {
+            max_output_buffer_bytes: Some(64),
+            ..VMOptions::default()
+        },
+    );
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+    vm.notify_input(encoder.encode(&start_message(1)));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+    vm.notify_input_closed();
+    vm.sys_input().unwrap();
+    assert_eq!(vm.output_buffer_len(), 0);
+    assert!(!vm.is_output_buffer_full());
+
+    vm.sys_state_set("STATE".to_owned(), Bytes::from(vec![0; 64]))
+        .unwrap();
+    assert!(vm.output_buffer_len() > 64);
+    assert!(vm.is_output_buffer_full());
+
+    // Draining the output resets the condition
+    let_assert!(TakeOutputResult::Buffer(_) = vm.take_output());
+    assert_eq!(vm.output_buffer_len(), 0);
+    assert!(!vm.is_output_buffer_full());
+}
+
+#[test]
+fn flush_hint() {
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+    vm.notify_input(encoder.encode(&start_message(1)));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+    vm.notify_input_closed();
+    vm.sys_input().unwrap();
+    assert!(!vm.flush_hint());
+
+    // State mutations can be batched
+    vm.sys_state_set("STATE".to_owned(), Bytes::from_static(b"value"))
+        .unwrap();
+    assert!(!vm.flush_hint());
+
+    // The runtime needs to see the call to complete it
+    vm.sys_call(
+        Target {
+            service: "greeter".to_owned(),
+            handler: "greet".to_owned(),
+            key: None,
+            idempotency_key: None,
+            headers: vec![],
+        },
+        Bytes::from_static(b"Francesco"),
+    )
+    .unwrap();
+    assert!(vm.flush_hint());
+
+    let_assert!(TakeOutputResult::Buffer(_) = vm.take_output());
+    assert!(!vm.flush_hint());
+
+    vm.sys_end().unwrap();
+    assert!(vm.flush_hint());
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn instantiate_core_vm_from_http_header_map() {
+    let content_type = Version::maximum_supported_version().content_type();
+    let mut headers = http::HeaderMap::new();
+    headers.insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static(content_type),
+    );
+
+    assert_eq!(headers.extract("Content-Type").unwrap(), Some(content_type));
+
+    let vm = CoreVM::new(&headers, VMOptions::default()).unwrap();
+    assert_eq!(
+        vm.negotiated_version(),
+        Version::maximum_supported_version()
+    );
+}
+
+#[test]
+fn extra_response_headers() {
+    let vm = CoreVM::mock_init_with_options(
+        Version::maximum_experimental_version(),
+        VMOptions {
+            extra_response_headers: vec![
+                Header {
+                    key: Cow::Borrowed("x-restate-server"),
+                    value: Cow::Borrowed("my-sdk/1.0"),
+                },
+                Header {
+                    key: Cow::Borrowed("Content-Type"),
+                    value: Cow::Borrowed("application/json"),
+                },
+                Header {
+                    key: Cow::Owned("traceparent".to_owned()),
+                    value: Cow::Owned("00-abc-def-01".to_owned()),
+                },
+            ],
+            ..VMOptions::default()
+        },
+    );
+
+    let response_head = vm.get_response_head();
+    assert_eq!(response_head.status_code, 200);
+    assert_eq!(
+        response_head.headers,
+        vec![
+            Header {
+                key: Cow::Borrowed("content-type"),
+                value: Cow::Borrowed(Version::maximum_experimental_version().content_type()),
+            },
+            Header {
+                key: Cow::Borrowed("x-restate-server"),
+                value: Cow::Borrowed("my-sdk/1.0"),
+            },
+            Header {
+                key: Cow::Borrowed("traceparent"),
+                value: Cow::Borrowed("00-abc-def-01"),
+            },
+        ]
+    );
+}
+
+#[test]
+fn output_checksum() {
+    let output = VMTestCase::with_options(VMOptions {
+        output_checksum: true,
+        ..VMOptions::default()
+    })
+    .input(start_message(1))
+    .input(input_entry_message(b"my-data"))
+    .run(|vm| {
+        vm.sys_input().unwrap();
+        vm.sys_write_output(NonEmptyValue::Success(Bytes::from_static(b"Till")))
+            .unwrap();
+        vm.sys_end().unwrap();
+    });
+
+    // The decoder validates the checksum of each frame
+    let messages: Vec<_> = output.collect();
+    assert!(messages.iter().all(|msg| msg.header().has_checksum()));
+    assert_eq!(
+        messages.into_iter().map(|msg| msg.ty()).collect::<Vec<_>>(),
+        vec![MessageType::OutputEntry, MessageType::End]
+    );
+}
+
+#[test]
+fn instantiate_core_vm_with_capitalized_content_type() {
+    let content_type = Version::maximum_supported_version().content_type();
+    let headers = vec![("Content-Type".to_owned(), content_type.to_owned())];
+
+    let vm = CoreVM::new(headers.as_slice(), VMOptions::default()).unwrap();
+    assert_eq!(
+        vm.negotiated_version(),
+        Version::maximum_supported_version()
+    );
+}
+
 #[test]
 fn instantiate_core_vm_minimum_supported_version() {
     CoreVM::mock_init(Version::minimum_supported_version());
 }
+
+#[test]
+fn negotiated_version_and_supported_features() {
+    let vm = CoreVM::mock_init(Version::V2);
+    assert_eq!(vm.negotiated_version(), Version::V2);
+    assert!(!vm.supports(ProtocolFeature::CancelInvocation));
+    assert!(!vm.supports(ProtocolFeature::IdempotencyKeyOnCall));
+
+    let vm = CoreVM::mock_init(Version::V3);
+    assert_eq!(vm.negotiated_version(), Version::V3);
+    assert!(vm.supports(ProtocolFeature::CancelInvocation));
+    assert!(vm.supports(ProtocolFeature::IdempotencyKeyOnCall));
+    assert!(!vm.supports(ProtocolFeature::ClearStatePrefix));
+
+    let vm = CoreVM::mock_init(Version::V4);
+    assert!(vm.supports(ProtocolFeature::ClearStatePrefix));
+}
+
+#[test]
+fn supported_message_types() {
+    let vm = CoreVM::mock_init(Version::V2);
+    assert!(vm
+        .supported_message_types()
+        .contains(&MessageType::CallEntry));
+    assert!(!vm
+        .supported_message_types()
+        .contains(&MessageType::CancelInvocationEntry));
+
+    let vm = CoreVM::mock_init(Version::V3);
+    assert!(vm
+        .supported_message_types()
+        .contains(&MessageType::CancelInvocationEntry));
+    assert!(!vm
+        .supported_message_types()
+        .contains(&MessageType::ClearStatePrefixEntry));
+
+    let vm = CoreVM::mock_init(Version::V4);
+    assert!(vm
+        .supported_message_types()
+        .contains(&MessageType::ClearStatePrefixEntry));
+    assert!(vm
+        .supported_message_types()
+        .iter()
+        .all(|ty| ty.minimum_required_version() <= Version::V4));
+}
+
+#[test]
+fn command_type_display_name() {
+    assert_eq!(CommandType::GetState.display_name(), "get state");
+    assert_eq!(CommandType::GetStateKeys.display_name(), "get state keys");
+    assert_eq!(CommandType::Sleep.display_name(), "sleep");
+    assert_eq!(CommandType::Call.display_name(), "call");
+    assert_eq!(CommandType::OneWayCall.display_name(), "one way call");
+    assert_eq!(
+        CommandType::ClearStatePrefix.display_name(),
+        "clear state by prefix"
+    );
+    assert_eq!(
+        CommandType::GetCallInvocationId.display_name(),
+        "get call invocation id"
+    );
+    assert_eq!(
+        ProtocolFeature::CancelInvocation.to_string(),
+        "cancel invocation"
+    );
+    assert_eq!(
+        ProtocolFeature::ClearStatePrefix.to_string(),
+        "clear state by prefix"
+    );
+}
+
+#[test]
+fn terminal_failure_constructors() {
+    assert_eq!(
+        TerminalFailure::bad_request("Invalid name"),
+        TerminalFailure::new(400, "Invalid name")
+    );
+    assert_eq!(
+        TerminalFailure::not_found("No such user"),
+        TerminalFailure::new(404, "No such user")
+    );
+    assert_eq!(
+        TerminalFailure::cancelled(),
+        TerminalFailure::new(409, "Cancelled")
+    );
+
+    let failure = TerminalFailure::not_found("No such user");
+    assert_eq!(
+        TerminalFailure::from(messages::Failure::from(failure.clone())),
+        failure
+    );
+}
+
+#[test]
+fn value_accessors() {
+    assert!(Value::Void.is_void());
+    assert!(!Value::Success(Bytes::new()).is_void());
+
+    assert_eq!(
+        Value::Success(Bytes::from_static(b"Till")).into_success(),
+        Some(Bytes::from_static(b"Till"))
+    );
+    assert_eq!(Value::Void.into_success(), None);
+
+    assert_eq!(
+        Value::Failure(TerminalFailure::not_found("No such user")).into_failure(),
+        Some(TerminalFailure::not_found("No such user"))
+    );
+    assert_eq!(Value::Success(Bytes::new()).into_failure(), None);
+
+    assert_eq!(
+        Value::StateKeys(vec!["a".to_owned(), "b".to_owned()]).as_state_keys(),
+        Some(&["a".to_owned(), "b".to_owned()][..])
+    );
+    assert_eq!(Value::Void.as_state_keys(), None);
+}
+
+#[test]
+fn value_try_into_bytes() {
+    assert_eq!(
+        Bytes::try_from(Value::Success(Bytes::from_static(b"Till"))).unwrap(),
+        Bytes::from_static(b"Till")
+    );
+
+    let err =
+        Bytes::try_from(Value::Failure(TerminalFailure::not_found("No such user"))).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Expected a success value, got failure [404]: No such user"
+    );
+    assert_eq!(
+        err.0,
+        Value::Failure(TerminalFailure::not_found("No such user"))
+    );
+    assert_eq!(
+        Bytes::try_from(Value::Void).unwrap_err().to_string(),
+        "Expected a success value, got void"
+    );
+}
+
+#[cfg(feature = "trace_events")]
+#[test]
+fn transition_trace() {
+    let mut vm = CoreVM::mock_init_with_options(
+        Version::maximum_experimental_version(),
+        VMOptions {
+            transition_trace_capacity: 4,
+            ..VMOptions::default()
+        },
+    );
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+    vm.notify_input(encoder.encode(&start_message(1)));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+    vm.notify_input_closed();
+    vm.sys_input().unwrap();
+    vm.sys_end().unwrap();
+
+    let trace: Vec<_> = vm
+        .transition_trace()
+        .into_iter()
+        .map(|t| (t.event, t.from_state, t.to_state, t.command_index))
+        .collect();
+    // The first transition, WaitingStart -> WaitingReplayEntries, was discarded because of the capacity
+    assert_eq!(
+        trace,
+        vec![
+            ("NewMessage", "WaitingReplayEntries", "Replaying", -1),
+            ("NotifyInputClosed", "Replaying", "Replaying", -1),
+            ("SysInput", "Replaying", "Processing", 0),
+            ("SysEnd", "Processing", "Ended", 0),
+        ]
+    );
+}
+
+#[cfg(feature = "vm_stats")]
+#[test]
+fn metrics() {
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+    vm.notify_input(encoder.encode(&start_message(2)));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+    vm.notify_input(encoder.encode(&messages::SetStateEntryMessage {
+        key: Bytes::from_static(b"STATE"),
+        value: Bytes::from_static(b"Till"),
+        ..Default::default()
+    }));
+    vm.notify_input_closed();
+
+    vm.sys_input().unwrap();
+    vm.sys_state_set("STATE".to_owned(), Bytes::from_static(b"Till"))
+        .unwrap();
+    vm.sys_state_set("STATE".to_owned(), Bytes::from_static(b"Francesco"))
+        .unwrap();
+    let h = vm.sys_sleep(Duration::from_secs(1), None).unwrap();
+    vm.notify_await_point(h);
+    assert_that!(vm.take_async_result(h), err(is_suspended()));
+
+    assert_eq!(
+        vm.metrics(),
+        InvocationMetrics {
+            commands: [
+                (CommandType::Input, 1),
+                (CommandType::SetState, 2),
+                (CommandType::Sleep, 1)
+            ]
+            .into_iter()
+            .collect(),
+            suspensions: 1,
+            journal_mismatches: 0,
+        }
+    );
+}
+
+#[cfg(feature = "vm_stats")]
+#[test]
+fn metrics_journal_mismatch() {
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+    vm.notify_input(encoder.encode(&start_message(2)));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+    vm.notify_input(encoder.encode(&messages::SetStateEntryMessage {
+        key: Bytes::from_static(b"STATE"),
+        value: Bytes::from_static(b"Till"),
+        ..Default::default()
+    }));
+    vm.notify_input_closed();
+
+    vm.sys_input().unwrap();
+    assert!(vm.sys_state_clear("STATE".to_owned()).is_err());
+
+    let metrics = vm.metrics();
+    assert_eq!(metrics.journal_mismatches, 1);
+    assert_eq!(metrics.suspensions, 0);
+}
+
+#[test]
+fn awakeable_id_roundtrip() {
+    let id = encode_awakeable_id(b"my-invocation-id", 3);
+    assert!(id.starts_with("prom_1"));
+    assert_eq!(
+        decode_awakeable_id(&id),
+        Some((b"my-invocation-id".to_vec(), 3))
+    );
+
+    assert_eq!(decode_awakeable_id("sign_1AAAAAQ"), None);
+    assert_eq!(decode_awakeable_id("prom_1!!!"), None);
+    // Too short to contain the entry index
+    assert_eq!(decode_awakeable_id("prom_1AAA"), None);
+}
+
+#[test]
+fn decode_awakeable_id_from_sys_awakeable() {
+    VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+            let (id, handle) = vm.sys_awakeable().unwrap();
+            assert_eq!(
+                decode_awakeable_id(&id),
+                Some((b"123".to_vec(), u32::from(handle)))
+            );
+            vm.sys_end().unwrap();
+        });
+}
+
+fn run_echo_invocation(vm: &mut CoreVM, version: Version, input: &'static [u8]) -> Vec<Bytes> {
+    let encoder = Encoder::new(version);
+    vm.notify_input(encoder.encode(&start_message(1)));
+    vm.notify_input(encoder.encode(&input_entry_message(input)));
+    vm.notify_input_closed();
+
+    let input = vm.sys_input().unwrap().input;
+    vm.sys_state_set("last".to_owned(), input.clone()).unwrap();
+    vm.sys_write_output(NonEmptyValue::Success(input)).unwrap();
+    vm.sys_end().unwrap();
+
+    let mut output = vec![];
+    while let TakeOutputResult::Buffer(b) = vm.take_output() {
+        output.push(b);
+    }
+    output
+}
+
+#[test]
+fn reset_behaves_like_new() {
+    let mut reused = CoreVM::mock_init(Version::V3);
+    let first = run_echo_invocation(&mut reused, Version::V3, b"Till");
+
+    reused
+        .reset(
+            vec![("content-type".to_owned(), Version::V4.to_string())],
+            VMOptions {
+                enable_experimental_protocol: true,
+                ..VMOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(reused.get_response_head().version, Version::V4);
+    let second = run_echo_invocation(&mut reused, Version::V4, b"Francesco");
+
+    assert_eq!(
+        first,
+        run_echo_invocation(&mut CoreVM::mock_init(Version::V3), Version::V3, b"Till")
+    );
+    assert_eq!(
+        second,
+        run_echo_invocation(
+            &mut CoreVM::mock_init(Version::V4),
+            Version::V4,
+            b"Francesco"
+        )
+    );
+}
+
+#[test]
+fn reset_with_unsupported_version_keeps_vm() {
+    let mut vm = CoreVM::mock_init(Version::V3);
+
+    assert!(vm
+        .reset(
+            vec![(
+                "content-type".to_owned(),
+                "application/vnd.restate.invocation.v99".to_owned()
+            )],
+            VMOptions::default(),
+        )
+        .is_err());
+    assert_eq!(vm.get_response_head().version, Version::V3);
+}
+
+#[test]
+fn content_type_negotiation() {
+    fn negotiate_with_options(content_type: &str, options: VMOptions) -> Result<Version, Error> {
+        CoreVM::new(
+            vec![("content-type".to_owned(), content_type.to_owned())],
+            options,
+        )
+        .map(|vm| vm.get_response_head().version)
+    }
+    fn negotiate(content_type: &str) -> Result<Version, Error> {
+        negotiate_with_options(content_type, VMOptions::default())
+    }
+
+    assert_eq!(
+        negotiate("application/vnd.restate.invocation.v3").unwrap(),
+        Version::V3
+    );
+    // The experimental version requires opting in
+    assert_eq!(
+        negotiate("application/vnd.restate.invocation.v4-experimental")
+            .unwrap_err()
+            .code_enum(),
+        Some(error::ErrorCode::UnsupportedMediaType)
+    );
+    // The upstream v4 is never negotiated as the experimental version
+    for options in [
+        VMOptions::default(),
+        VMOptions {
+            enable_experimental_protocol: true,
+            ..VMOptions::default()
+        },
+    ] {
+        assert_eq!(
+            negotiate_with_options("application/vnd.restate.invocation.v4", options)
+                .unwrap_err()
+                .code_enum(),
+            Some(error::ErrorCode::UnsupportedMediaType)
+        );
+    }
+    assert_eq!(
+        negotiate_with_options(
+            "application/vnd.restate.invocation.v4-experimental",
+            VMOptions {
+                enable_experimental_protocol: true,
+                ..VMOptions::default()
+            }
+        )
+        .unwrap(),
+        Version::V4
+    );
+    assert_eq!(
+        negotiate("application/vnd.restate.invocation.v0")
+            .unwrap_err()
+            .code_enum(),
+        Some(error::ErrorCode::UnsupportedMediaType)
+    );
+    for unsupported in [
+        "application/vnd.restate.invocation.v0+proto",
+        "application/vnd.restate.invocation.v",
+        "application/json",
+        "application/json; charset=utf-8",
+    ] {
+        assert_eq!(
+            negotiate(unsupported).unwrap_err().code_enum(),
+            Some(error::ErrorCode::UnsupportedMediaType),
+            "{unsupported}"
+        );
+    }
+    for malformed in ["garbage", "", "application/", "/json", "application/js on"] {
+        let err = negotiate(malformed).unwrap_err();
+        assert_eq!(
+            err.code_enum(),
+            Some(error::ErrorCode::BadRequest),
+            "{malformed}"
+        );
+        assert!(!err.is_retryable());
+    }
+}
+
+#[test]
+fn version_try_from_i32() {
+    assert_eq!(Version::try_from(1).unwrap(), Version::V1);
+    assert_eq!(Version::try_from(4).unwrap(), Version::V4);
+    // Unspecified
+    assert!(Version::try_from(0).is_err());
+    assert!(Version::try_from(5).is_err());
+    assert!(Version::try_from(-1).is_err());
+}
+
+#[test]
+fn supported_content_types() {
+    assert_eq!(
+        Version::all_supported().collect::<Vec<_>>(),
+        vec![Version::V2, Version::V3]
+    );
+    assert_eq!(
+        Version::content_types(),
+        vec![
+            "application/vnd.restate.invocation.v2",
+            "application/vnd.restate.invocation.v3"
+        ]
+    );
+}
+
+#[test]
+fn negotiate_accept() {
+    assert_eq!(
+        Version::negotiate(
+            "application/vnd.restate.invocation.v2;q=0.5,application/vnd.restate.invocation.v3"
+        ),
+        Some(Version::V3)
+    );
+    // Unsupported, experimental and unknown versions are skipped
+    assert_eq!(
+        Version::negotiate(
+            "application/vnd.restate.invocation.v1, application/vnd.restate.invocation.v4, application/vnd.restate.invocation.v99, application/vnd.restate.invocation.v3"
+        ),
+        Some(Version::V3)
+    );
+    assert_eq!(
+        Version::negotiate("application/vnd.restate.invocation.v1, application/json"),
+        None
+    );
+    assert_eq!(Version::negotiate(""), None);
+}
+
+#[test]
+fn negotiate_accept_skips_not_acceptable() {
+    assert_eq!(
+        Version::negotiate(
+            "application/vnd.restate.invocation.v2, application/vnd.restate.invocation.v3;q=0"
+        ),
+        Some(Version::V2)
+    );
+    assert_eq!(
+        Version::negotiate(
+            "application/vnd.restate.invocation.v2, application/vnd.restate.invocation.v3; Q=0.000"
+        ),
+        Some(Version::V2)
+    );
+    assert_eq!(
+        Version::negotiate(
+            "application/vnd.restate.invocation.v2;q=0.0,application/vnd.restate.invocation.v3;q=0"
+        ),
+        None
+    );
+    assert_eq!(
+        Version::negotiate(
+            "application/vnd.restate.invocation.v2, application/vnd.restate.invocation.v3;q=0.001"
+        ),
+        Some(Version::V3)
+    );
+}
+
+#[test]
+fn cloned_vm_progresses_independently() {
+    fn take_all_output(vm: &mut CoreVM) -> Vec<Bytes> {
+        let mut output = vec![];
+        while let TakeOutputResult::Buffer(b) = vm.take_output() {
+            output.push(b);
+        }
+        output
+    }
+
+    fn resume(vm: &mut CoreVM, encoder: &Encoder, handle: AsyncResultHandle) {
+        vm.notify_input(encoder.encode(&messages::CompletionMessage {
+            entry_index: 2,
+            result: Some(messages::completion_message::Result::Value(
+                Bytes::from_static(b"Francesco"),
+            )),
+        }));
+        vm.notify_input_closed();
+        vm.notify_await_point(handle);
+        let_assert!(Some(Value::Success(s)) = vm.take_async_result(handle).unwrap());
+        vm.sys_write_output(NonEmptyValue::Success(s)).unwrap();
+        vm.sys_end().unwrap();
+    }
+
+    let encoder = Encoder::new(Version::maximum_experimental_version());
+    let mut vm = CoreVM::mock_init(Version::maximum_experimental_version());
+    vm.notify_input(encoder.encode(&start_message(1)));
+    vm.notify_input(encoder.encode(&input_entry_message(b"my-data")));
+
+    vm.sys_input().unwrap();
+    vm.sys_state_set("STATE".to_owned(), Bytes::from_static(b"Till"))
+        .unwrap();
+    let handle = vm.sys_state_get("OTHER".to_owned()).unwrap();
+
+    // Driving a clone down another path doesn't affect the original
+    let mut speculative = vm.clone();
+    speculative.sys_state_clear("STATE".to_owned()).unwrap();
+    drop(speculative);
+
+    let mut cloned = vm.clone();
+    resume(&mut vm, &encoder, handle);
+    resume(&mut cloned, &encoder, handle);
+
+    let output = take_all_output(&mut vm);
+    assert_eq!(output, take_all_output(&mut cloned));
+
+    let mut decoder = Decoder::new(
+        Version::maximum_experimental_version(),
+        DEFAULT_MAX_MESSAGE_SIZE,
+    );
+    for b in output {
+        decoder.push(b);
+    }
+    let tys: Vec<_> = decoder.drain().map(|msg| msg.unwrap().ty()).collect();
+    assert_eq!(
+        tys,
+        vec![
+            MessageType::SetStateEntry,
+            MessageType::GetStateEntry,
+            MessageType::OutputEntry,
+            MessageType::End
+        ]
+    );
+}
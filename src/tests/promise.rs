@@ -498,4 +498,268 @@ mod complete_promise {
 
         assert_eq!(output.next(), None);
     }
+    #[test]
+    fn resolve_promise_with_idempotency_key() {
+        let mut output = VMTestCase::new()
+            .input(start_message(1))
+            .input(input_entry_message(b"my-data"))
+            .run(|vm| {
+                vm.sys_input().unwrap();
+                vm.sys_complete_promise_with_idempotency_key(
+                    "my-prom".to_owned(),
+                    NonEmptyValue::Success(Bytes::from_static(b"my val")),
+                    "my-idempotency-key".to_owned(),
+                )
+                .unwrap();
+                vm.sys_end().unwrap();
+            });
+
+        assert_eq!(
+            output
+                .next_decoded::<CompletePromiseEntryMessage>()
+                .unwrap(),
+            CompletePromiseEntryMessage {
+                key: "my-prom".to_owned(),
+                idempotency_key: Some("my-idempotency-key".to_owned()),
+                completion: Some(complete_promise_entry_message::Completion::CompletionValue(
+                    Bytes::from_static(b"my val")
+                )),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn resolve_promise_with_idempotency_key_unsupported_on_v3() {
+        let mut output = VMTestCase::with_version(Version::V3)
+            .input(start_message(1))
+            .input(input_entry_message(b"my-data"))
+            .run(|vm| {
+                vm.sys_input().unwrap();
+                assert_that!(
+                    vm.sys_complete_promise_with_idempotency_key(
+                        "my-prom".to_owned(),
+                        NonEmptyValue::Success(Bytes::from_static(b"my val")),
+                        "my-idempotency-key".to_owned(),
+                    ),
+                    err(eq_vm_error(
+                        vm::errors::UnsupportedFeatureForNegotiatedVersion::new(
+                            ProtocolFeature::IdempotencyKeyOnCompletion,
+                            Version::V3
+                        )
+                        .into()
+                    ))
+                );
+            });
+
+        assert_that!(
+            output.next_decoded::<ErrorMessage>().unwrap(),
+            error_message_as_vm_error(
+                vm::errors::UnsupportedFeatureForNegotiatedVersion::new(
+                    ProtocolFeature::IdempotencyKeyOnCompletion,
+                    Version::V3
+                )
+                .into()
+            )
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn replay_resolve_promise_with_idempotency_key() {
+        let mut output = VMTestCase::new()
+            .input(start_message(2))
+            .input(input_entry_message(b"my-data"))
+            .input(CompletePromiseEntryMessage {
+                key: "my-prom".to_owned(),
+                idempotency_key: Some("my-idempotency-key".to_owned()),
+                completion: Some(complete_promise_entry_message::Completion::CompletionValue(
+                    Bytes::from_static(b"my val"),
+                )),
+                result: Some(complete_promise_entry_message::Result::Empty(
+                    Empty::default(),
+                )),
+                ..Default::default()
+            })
+            .run(handler_with_idempotency_key);
+
+        assert_eq!(
+            output.next_decoded::<OutputEntryMessage>().unwrap(),
+            OutputEntryMessage {
+                result: Some(output_entry_message::Result::Value(RESOLVED)),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    fn handler_with_idempotency_key(vm: &mut CoreVM) {
+        vm.sys_input().unwrap();
+
+        let h1 = vm
+            .sys_complete_promise_with_idempotency_key(
+                "my-prom".to_owned(),
+                NonEmptyValue::Success(Bytes::from_static(b"my val")),
+                "my-idempotency-key".to_owned(),
+            )
+            .unwrap();
+        vm.notify_await_point(h1);
+        assert_that!(vm.take_async_result(h1), ok(some(eq(Value::Void))));
+
+        vm.sys_write_output(NonEmptyValue::Success(RESOLVED))
+            .unwrap();
+        vm.sys_end().unwrap();
+    }
+}
+
+mod complete_promise_if_unset {
+    use super::*;
+
+    use test_log::test;
+
+    fn handler(vm: &mut CoreVM) {
+        vm.sys_input().unwrap();
+
+        let h1 = vm
+            .sys_complete_promise_if_unset(
+                "my-prom".to_owned(),
+                NonEmptyValue::Success(Bytes::from_static(b"\"my value\"")),
+            )
+            .unwrap();
+        vm.notify_await_point(h1);
+        let h1_result = vm.take_async_result(h1);
+        if let Err(SuspendedOrVMError::Suspended(_)) = &h1_result {
+            return;
+        }
+
+        let output = match h1_result.unwrap().expect("Should be ready") {
+            Value::PromiseCompleted(true) => NonEmptyValue::Success("won".into()),
+            Value::PromiseCompleted(false) => NonEmptyValue::Success("lost".into()),
+            Value::Failure(f) => NonEmptyValue::Failure(f),
+            v => panic!("Unexpected value {v:?}"),
+        };
+
+        vm.sys_write_output(output).unwrap();
+        vm.sys_end().unwrap();
+    }
+
+    fn test_race(completion_value: &'static [u8], expected_output: &'static [u8]) {
+        let mut output = VMTestCase::new()
+            .input(start_message(1))
+            .input(InputEntryMessage::default())
+            .input(CompletionMessage {
+                entry_index: 1,
+                result: Some(completion_message::Result::Value(Bytes::from_static(
+                    completion_value,
+                ))),
+            })
+            .run(handler);
+
+        assert_eq!(
+            output
+                .next_decoded::<CompletePromiseIfUnsetEntryMessage>()
+                .unwrap(),
+            CompletePromiseIfUnsetEntryMessage {
+                key: "my-prom".to_owned(),
+                completion: Some(
+                    complete_promise_if_unset_entry_message::Completion::CompletionValue(
+                        Bytes::from_static(b"\"my value\"")
+                    )
+                ),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<OutputEntryMessage>().unwrap(),
+            OutputEntryMessage {
+                result: Some(output_entry_message::Result::Value(Bytes::from_static(
+                    expected_output
+                ))),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn won_race() {
+        test_race(&[1], b"won");
+    }
+
+    #[test]
+    fn lost_race() {
+        test_race(&[0], b"lost");
+    }
+
+    #[test]
+    fn replay_lost_race() {
+        let mut output = VMTestCase::new()
+            .input(start_message(2))
+            .input(InputEntryMessage::default())
+            .input(CompletePromiseIfUnsetEntryMessage {
+                key: "my-prom".to_owned(),
+                completion: Some(
+                    complete_promise_if_unset_entry_message::Completion::CompletionValue(
+                        Bytes::from_static(b"\"my value\""),
+                    ),
+                ),
+                result: Some(complete_promise_if_unset_entry_message::Result::Value(
+                    false,
+                )),
+                ..Default::default()
+            })
+            .run(handler);
+
+        assert_eq!(
+            output.next_decoded::<OutputEntryMessage>().unwrap(),
+            OutputEntryMessage {
+                result: Some(output_entry_message::Result::Value(Bytes::from_static(
+                    b"lost"
+                ))),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn unsupported_on_v3() {
+        let mut output = VMTestCase::with_version(Version::V3)
+            .input(start_message(1))
+            .input(InputEntryMessage::default())
+            .run(|vm| {
+                vm.sys_input().unwrap();
+                assert!(vm
+                    .sys_complete_promise_if_unset(
+                        "my-prom".to_owned(),
+                        NonEmptyValue::Success(Bytes::from_static(b"\"my value\"")),
+                    )
+                    .is_err());
+            });
+
+        assert_that!(
+            output.next_decoded::<ErrorMessage>().unwrap(),
+            pat!(ErrorMessage {
+                code: eq(u32::from(vm::errors::codes::UNSUPPORTED_FEATURE))
+            })
+        );
+        assert_eq!(output.next(), None);
+    }
 }
@@ -575,15 +575,17 @@ mod retry_policy {
                     RunEnterResult::NotExecuted { .. } =
                         vm.sys_run_enter("my-side-effect".to_owned()).unwrap()
                 );
-                assert!(vm
-                    .sys_run_exit(
+                let_assert!(
+                    Err(err) = vm.sys_run_exit(
                         RunExitResult::RetryableFailure {
                             error: Error::internal("my-error"),
                             attempt_duration
                         },
                         retry_policy
                     )
-                    .is_err());
+                );
+                assert!(err.is_retryable());
+                assert_eq!(vm.next_retry_delay(), next_retry_interval);
             });
 
         assert_that!(
@@ -608,6 +610,17 @@ mod retry_policy {
         );
     }
 
+    #[test]
+    fn exit_with_retryable_error_retry_policy_runtime_default() {
+        test_should_continue_retrying(
+            10,
+            Duration::from_secs(10),
+            Duration::from_secs(1),
+            RetryPolicy::RuntimeDefault,
+            None,
+        );
+    }
+
     #[test]
     fn exit_with_retryable_error_retry_policy_none() {
         test_should_stop_retrying(0, Duration::ZERO, Duration::ZERO, RetryPolicy::None)
@@ -709,3 +722,285 @@ mod retry_policy {
         assert_eq!(output.next(), None);
     }
 }
+
+#[test]
+fn exit_with_success_buf_journals_same_bytes() {
+    fn run_with(exit: impl FnOnce(&mut CoreVM) -> AsyncResultHandle) -> Vec<(MessageType, Bytes)> {
+        VMTestCase::new()
+            .input(start_message(1))
+            .input(input_entry_message(b"my-data"))
+            .run(|vm| {
+                vm.sys_input().unwrap();
+                let_assert!(
+                    RunEnterResult::NotExecuted { .. } =
+                        vm.sys_run_enter("my-side-effect".to_owned()).unwrap()
+                );
+                let handle = exit(vm);
+                vm.notify_await_point(handle);
+                assert_that!(vm.take_async_result(handle), err(is_suspended()));
+            })
+            .map(|msg| (msg.ty(), msg.payload().clone()))
+            .collect()
+    }
+
+    let expected = run_with(|vm| {
+        vm.sys_run_exit(
+            RunExitResult::Success(Bytes::from_static(b"Hello Till")),
+            RetryPolicy::default(),
+        )
+        .unwrap()
+    });
+
+    assert_eq!(
+        run_with(|vm| {
+            vm.sys_run_exit_success_buf(
+                Bytes::from_static(b"Hello ").chain(BytesMut::from(&b"Till"[..])),
+                RetryPolicy::default(),
+            )
+            .unwrap()
+        }),
+        expected
+    );
+    assert_eq!(
+        run_with(|vm| {
+            vm.sys_run_exit(
+                RunExitResult::success(BytesMut::from(&b"Hello Till"[..])),
+                RetryPolicy::default(),
+            )
+            .unwrap()
+        }),
+        expected
+    );
+}
+
+#[test]
+fn entry_acks() {
+    let mut output = VMTestCase::new()
+        .input(start_message(2))
+        .input(input_entry_message(b"my-data"))
+        .input(messages::SetStateEntryMessage {
+            key: Bytes::from_static(b"STATE"),
+            value: Bytes::from_static(b"Till"),
+            ..Default::default()
+        })
+        .run_without_closing_input(|vm, encoder| {
+            vm.sys_input().unwrap();
+            vm.sys_state_set("STATE".to_owned(), Bytes::from_static(b"Till"))
+                .unwrap();
+            assert!(vm.is_entry_acked(1));
+
+            vm.sys_state_set("STATE".to_owned(), Bytes::from_static(b"Francesco"))
+                .unwrap();
+            assert!(!vm.is_entry_acked(2));
+
+            let_assert!(
+                RunEnterResult::NotExecuted { .. } =
+                    vm.sys_run_enter("my-side-effect".to_owned()).unwrap()
+            );
+            let handle = vm
+                .sys_run_exit(
+                    RunExitResult::Success(Bytes::from_static(b"123")),
+                    RetryPolicy::default(),
+                )
+                .unwrap();
+            assert!(!vm.is_entry_acked(3));
+
+            // The ack of the run entry acks the previous entries too
+            vm.notify_input(encoder.encode(&EntryAckMessage { entry_index: 3 }));
+            assert!(vm.is_entry_acked(2));
+            assert!(vm.is_entry_acked(3));
+            assert!(!vm.is_entry_acked(4));
+
+            vm.notify_input_closed();
+            vm.notify_await_point(handle);
+            let_assert!(Some(Value::Success(_)) = vm.take_async_result(handle).unwrap());
+            vm.sys_end().unwrap();
+        });
+
+    output
+        .next_decoded::<messages::SetStateEntryMessage>()
+        .unwrap();
+    output.next_decoded::<RunEntryMessage>().unwrap();
+    output.next_decoded::<EndMessage>().unwrap();
+    assert_eq!(output.next(), None);
+}
+
+mod idempotent_run {
+    use super::*;
+
+    use test_log::test;
+
+    fn handler(vm: &mut CoreVM) {
+        vm.sys_input().unwrap();
+
+        let_assert!(
+            RunEnterResult::NotExecuted { .. } = vm
+                .sys_run_enter_with_kind("my-side-effect".to_owned(), RunKind::Idempotent)
+                .unwrap()
+        );
+        let value = vm
+            .sys_run_exit_idempotent(
+                RunExitResult::Success(Bytes::from_static(b"123")),
+                RetryPolicy::default(),
+            )
+            .unwrap();
+        let_assert!(NonEmptyValue::Success(s) = value);
+
+        vm.sys_state_set("STATE".to_owned(), s.clone()).unwrap();
+        vm.sys_write_output(NonEmptyValue::Success(s)).unwrap();
+        vm.sys_end().unwrap();
+    }
+
+    #[test]
+    fn does_not_write_entry() {
+        let mut output = VMTestCase::new()
+            .input(start_message(1))
+            .input(input_entry_message(b"my-data"))
+            .run(handler);
+
+        assert_eq!(
+            output
+                .next_decoded::<messages::SetStateEntryMessage>()
+                .unwrap(),
+            messages::SetStateEntryMessage {
+                key: Bytes::from_static(b"STATE"),
+                value: Bytes::from_static(b"123"),
+                ..Default::default()
+            }
+        );
+        assert_that!(
+            output.next_decoded::<OutputEntryMessage>().unwrap(),
+            is_output_with_success(b"123")
+        );
+        output.next_decoded::<EndMessage>().unwrap();
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn is_executed_again_on_replay() {
+        let mut output = VMTestCase::new()
+            .input(start_message(2))
+            .input(input_entry_message(b"my-data"))
+            .input(messages::SetStateEntryMessage {
+                key: Bytes::from_static(b"STATE"),
+                value: Bytes::from_static(b"123"),
+                ..Default::default()
+            })
+            .run(handler);
+
+        // The set state entry at index 1 is replayed
+        assert_that!(
+            output.next_decoded::<OutputEntryMessage>().unwrap(),
+            is_output_with_success(b"123")
+        );
+        output.next_decoded::<EndMessage>().unwrap();
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn at_least_once_is_replayed() {
+        let mut output = VMTestCase::new()
+            .input(start_message(2))
+            .input(input_entry_message(b"my-data"))
+            .input(RunEntryMessage {
+                name: "my-side-effect".to_owned(),
+                result: Some(run_entry_message::Result::Value(Bytes::from_static(b"123"))),
+            })
+            .run(|vm| {
+                vm.sys_input().unwrap();
+
+                let_assert!(
+                    RunEnterResult::Executed(NonEmptyValue::Success(s)) = vm
+                        .sys_run_enter_with_kind("my-side-effect".to_owned(), RunKind::AtLeastOnce)
+                        .unwrap()
+                );
+
+                vm.sys_write_output(NonEmptyValue::Success(s)).unwrap();
+                vm.sys_end().unwrap();
+            });
+
+        assert_that!(
+            output.next_decoded::<OutputEntryMessage>().unwrap(),
+            is_output_with_success(b"123")
+        );
+        output.next_decoded::<EndMessage>().unwrap();
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn retryable_failure_without_retries_is_returned() {
+        let mut output = VMTestCase::new()
+            .input(start_message(1))
+            .input(input_entry_message(b"my-data"))
+            .run(|vm| {
+                vm.sys_input().unwrap();
+
+                vm.sys_run_enter_with_kind("my-side-effect".to_owned(), RunKind::Idempotent)
+                    .unwrap();
+                let value = vm
+                    .sys_run_exit_idempotent(
+                        RunExitResult::RetryableFailure {
+                            error: Error::internal("my-error"),
+                            attempt_duration: Duration::ZERO,
+                        },
+                        RetryPolicy::None,
+                    )
+                    .unwrap();
+                let_assert!(NonEmptyValue::Failure(failure) = value);
+                assert_eq!(failure.code, 500);
+                assert_eq!(failure.message, "my-error");
+
+                vm.sys_end().unwrap();
+            });
+
+        output.next_decoded::<EndMessage>().unwrap();
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn guard() {
+        let mut output = VMTestCase::new()
+            .input(start_message(1))
+            .input(input_entry_message(b"my-data"))
+            .run(|vm| {
+                vm.sys_input().unwrap();
+
+                vm.sys_run_enter_with_kind("my-side-effect".to_owned(), RunKind::Idempotent)
+                    .unwrap();
+                assert_that!(
+                    vm.sys_state_get("Personaggio".to_owned()),
+                    err(eq_vm_error(vm::errors::INSIDE_RUN))
+                );
+            });
+
+        assert_that!(
+            output.next_decoded::<ErrorMessage>().unwrap(),
+            error_message_as_vm_error(vm::errors::INSIDE_RUN)
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn exit_without_enter() {
+        let mut output = VMTestCase::new()
+            .input(start_message(1))
+            .input(input_entry_message(b"my-data"))
+            .run(|vm| {
+                vm.sys_input().unwrap();
+
+                assert_that!(
+                    vm.sys_run_exit_idempotent(
+                        RunExitResult::Success(Bytes::from_static(b"123")),
+                        RetryPolicy::default(),
+                    ),
+                    err(eq_vm_error(vm::errors::INVOKED_RUN_EXIT_WITHOUT_ENTER))
+                );
+            });
+
+        assert_that!(
+            output.next_decoded::<ErrorMessage>().unwrap(),
+            error_message_as_vm_error(vm::errors::INVOKED_RUN_EXIT_WITHOUT_ENTER)
+        );
+        assert_eq!(output.next(), None);
+    }
+}
@@ -136,3 +136,64 @@ fn sleep_still_sleeping() {
     );
     assert_eq!(output.next(), None);
 }
+
+#[test]
+fn sleep_for_computes_wake_up_time() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"Till"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let h1 = vm
+                .sys_sleep_for(
+                    "my-sleep".to_owned(),
+                    Duration::from_secs(1),
+                    Duration::from_millis(1721123699086),
+                )
+                .unwrap();
+            vm.notify_await_point(h1);
+            let_assert!(Err(SuspendedOrVMError::Suspended(_)) = vm.take_async_result(h1));
+        });
+
+    assert_eq!(
+        output.next_decoded::<SleepEntryMessage>().unwrap(),
+        SleepEntryMessage {
+            wake_up_time: 1721123700086,
+            name: "my-sleep".to_owned(),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        output.next_decoded::<SuspensionMessage>().unwrap(),
+        SuspensionMessage {
+            entry_indexes: vec![1],
+        }
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn sleep_for_overflowing_duration() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            assert_that!(
+                vm.sys_sleep_for(
+                    String::new(),
+                    Duration::MAX,
+                    Duration::from_millis(1721123699086)
+                ),
+                err(eq_vm_error(vm::errors::SLEEP_DURATION_OVERFLOW))
+            );
+        });
+
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(vm::errors::SLEEP_DURATION_OVERFLOW)
+    );
+    assert_eq!(output.next(), None);
+}
@@ -1,5 +1,7 @@
 use crate::service_protocol::messages::{start_message::StateEntry, *};
+use crate::service_protocol::Version;
 use crate::tests::VMTestCase;
+use crate::vm::errors::codes;
 use crate::{CoreVM, NonEmptyValue, SuspendedOrVMError, Value, VM};
 use assert2::let_assert;
 use bytes::Bytes;
@@ -1112,6 +1114,205 @@ mod eager {
         assert_eq!(output.next(), None);
     }
 
+    fn get_and_clear_state_prefix_handler(vm: &mut CoreVM) {
+        vm.sys_input().unwrap();
+
+        vm.sys_state_clear_prefix("cart:".to_owned()).unwrap();
+
+        let h1 = vm.sys_state_get("cart:1".to_owned()).unwrap();
+        vm.notify_await_point(h1);
+        let_assert!(Ok(Some(Value::Void)) = vm.take_async_result(h1));
+
+        let h2 = vm.sys_state_get("cart:2".to_owned()).unwrap();
+        vm.notify_await_point(h2);
+        let_assert!(Ok(Some(Value::Void)) = vm.take_async_result(h2));
+
+        let h3 = vm.sys_state_get("owner".to_owned()).unwrap();
+        vm.notify_await_point(h3);
+        let_assert!(Ok(Some(Value::Success(owner))) = vm.take_async_result(h3));
+
+        vm.sys_write_output(NonEmptyValue::Success(owner)).unwrap();
+        vm.sys_end().unwrap()
+    }
+
+    #[test]
+    fn get_clear_prefix_with_partial_state() {
+        let mut output = VMTestCase::new()
+            .input(StartMessage {
+                id: Bytes::from_static(b"abc"),
+                debug_id: "abc".to_owned(),
+                known_entries: 1,
+                state_map: vec![
+                    StateEntry {
+                        key: Bytes::from_static(b"cart:1"),
+                        value: Bytes::from_static(b"apple"),
+                    },
+                    StateEntry {
+                        key: Bytes::from_static(b"owner"),
+                        value: Bytes::from_static(b"Francesco"),
+                    },
+                ],
+                partial_state: true,
+                key: "my-greeter".to_owned(),
+                ..Default::default()
+            })
+            .input(InputEntryMessage {
+                value: Bytes::from_static(b"Till"),
+                ..Default::default()
+            })
+            .run(get_and_clear_state_prefix_handler);
+
+        assert_eq!(
+            output
+                .next_decoded::<ClearStatePrefixEntryMessage>()
+                .unwrap(),
+            ClearStatePrefixEntryMessage {
+                prefix: Bytes::from_static(b"cart:"),
+                ..Default::default()
+            }
+        );
+        // Both the known and the unknown key matching the prefix are eagerly empty
+        assert_eq!(
+            output.next_decoded::<GetStateEntryMessage>().unwrap(),
+            GetStateEntryMessage {
+                key: Bytes::from_static(b"cart:1"),
+                result: Some(get_state_entry_message::Result::Empty(Empty::default())),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<GetStateEntryMessage>().unwrap(),
+            GetStateEntryMessage {
+                key: Bytes::from_static(b"cart:2"),
+                result: Some(get_state_entry_message::Result::Empty(Empty::default())),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<GetStateEntryMessage>().unwrap(),
+            GetStateEntryMessage {
+                key: Bytes::from_static(b"owner"),
+                result: Some(get_state_entry_message::Result::Value(Bytes::from_static(
+                    b"Francesco"
+                ))),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<OutputEntryMessage>().unwrap(),
+            OutputEntryMessage {
+                result: Some(output_entry_message::Result::Value(Bytes::from_static(
+                    b"Francesco"
+                ))),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn clear_prefix_unsupported_on_v3() {
+        let mut output = VMTestCase::with_version(Version::V3)
+            .input(StartMessage {
+                id: Bytes::from_static(b"abc"),
+                debug_id: "abc".to_owned(),
+                known_entries: 1,
+                partial_state: true,
+                ..Default::default()
+            })
+            .input(InputEntryMessage {
+                value: Bytes::from_static(b"Till"),
+                ..Default::default()
+            })
+            .run(|vm| {
+                vm.sys_input().unwrap();
+                let_assert!(Err(err) = vm.sys_state_clear_prefix("cart:".to_owned()));
+                assert_eq!(err.code(), u16::from(codes::UNSUPPORTED_FEATURE));
+            });
+
+        assert_eq!(
+            output.next_decoded::<ErrorMessage>().unwrap().code,
+            u32::from(codes::UNSUPPORTED_FEATURE)
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn eager_state_snapshot_reflects_mutations() {
+        let mut output = VMTestCase::new()
+            .input(StartMessage {
+                id: Bytes::from_static(b"abc"),
+                debug_id: "abc".to_owned(),
+                known_entries: 1,
+                state_map: vec![
+                    StateEntry {
+                        key: Bytes::from_static(b"STATE"),
+                        value: Bytes::from_static(b"Francesco"),
+                    },
+                    StateEntry {
+                        key: Bytes::from_static(b"ANOTHER_STATE"),
+                        value: Bytes::from_static(b"Till"),
+                    },
+                ],
+                partial_state: false,
+                key: "my-greeter".to_owned(),
+                ..Default::default()
+            })
+            .input(InputEntryMessage::default())
+            .run(|vm| {
+                vm.sys_input().unwrap();
+
+                vm.sys_state_set("NEW_STATE".to_owned(), Bytes::from_static(b"Ahmed"))
+                    .unwrap();
+                vm.sys_state_clear("ANOTHER_STATE".to_owned()).unwrap();
+
+                assert_eq!(
+                    vm.eager_state_snapshot(),
+                    Some(vec![
+                        ("NEW_STATE".to_owned(), Bytes::from_static(b"Ahmed")),
+                        ("STATE".to_owned(), Bytes::from_static(b"Francesco")),
+                    ])
+                );
+
+                vm.sys_end().unwrap()
+            });
+
+        assert!(output.next_decoded::<SetStateEntryMessage>().is_some());
+        assert!(output.next_decoded::<ClearStateEntryMessage>().is_some());
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn eager_state_snapshot_with_partial_state() {
+        VMTestCase::new()
+            .input(StartMessage {
+                id: Bytes::from_static(b"abc"),
+                debug_id: "abc".to_owned(),
+                known_entries: 1,
+                state_map: vec![StateEntry {
+                    key: Bytes::from_static(b"STATE"),
+                    value: Bytes::from_static(b"Francesco"),
+                }],
+                partial_state: true,
+                key: "my-greeter".to_owned(),
+                ..Default::default()
+            })
+            .input(InputEntryMessage::default())
+            .run(|vm| {
+                vm.sys_input().unwrap();
+                assert_eq!(vm.eager_state_snapshot(), None);
+                vm.sys_end().unwrap()
+            });
+    }
+
     fn consecutive_get_with_empty_handler(vm: &mut CoreVM) {
         vm.sys_input().unwrap();
 
@@ -1454,4 +1655,302 @@ mod state_keys {
 
         assert_eq!(output.next(), None);
     }
+
+    #[test]
+    fn new_entry_with_eager_state_is_sorted() {
+        let mut output = VMTestCase::new()
+            .input(StartMessage {
+                id: Bytes::from_static(b"abc"),
+                debug_id: "abc".to_owned(),
+                known_entries: 1,
+                partial_state: false,
+                state_map: vec![
+                    StateEntry {
+                        key: Bytes::from_static(b"MY-STATE"),
+                        value: Bytes::from_static(b"Francesco"),
+                    },
+                    StateEntry {
+                        key: Bytes::from_static(b"ANOTHER-STATE"),
+                        value: Bytes::from_static(b"Till"),
+                    },
+                    StateEntry {
+                        key: Bytes::from_static(b"YET-ANOTHER-STATE"),
+                        value: Bytes::from_static(b"Igal"),
+                    },
+                ],
+                ..Default::default()
+            })
+            .input(InputEntryMessage {
+                value: Bytes::from_static(b"Till"),
+                ..Default::default()
+            })
+            .run(get_state_keys_handler);
+
+        assert_eq!(
+            output.next_decoded::<GetStateKeysEntryMessage>().unwrap(),
+            GetStateKeysEntryMessage {
+                result: Some(get_state_keys_entry_message::Result::Value(StateKeys {
+                    keys: vec![
+                        Bytes::from_static(b"ANOTHER-STATE"),
+                        Bytes::from_static(b"MY-STATE"),
+                        Bytes::from_static(b"YET-ANOTHER-STATE"),
+                    ],
+                })),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<OutputEntryMessage>().unwrap(),
+            OutputEntryMessage {
+                result: Some(output_entry_message::Result::Value(Bytes::from_static(
+                    b"ANOTHER-STATE,MY-STATE,YET-ANOTHER-STATE"
+                ))),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    fn set_then_get_state_keys_handler(vm: &mut CoreVM) {
+        vm.sys_input().unwrap();
+
+        vm.sys_state_set("NEW-STATE".to_owned(), Bytes::from_static(b"Francesco"))
+            .unwrap();
+        vm.sys_state_clear("MY-STATE".to_owned()).unwrap();
+
+        let h1 = vm.sys_state_get_keys().unwrap();
+
+        vm.notify_await_point(h1);
+        let h1_result = vm.take_async_result(h1);
+        if let Err(SuspendedOrVMError::Suspended(_)) = &h1_result {
+            return;
+        }
+
+        let output = match h1_result.unwrap().unwrap() {
+            Value::StateKeys(keys) => NonEmptyValue::Success(Bytes::from(keys.join(","))),
+            _ => panic!("Unexpected variants"),
+        };
+
+        vm.sys_write_output(output).unwrap();
+        vm.sys_end().unwrap()
+    }
+
+    #[test]
+    fn lazy_keys_are_merged_with_local_changes() {
+        let state_keys: Bytes = StateKeys {
+            keys: vec![
+                Bytes::from_static(b"MY-STATE"),
+                Bytes::from_static(b"ANOTHER-STATE"),
+            ],
+        }
+        .encode_to_vec()
+        .into();
+        let mut output = VMTestCase::new()
+            .input(StartMessage {
+                id: Bytes::from_static(b"abc"),
+                debug_id: "abc".to_owned(),
+                known_entries: 1,
+                partial_state: true,
+                ..Default::default()
+            })
+            .input(InputEntryMessage {
+                value: Bytes::from_static(b"Till"),
+                ..Default::default()
+            })
+            .input(CompletionMessage {
+                entry_index: 3,
+                result: Some(completion_message::Result::Value(state_keys)),
+            })
+            .run(set_then_get_state_keys_handler);
+
+        assert_eq!(
+            output.next_decoded::<SetStateEntryMessage>().unwrap(),
+            SetStateEntryMessage {
+                key: Bytes::from_static(b"NEW-STATE"),
+                value: Bytes::from_static(b"Francesco"),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<ClearStateEntryMessage>().unwrap(),
+            ClearStateEntryMessage {
+                key: Bytes::from_static(b"MY-STATE"),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<GetStateKeysEntryMessage>().unwrap(),
+            GetStateKeysEntryMessage::default()
+        );
+        assert_eq!(
+            output.next_decoded::<OutputEntryMessage>().unwrap(),
+            OutputEntryMessage {
+                result: Some(output_entry_message::Result::Value(Bytes::from_static(
+                    b"ANOTHER-STATE,NEW-STATE"
+                ))),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn lazy_keys_are_merged_with_local_changes_on_replay() {
+        let mut output = VMTestCase::new()
+            .input(StartMessage {
+                id: Bytes::from_static(b"abc"),
+                debug_id: "abc".to_owned(),
+                known_entries: 4,
+                partial_state: true,
+                ..Default::default()
+            })
+            .input(InputEntryMessage {
+                value: Bytes::from_static(b"Till"),
+                ..Default::default()
+            })
+            .input(SetStateEntryMessage {
+                key: Bytes::from_static(b"NEW-STATE"),
+                value: Bytes::from_static(b"Francesco"),
+                ..Default::default()
+            })
+            .input(ClearStateEntryMessage {
+                key: Bytes::from_static(b"MY-STATE"),
+                ..Default::default()
+            })
+            .input(GetStateKeysEntryMessage {
+                result: Some(get_state_keys_entry_message::Result::Value(StateKeys {
+                    keys: vec![
+                        Bytes::from_static(b"MY-STATE"),
+                        Bytes::from_static(b"ANOTHER-STATE"),
+                    ],
+                })),
+                ..Default::default()
+            })
+            .run(set_then_get_state_keys_handler);
+
+        assert_eq!(
+            output.next_decoded::<OutputEntryMessage>().unwrap(),
+            OutputEntryMessage {
+                result: Some(output_entry_message::Result::Value(Bytes::from_static(
+                    b"ANOTHER-STATE,NEW-STATE"
+                ))),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+}
+
+mod named_entries {
+    use super::*;
+
+    use test_log::test;
+
+    #[test]
+    fn new_entries_carry_name() {
+        let mut output = VMTestCase::new()
+            .input(StartMessage {
+                id: Bytes::from_static(b"abc"),
+                debug_id: "abc".to_owned(),
+                known_entries: 1,
+                partial_state: true,
+                ..Default::default()
+            })
+            .input(InputEntryMessage::default())
+            .run(|vm| {
+                vm.sys_input().unwrap();
+                vm.sys_state_get_with_name("STATE".to_owned(), "load-cart".to_owned())
+                    .unwrap();
+                vm.sys_state_get_keys_with_name("list-carts".to_owned())
+                    .unwrap();
+                vm.sys_state_set_with_name(
+                    "STATE".to_owned(),
+                    Bytes::from_static(b"Francesco"),
+                    "store-cart".to_owned(),
+                )
+                .unwrap();
+                vm.sys_state_clear_with_name("STATE".to_owned(), "drop-cart".to_owned())
+                    .unwrap();
+                vm.sys_end().unwrap()
+            });
+
+        assert_eq!(
+            output.next_decoded::<GetStateEntryMessage>().unwrap().name,
+            "load-cart"
+        );
+        assert_eq!(
+            output
+                .next_decoded::<GetStateKeysEntryMessage>()
+                .unwrap()
+                .name,
+            "list-carts"
+        );
+        assert_eq!(
+            output.next_decoded::<SetStateEntryMessage>().unwrap().name,
+            "store-cart"
+        );
+        assert_eq!(
+            output
+                .next_decoded::<ClearStateEntryMessage>()
+                .unwrap()
+                .name,
+            "drop-cart"
+        );
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
+
+    #[test]
+    fn name_is_ignored_on_replay() {
+        let mut output = VMTestCase::new()
+            .input(StartMessage {
+                id: Bytes::from_static(b"abc"),
+                debug_id: "abc".to_owned(),
+                known_entries: 3,
+                partial_state: true,
+                ..Default::default()
+            })
+            .input(InputEntryMessage::default())
+            .input(SetStateEntryMessage {
+                key: Bytes::from_static(b"STATE"),
+                value: Bytes::from_static(b"Francesco"),
+                ..Default::default()
+            })
+            .input(ClearStateEntryMessage {
+                key: Bytes::from_static(b"STATE"),
+                name: "old-name".to_owned(),
+            })
+            .run(|vm| {
+                vm.sys_input().unwrap();
+                vm.sys_state_set_with_name(
+                    "STATE".to_owned(),
+                    Bytes::from_static(b"Francesco"),
+                    "store-cart".to_owned(),
+                )
+                .unwrap();
+                vm.sys_state_clear("STATE".to_owned()).unwrap();
+                vm.sys_end().unwrap()
+            });
+
+        assert_eq!(
+            output.next_decoded::<EndMessage>().unwrap(),
+            EndMessage::default()
+        );
+        assert_eq!(output.next(), None);
+    }
 }
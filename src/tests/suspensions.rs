@@ -1,8 +1,8 @@
 use super::*;
 
 use crate::service_protocol::messages::{
-    completion_message, AwakeableEntryMessage, CompletionMessage, EndMessage, GetStateEntryMessage,
-    SuspensionMessage,
+    completion_message, AwakeableEntryMessage, CompletionMessage, EndMessage, ErrorMessage,
+    GetStateEntryMessage, SuspensionMessage,
 };
 use test_log::test;
 
@@ -194,3 +194,106 @@ fn when_notify_await_point_then_notify_completion_then_notify_input_closed_then_
     );
     assert_eq!(output.next(), None);
 }
+
+#[test]
+fn on_suspension_callback() {
+    let suspended_on = std::sync::Arc::new(std::sync::Mutex::new(None));
+    let suspended_on_clone = suspended_on.clone();
+
+    let mut output = VMTestCase::with_options(VMOptions {
+        on_suspension: Some(std::sync::Arc::new(move |entry_indexes| {
+            *suspended_on_clone.lock().unwrap() = Some(entry_indexes.to_vec());
+        })),
+        ..VMOptions::default()
+    })
+    .input(start_message(1))
+    .input(input_entry_message(b"my-data"))
+    .run(|vm| {
+        vm.sys_input().unwrap();
+
+        let (_, h) = vm.sys_awakeable().unwrap();
+        vm.notify_await_point(h);
+        assert_that!(
+            vm.take_async_result(h),
+            err(pat!(SuspendedOrVMError::Suspended(_)))
+        );
+    });
+
+    assert_eq!(*suspended_on.lock().unwrap(), Some(vec![1]));
+
+    let _ = output.next_decoded::<AwakeableEntryMessage>().unwrap();
+    assert_eq!(
+        output.next_decoded::<SuspensionMessage>().unwrap(),
+        SuspensionMessage {
+            entry_indexes: vec![1],
+        }
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn never_suspend_policy() {
+    let mut output = VMTestCase::with_options(VMOptions {
+        suspension_policy: SuspensionPolicy::NeverSuspend,
+        ..VMOptions::default()
+    })
+    .input(start_message(1))
+    .input(input_entry_message(b"my-data"))
+    .run(|vm| {
+        vm.sys_input().unwrap();
+
+        let (_, h) = vm.sys_awakeable().unwrap();
+        vm.notify_await_point(h);
+        assert_that!(
+            vm.take_async_result(h),
+            err(pat!(SuspendedOrVMError::VM(eq_vm_error(
+                vm::errors::WouldSuspendError {
+                    entry_indexes: vec![1]
+                }
+                .into()
+            ))))
+        );
+        assert_eq!(vm.last_suspension(), None);
+    });
+
+    let _ = output.next_decoded::<AwakeableEntryMessage>().unwrap();
+    assert_that!(
+        output.next_decoded::<ErrorMessage>().unwrap(),
+        error_message_as_vm_error(
+            vm::errors::WouldSuspendError {
+                entry_indexes: vec![1]
+            }
+            .into()
+        )
+    );
+    assert_eq!(output.next(), None);
+}
+
+#[test]
+fn last_suspension() {
+    let mut output = VMTestCase::new()
+        .input(start_message(1))
+        .input(input_entry_message(b"my-data"))
+        .run(|vm| {
+            vm.sys_input().unwrap();
+
+            let (_, h) = vm.sys_awakeable().unwrap();
+            assert_eq!(vm.last_suspension(), None);
+
+            vm.notify_await_point(h);
+            assert_that!(
+                vm.take_async_result(h),
+                err(pat!(SuspendedOrVMError::Suspended(_)))
+            );
+            assert_eq!(
+                vm.last_suspension(),
+                Some(SuspensionInfo {
+                    entry_indexes: vec![1]
+                })
+            );
+        });
+
+    let _ = output.next_decoded::<AwakeableEntryMessage>().unwrap();
+    let _ = output.next_decoded::<SuspensionMessage>().unwrap();
+    assert_eq!(output.next(), None);
+}
@@ -1,12 +1,15 @@
 use crate::service_protocol::messages::{
-    completion_message, CompletionParsingHint, EntryMessage, RestateMessage,
+    completion_message, CompletionParsingHint, EntryMessage, RestateMessage, SuspensionMessage,
     WriteableRestateMessage,
 };
 use crate::service_protocol::{Encoder, MessageType, Version};
-use crate::{AsyncResultHandle, AsyncResultState, EntryRetryInfo, Error, VMOptions, Value};
-use bytes::Bytes;
-use bytes_utils::SegmentedBuf;
-use std::collections::{HashMap, VecDeque};
+use crate::vm::errors::{DuplicateCompletionError, OutputTooLargeError, WouldSuspendError};
+use crate::{
+    AsyncResultHandle, AsyncResultState, CommandType, EntryRetryInfo, Error, SuspensionInfo,
+    SuspensionPolicy, VMOptions, Value,
+};
+use bytes::{Bytes, BytesMut};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::time::Duration;
 
 #[derive(Clone, Debug)]
@@ -17,8 +20,10 @@ pub(crate) struct StartInfo {
     pub(crate) entries_to_replay: u32,
     pub(crate) retry_count_since_last_stored_entry: u32,
     pub(crate) duration_since_last_stored_entry: u64,
+    pub(crate) attributes: Vec<(String, String)>,
 }
 
+#[derive(Clone)]
 pub(crate) struct Journal {
     index: Option<u32>,
     pub(crate) current_entry_ty: MessageType,
@@ -27,11 +32,15 @@ pub(crate) struct Journal {
 
 impl Journal {
     pub(crate) fn transition<M: EntryMessage + RestateMessage>(&mut self, expected: &M) {
-        self.index = Some(self.index.take().map(|i| i + 1).unwrap_or(0));
+        self.index = Some(self.next_index());
         self.current_entry_name = expected.name();
         self.current_entry_ty = M::ty();
     }
 
+    pub(crate) fn next_index(&self) -> u32 {
+        self.index.map(|i| i + 1).unwrap_or(0)
+    }
+
     pub(crate) fn index(&self) -> i64 {
         self.index.map(|u| u as i64).unwrap_or(-1)
     }
@@ -51,29 +60,73 @@ impl Default for Journal {
     }
 }
 
+#[derive(Clone)]
 pub struct Output {
     encoder: Encoder,
-    pub(crate) buffer: SegmentedBuf<Bytes>,
+    pub(crate) buffer: BytesMut,
     is_closed: bool,
+    written_bytes: usize,
+    flush_recommended: bool,
 }
 
 impl Output {
-    pub(crate) fn new(version: Version) -> Self {
+    pub(crate) fn new(version: Version, checksum: bool) -> Self {
         Self {
-            encoder: Encoder::new(version),
+            encoder: Encoder::new(version).with_checksum(checksum),
             buffer: Default::default(),
             is_closed: false,
+            written_bytes: 0,
+            flush_recommended: false,
         }
     }
 
+    /// Like [`Output::new`], but retains the buffer allocation.
+    pub(crate) fn reset(&mut self, version: Version, checksum: bool) {
+        self.encoder = Encoder::new(version).with_checksum(checksum);
+        self.buffer.clear();
+        self.is_closed = false;
+        self.written_bytes = 0;
+        self.flush_recommended = false;
+    }
+
     pub(crate) fn send<M: WriteableRestateMessage>(&mut self, msg: &M) {
         if !self.is_closed {
-            self.buffer.push(self.encoder.encode(msg))
+            let header = msg.generate_header(false);
+            // State mutations and already completed entries don't need the runtime to act promptly
+            self.flush_recommended |= !matches!(
+                header.message_type(),
+                MessageType::SetStateEntry
+                    | MessageType::ClearStateEntry
+                    | MessageType::ClearAllStateEntry
+                    | MessageType::ClearStatePrefixEntry
+            ) && header.completed() != Some(true);
+
+            let len_before = self.buffer.len();
+            self.encoder.encode_into(msg, &mut self.buffer);
+            self.written_bytes += self.buffer.len() - len_before;
         }
     }
 
+    pub(crate) fn flush_recommended(&self) -> bool {
+        self.flush_recommended
+    }
+
+    pub(crate) fn reset_flush_recommended(&mut self) {
+        self.flush_recommended = false;
+    }
+
+    /// Total bytes written since the beginning of the invocation, including the ones already taken.
+    pub(crate) fn written_bytes(&self) -> usize {
+        self.written_bytes
+    }
+
+    pub(crate) fn encoded_len<M: WriteableRestateMessage>(&self, msg: &M) -> usize {
+        self.encoder.encoded_len(msg)
+    }
+
     pub(crate) fn send_eof(&mut self) {
         self.is_closed = true;
+        self.flush_recommended = true;
     }
 
     pub(crate) fn is_closed(&self) -> bool {
@@ -81,18 +134,20 @@ impl Output {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum UnparsedCompletionOrParsingHint {
     UnparsedCompletion(completion_message::Result),
     ParsingHint(CompletionParsingHint),
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub(crate) struct AsyncResultsState {
     unparsed_completions_or_parsing_hints: HashMap<u32, UnparsedCompletionOrParsingHint>,
     ready_results: HashMap<u32, Value>,
     last_acked_entry: u32,
     waiting_ack_results: VecDeque<(u32, Value)>,
+    replayed_resolutions: Vec<(u32, CommandType)>,
+    taken_results: HashSet<u32>,
 }
 
 impl AsyncResultsState {
@@ -100,8 +155,21 @@ impl AsyncResultsState {
         self.ready_results.contains_key(&index)
     }
 
+    pub(crate) fn peek_ready_result(&self, index: u32) -> Option<&Value> {
+        self.ready_results.get(&index)
+    }
+
     pub(crate) fn take_ready_result(&mut self, index: u32) -> Option<Value> {
-        self.ready_results.remove(&index)
+        let value = self.ready_results.remove(&index);
+        if value.is_some() {
+            self.taken_results.insert(index);
+        }
+        value
+    }
+
+    /// True if the result of the index was already taken with [`Self::take_ready_result`].
+    pub(crate) fn was_taken(&self, index: u32) -> bool {
+        self.taken_results.contains(&index)
     }
 
     pub(crate) fn insert_completion_parsing_hint(
@@ -118,7 +186,9 @@ impl AsyncResultsState {
                         .insert(index, completion_parsing_hint.parse(result)?);
                 }
                 UnparsedCompletionOrParsingHint::ParsingHint(_) => {
-                    panic!("Unexpected double call to insert_completion_parsing_hint for entry {index}")
+                    return Err(Error::internal(format!(
+                        "Unexpected double call to insert_completion_parsing_hint for entry {index}"
+                    )))
                 }
             }
         } else {
@@ -135,12 +205,15 @@ impl AsyncResultsState {
         index: u32,
         result: completion_message::Result,
     ) -> Result<(), Error> {
+        if self.ready_results.contains_key(&index) {
+            return Err(DuplicateCompletionError { index }.into());
+        }
         if let Some(unparsed_completion_or_parsing_hint) =
             self.unparsed_completions_or_parsing_hints.remove(&index)
         {
             match unparsed_completion_or_parsing_hint {
                 UnparsedCompletionOrParsingHint::UnparsedCompletion(_) => {
-                    panic!("Unexpected double call to insert_unparsed_completion for entry {index}")
+                    return Err(DuplicateCompletionError { index }.into())
                 }
                 UnparsedCompletionOrParsingHint::ParsingHint(completion_parsing_hint) => {
                     self.ready_results
@@ -160,6 +233,59 @@ impl AsyncResultsState {
         self.ready_results.insert(index, value);
     }
 
+    pub(crate) fn insert_replayed_result(
+        &mut self,
+        index: u32,
+        command_type: CommandType,
+        value: Value,
+    ) {
+        self.replayed_resolutions.push((index, command_type));
+        self.insert_ready_result(index, value);
+    }
+
+    /// Number of async results created and not taken yet, either still waiting for their completion or ready.
+    pub(crate) fn pending_results(&self) -> usize {
+        self.unparsed_completions_or_parsing_hints
+            .values()
+            .filter(|v| matches!(v, UnparsedCompletionOrParsingHint::ParsingHint(_)))
+            .count()
+            + self.ready_results.len()
+            + self.waiting_ack_results.len()
+    }
+
+    /// True if the index is one of the async results counted by [`Self::pending_results`].
+    pub(crate) fn is_pending_result(&self, index: u32) -> bool {
+        matches!(
+            self.unparsed_completions_or_parsing_hints.get(&index),
+            Some(UnparsedCompletionOrParsingHint::ParsingHint(_))
+        ) || self.ready_results.contains_key(&index)
+            || self
+                .waiting_ack_results
+                .iter()
+                .any(|(idx, _)| *idx == index)
+    }
+
+    /// Indexes of the async results counted by [`Self::pending_results`], sorted.
+    pub(crate) fn pending_result_indexes(&self) -> Vec<u32> {
+        let mut indexes: Vec<u32> = self
+            .unparsed_completions_or_parsing_hints
+            .iter()
+            .filter(|(_, v)| matches!(v, UnparsedCompletionOrParsingHint::ParsingHint(_)))
+            .map(|(idx, _)| *idx)
+            .chain(self.ready_results.keys().copied())
+            .chain(self.waiting_ack_results.iter().map(|(idx, _)| *idx))
+            .collect();
+        indexes.sort_unstable();
+        indexes
+    }
+
+    pub(crate) fn replayed_resolutions(&self) -> Vec<(AsyncResultHandle, CommandType)> {
+        self.replayed_resolutions
+            .iter()
+            .map(|(idx, ty)| (AsyncResultHandle(*idx), *ty))
+            .collect()
+    }
+
     pub(crate) fn insert_waiting_ack_result(&mut self, index: u32, value: Value) {
         if index <= self.last_acked_entry {
             self.ready_results.insert(index, value);
@@ -168,6 +294,10 @@ impl AsyncResultsState {
         }
     }
 
+    pub(crate) fn is_acked(&self, index: u32) -> bool {
+        index <= self.last_acked_entry
+    }
+
     pub(crate) fn notify_ack(&mut self, ack: u32) {
         if ack <= self.last_acked_entry {
             return;
@@ -194,6 +324,7 @@ impl AsyncResultsState {
                         | Value::Success(_)
                         | Value::StateKeys(_)
                         | Value::InvocationId(_)
+                        | Value::PromiseCompleted(_)
                         | Value::CombinatorResult(_) => AsyncResultState::Success,
                         Value::Failure(_) => AsyncResultState::Failure,
                     },
@@ -203,7 +334,7 @@ impl AsyncResultsState {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum RunState {
     Running(String),
     NotRunning,
@@ -236,10 +367,41 @@ pub(crate) enum EagerGetStateKeys {
     Keys(Vec<String>),
 }
 
+/// State changes performed locally by the handler while the state was partial.
+/// These are merged into the lazy get state keys result, which the runtime computes without them.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct LocalStateKeysChanges {
+    set: Vec<String>,
+    cleared: Vec<String>,
+    cleared_prefixes: Vec<String>,
+}
+
+impl LocalStateKeysChanges {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.cleared.is_empty() && self.cleared_prefixes.is_empty()
+    }
+
+    pub(crate) fn apply(&self, mut keys: Vec<String>) -> Vec<String> {
+        keys.retain(|k| {
+            !self.cleared.contains(k) && !self.cleared_prefixes.iter().any(|p| k.starts_with(p))
+        });
+        for k in &self.set {
+            if !keys.contains(k) {
+                keys.push(k.clone());
+            }
+        }
+        keys.sort();
+        keys
+    }
+}
+
+#[derive(Clone)]
 pub(crate) struct EagerState {
     is_partial: bool,
     // None means Void, Value means value
     values: HashMap<String, Option<Bytes>>,
+    // Prefixes cleared while the state was partial: unknown keys matching one of them are Void
+    cleared_prefixes: Vec<String>,
 }
 
 impl Default for EagerState {
@@ -247,6 +409,7 @@ impl Default for EagerState {
         Self {
             is_partial: true,
             values: Default::default(),
+            cleared_prefixes: Default::default(),
         }
     }
 }
@@ -259,6 +422,7 @@ impl EagerState {
                 .into_iter()
                 .map(|(key, val)| (key, Some(val)))
                 .collect(),
+            cleared_prefixes: Default::default(),
         }
     }
 
@@ -269,7 +433,7 @@ impl EagerState {
                 None => EagerGetState::Empty,
                 Some(s) => EagerGetState::Value(s.clone()),
             })
-            .unwrap_or(if self.is_partial {
+            .unwrap_or(if self.is_partial && !self.is_cleared_by_prefix(k) {
                 EagerGetState::Unknown
             } else {
                 EagerGetState::Empty
@@ -281,10 +445,45 @@ impl EagerState {
         if self.is_partial {
             EagerGetStateKeys::Unknown
         } else {
-            EagerGetStateKeys::Keys(self.values.keys().cloned().collect())
+            // Sorted, so the journaled entry doesn't depend on the HashMap iteration order
+            let mut keys: Vec<_> = self
+                .values
+                .iter()
+                .filter_map(|(k, v)| v.as_ref().map(|_| k.clone()))
+                .collect();
+            keys.sort();
+            EagerGetStateKeys::Keys(keys)
         }
     }
 
+    pub(crate) fn local_changes(&self) -> LocalStateKeysChanges {
+        let mut changes = LocalStateKeysChanges {
+            cleared_prefixes: self.cleared_prefixes.clone(),
+            ..Default::default()
+        };
+        for (k, v) in &self.values {
+            if v.is_some() {
+                changes.set.push(k.clone());
+            } else {
+                changes.cleared.push(k.clone());
+            }
+        }
+        changes
+    }
+
+    pub(crate) fn snapshot(&self) -> Option<Vec<(String, Bytes)>> {
+        if self.is_partial {
+            return None;
+        }
+        let mut entries: Vec<_> = self
+            .values
+            .iter()
+            .filter_map(|(k, v)| v.as_ref().map(|v| (k.clone(), v.clone())))
+            .collect();
+        entries.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+        Some(entries)
+    }
+
     pub(crate) fn set(&mut self, k: String, v: Bytes) {
         self.values.insert(k, Some(v));
     }
@@ -295,11 +494,24 @@ impl EagerState {
 
     pub(crate) fn clear_all(&mut self) {
         self.values.clear();
+        self.cleared_prefixes.clear();
         self.is_partial = false;
     }
+
+    pub(crate) fn clear_prefix(&mut self, prefix: String) {
+        self.values.retain(|k, _| !k.starts_with(&prefix));
+        if self.is_partial {
+            self.cleared_prefixes.push(prefix);
+        }
+    }
+
+    fn is_cleared_by_prefix(&self, k: &str) -> bool {
+        self.cleared_prefixes.iter().any(|p| k.starts_with(p))
+    }
 }
 
 /// Context of the current invocation. Holds some state across all the different FSM transitions.
+#[derive(Clone)]
 pub(crate) struct Context {
     // We keep those here to persist them in case of logging after transitioning to a failure state
     // It's not very Rusty I know, but it makes much more reasonable handling failure cases.
@@ -307,8 +519,28 @@ pub(crate) struct Context {
     pub(crate) journal: Journal,
 
     pub(crate) input_is_closed: bool,
+    pub(crate) input_consumed: bool,
+    // Whether an output entry was replayed or written, used by VMOptions::strict_output_check
+    pub(crate) output_written: bool,
     pub(crate) output: Output,
     pub(crate) eager_state: EagerState,
+    // Local state changes to merge into the lazy get state keys results, by entry index
+    pub(crate) pending_state_keys_changes: HashMap<u32, LocalStateKeysChanges>,
+    // Keys hinted with VM::prefetch_state_keys, fetched together with the next lazy get state
+    pub(crate) state_prefetch_hints: Vec<String>,
+    // Entry indexes of the get state entries issued ahead of time, by key
+    pub(crate) prefetched_state: HashMap<String, u32>,
+    // Entry indexes of the prefetched get state entries not returned to the SDK yet, either still in prefetched_state
+    // or discarded by a later set/clear. They're excluded from VMOptions::strict_completion_check
+    pub(crate) unclaimed_prefetched_state: HashSet<u32>,
+
+    pub(crate) last_suspension: Option<SuspensionInfo>,
+
+    // Name of the idempotent run being executed, which doesn't write any entry
+    pub(crate) idempotent_run: Option<String>,
+
+    // Parsed from the input entry headers by sys_input
+    pub(crate) invocation_deadline: Option<Duration>,
 
     // Used by the error handler to set ErrorMessage.next_retry_delay
     pub(crate) next_retry_delay: Option<Duration>,
@@ -325,9 +557,41 @@ impl Context {
         self.start_info().expect("state is not WaitingStart")
     }
 
+    /// Write a journal entry, checking it doesn't exceed [`VMOptions::max_total_output_bytes`].
+    pub(crate) fn send_entry<M: WriteableRestateMessage>(&mut self, msg: &M) -> Result<(), Error> {
+        if let Some(max) = self.options.max_total_output_bytes {
+            let total = self.output.written_bytes() + self.output.encoded_len(msg);
+            if total > max {
+                return Err(OutputTooLargeError { total, max }.into());
+            }
+        }
+        self.output.send(msg);
+        Ok(())
+    }
+
+    pub(crate) fn suspend(&mut self, entry_indexes: Vec<u32>) -> Result<(), Error> {
+        if self.options.suspension_policy == SuspensionPolicy::NeverSuspend {
+            return Err(WouldSuspendError { entry_indexes }.into());
+        }
+        if let Some(on_suspension) = &self.options.on_suspension {
+            on_suspension(&entry_indexes);
+        }
+        self.output.send(&SuspensionMessage {
+            entry_indexes: entry_indexes.clone(),
+        });
+        self.output.send_eof();
+        self.last_suspension = Some(SuspensionInfo { entry_indexes });
+        Ok(())
+    }
+
     pub(crate) fn infer_entry_retry_info(&self) -> EntryRetryInfo {
+        self.infer_retry_info_at(self.journal.expect_index())
+    }
+
+    /// Retry info of an attempt executed at the given entry index.
+    pub(crate) fn infer_retry_info_at(&self, entry_index: u32) -> EntryRetryInfo {
         let start_info = self.expect_start_info();
-        if self.journal.expect_index() == start_info.entries_to_replay {
+        if entry_index == start_info.entries_to_replay {
             // This is the first entry we try to commit after replay.
             //  ONLY in this case we re-use the StartInfo!
             let retry_count = start_info.retry_count_since_last_stored_entry;
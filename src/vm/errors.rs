@@ -1,4 +1,6 @@
-use crate::service_protocol::{DecodingError, MessageType, UnsupportedVersionError};
+use crate::service_protocol::{
+    DecodingError, MessageType, ProtocolFeature, UnsupportedVersionError,
+};
 use crate::{Error, Version};
 use std::borrow::Cow;
 use std::fmt;
@@ -57,12 +59,85 @@ pub mod codes {
     use super::InvocationErrorCode;
 
     pub const BAD_REQUEST: InvocationErrorCode = InvocationErrorCode(400);
+    pub const CANCELLED: InvocationErrorCode = InvocationErrorCode(409);
     pub const INTERNAL: InvocationErrorCode = InvocationErrorCode(500);
     pub const UNSUPPORTED_MEDIA_TYPE: InvocationErrorCode = InvocationErrorCode(415);
     pub const JOURNAL_MISMATCH: InvocationErrorCode = InvocationErrorCode(570);
     pub const PROTOCOL_VIOLATION: InvocationErrorCode = InvocationErrorCode(571);
     pub const AWAITING_TWO_ASYNC_RESULTS: InvocationErrorCode = InvocationErrorCode(572);
     pub const UNSUPPORTED_FEATURE: InvocationErrorCode = InvocationErrorCode(573);
+    pub const WOULD_SUSPEND: InvocationErrorCode = InvocationErrorCode(574);
+    pub const INVALID_ASYNC_RESULT_HANDLE: InvocationErrorCode = InvocationErrorCode(575);
+    pub const OUTPUT_TOO_LARGE: InvocationErrorCode = InvocationErrorCode(576);
+    pub const UNAWAITED_ASYNC_RESULTS: InvocationErrorCode = InvocationErrorCode(577);
+}
+
+/// Typed counterpart of the well-known error [`codes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    BadRequest,
+    Cancelled,
+    Internal,
+    UnsupportedMediaType,
+    JournalMismatch,
+    ProtocolViolation,
+    AwaitingTwoAsyncResults,
+    UnsupportedFeature,
+    WouldSuspend,
+    InvalidAsyncResultHandle,
+    OutputTooLarge,
+    UnawaitedAsyncResults,
+}
+
+impl ErrorCode {
+    pub const fn invocation_error_code(self) -> InvocationErrorCode {
+        match self {
+            ErrorCode::BadRequest => codes::BAD_REQUEST,
+            ErrorCode::Cancelled => codes::CANCELLED,
+            ErrorCode::Internal => codes::INTERNAL,
+            ErrorCode::UnsupportedMediaType => codes::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::JournalMismatch => codes::JOURNAL_MISMATCH,
+            ErrorCode::ProtocolViolation => codes::PROTOCOL_VIOLATION,
+            ErrorCode::AwaitingTwoAsyncResults => codes::AWAITING_TWO_ASYNC_RESULTS,
+            ErrorCode::UnsupportedFeature => codes::UNSUPPORTED_FEATURE,
+            ErrorCode::WouldSuspend => codes::WOULD_SUSPEND,
+            ErrorCode::InvalidAsyncResultHandle => codes::INVALID_ASYNC_RESULT_HANDLE,
+            ErrorCode::OutputTooLarge => codes::OUTPUT_TOO_LARGE,
+            ErrorCode::UnawaitedAsyncResults => codes::UNAWAITED_ASYNC_RESULTS,
+        }
+    }
+
+    /// Returns `None` if the code is not one of the well-known codes, e.g. an arbitrary HTTP status code.
+    pub const fn from_code(code: u16) -> Option<Self> {
+        Some(match code {
+            400 => ErrorCode::BadRequest,
+            409 => ErrorCode::Cancelled,
+            500 => ErrorCode::Internal,
+            415 => ErrorCode::UnsupportedMediaType,
+            570 => ErrorCode::JournalMismatch,
+            571 => ErrorCode::ProtocolViolation,
+            572 => ErrorCode::AwaitingTwoAsyncResults,
+            573 => ErrorCode::UnsupportedFeature,
+            574 => ErrorCode::WouldSuspend,
+            575 => ErrorCode::InvalidAsyncResultHandle,
+            576 => ErrorCode::OutputTooLarge,
+            577 => ErrorCode::UnawaitedAsyncResults,
+            _ => return None,
+        })
+    }
+}
+
+impl From<ErrorCode> for InvocationErrorCode {
+    fn from(value: ErrorCode) -> Self {
+        value.invocation_error_code()
+    }
+}
+
+impl From<ErrorCode> for u16 {
+    fn from(value: ErrorCode) -> Self {
+        value.invocation_error_code().0
+    }
 }
 
 // Const errors
@@ -120,16 +195,49 @@ pub const INPUT_CLOSED_WHILE_WAITING_ENTRIES: Error = Error::new_const(
     "The input was closed while still waiting to receive all the `known_entries`",
 );
 
+pub const INPUT_ALREADY_CONSUMED: Error = Error::new_const(
+    codes::PROTOCOL_VIOLATION,
+    "sys_input was already invoked, the input can be consumed only once",
+);
+
+pub const OUTPUT_ALREADY_WRITTEN: Error = Error::new_const(
+    codes::PROTOCOL_VIOLATION,
+    "sys_write_output was already invoked, the output can be written only once",
+);
+
 pub const BAD_COMBINATOR_ENTRY: Error = Error::new_const(
     codes::PROTOCOL_VIOLATION,
     "The combinator cannot be replayed. This is most likely caused by non deterministic code.",
 );
 
+pub const SLEEP_DURATION_OVERFLOW: Error = Error::new_const(
+    codes::BAD_REQUEST,
+    "The sleep duration is too large, the wake up time overflows",
+);
+
+pub const EMPTY_SERVICE_NAME: Error = Error::new_const(
+    codes::BAD_REQUEST,
+    "The target service name must not be empty",
+);
+
+pub const EMPTY_HANDLER_NAME: Error = Error::new_const(
+    codes::BAD_REQUEST,
+    "The target handler name must not be empty",
+);
+
 pub const EMPTY_IDEMPOTENCY_KEY: Error = Error::new_const(
     codes::INTERNAL,
     "Trying to execute an idempotent request with an empty idempotency key, this is not supported",
 );
 
+pub const EMPTY_COMPLETION_IDEMPOTENCY_KEY: Error = Error::new_const(
+    codes::INTERNAL,
+    "Trying to complete an awakeable or promise with an empty idempotency key, this is not supported",
+);
+
+/// Message of the terminal failure written by `sys_self_cancel`.
+pub const CANCELLED_MESSAGE: &str = "Cancelled";
+
 // Other errors
 
 #[derive(Debug, Clone, thiserror::Error)]
@@ -186,6 +294,33 @@ pub struct AwaitingTwoAsyncResultError {
     pub(crate) current: u32,
 }
 
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Async result handle {handle} doesn't correspond to any async result of this invocation: either the entry was never created, or it is not completable")]
+pub struct InvalidAsyncResultHandleError {
+    pub(crate) handle: u32,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error(
+    "The invocation output reached {total} bytes, exceeding the configured maximum of {max} bytes"
+)]
+pub struct OutputTooLargeError {
+    pub(crate) total: usize,
+    pub(crate) max: usize,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("The invocation would suspend waiting on entries {entry_indexes:?}, but the suspension policy forbids it")]
+pub struct WouldSuspendError {
+    pub(crate) entry_indexes: Vec<u32>,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("The invocation ended with async results that were never awaited: {handles:?}")]
+pub struct UnawaitedAsyncResultsError {
+    pub(crate) handles: Vec<u32>,
+}
+
 #[derive(Debug, Clone, thiserror::Error)]
 #[error("Cannot convert a eager state key into UTF-8 String: {0:?}")]
 pub struct BadEagerStateKeyError(#[from] pub(crate) std::string::FromUtf8Error);
@@ -210,24 +345,72 @@ pub struct EmptyGetCallInvocationId;
 #[error("Cannot decode get call invocation id: {0}")]
 pub struct DecodeGetCallInvocationIdUtf8(#[from] pub(crate) std::string::FromUtf8Error);
 
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Unexpected empty variant for complete promise if unset")]
+pub struct EmptyPromiseCompleted;
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Received more than one completion for entry {index}")]
+pub struct DuplicateCompletionError {
+    pub(crate) index: u32,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error(
+    "Cannot decode complete promise if unset result, expected a single byte 0x00 or 0x01: {0:?}"
+)]
+pub struct DecodePromiseCompleted(pub(crate) bytes::Bytes);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Message type {ty:?} is not supported by the negotiated protocol version '{current_version}', the minimum required version is '{minimum_required_version}'")]
+pub struct UnsupportedMessageTypeForNegotiatedVersion {
+    ty: MessageType,
+    current_version: Version,
+    minimum_required_version: Version,
+}
+
+impl UnsupportedMessageTypeForNegotiatedVersion {
+    pub fn new(ty: MessageType, current_version: Version) -> Self {
+        Self {
+            ty,
+            current_version,
+            minimum_required_version: ty.minimum_required_version(),
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 #[error("Feature {feature} is not supported by the negotiated protocol version '{current_version}', the minimum required version is '{minimum_required_version}'")]
 pub struct UnsupportedFeatureForNegotiatedVersion {
-    feature: &'static str,
+    feature: ProtocolFeature,
     current_version: Version,
     minimum_required_version: Version,
 }
 
 impl UnsupportedFeatureForNegotiatedVersion {
-    pub fn new(
-        feature: &'static str,
-        current_version: Version,
-        minimum_required_version: Version,
-    ) -> Self {
+    pub fn new(feature: ProtocolFeature, current_version: Version) -> Self {
         Self {
             feature,
             current_version,
-            minimum_required_version,
+            minimum_required_version: feature.minimum_required_version(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Invalid journal at message {position} ({ty:?}): {reason}")]
+pub struct InvalidJournalError {
+    position: usize,
+    ty: MessageType,
+    reason: String,
+}
+
+impl InvalidJournalError {
+    pub fn new(position: usize, ty: MessageType, reason: impl Into<String>) -> Self {
+        Self {
+            position,
+            ty,
+            reason: reason.into(),
         }
     }
 }
@@ -254,22 +437,45 @@ macro_rules! impl_error_code {
     };
 }
 
-impl_error_code!(UnsupportedVersionError, UNSUPPORTED_MEDIA_TYPE);
+impl WithInvocationErrorCode for UnsupportedVersionError {
+    fn code(&self) -> InvocationErrorCode {
+        match self {
+            UnsupportedVersionError::Malformed(_) => codes::BAD_REQUEST,
+            UnsupportedVersionError::Unsupported(_) => codes::UNSUPPORTED_MEDIA_TYPE,
+        }
+    }
+}
+impl_error_code!(InvalidJournalError, PROTOCOL_VIOLATION);
 impl WithInvocationErrorCode for DecodingError {
     fn code(&self) -> InvocationErrorCode {
         match self {
             DecodingError::UnexpectedMessageType { .. } => codes::JOURNAL_MISMATCH,
-            _ => codes::INTERNAL,
+            DecodingError::DecodeMessage(..)
+            | DecodingError::UnknownMessageType(_)
+            | DecodingError::NotACommand(_)
+            | DecodingError::ChecksumMismatch { .. }
+            | DecodingError::MessageTooLarge { .. } => codes::PROTOCOL_VIOLATION,
         }
     }
 }
 impl_error_code!(UnavailableEntryError, JOURNAL_MISMATCH);
 impl_error_code!(UnexpectedStateError, PROTOCOL_VIOLATION);
 impl_error_code!(AwaitingTwoAsyncResultError, AWAITING_TWO_ASYNC_RESULTS);
+impl_error_code!(InvalidAsyncResultHandleError, INVALID_ASYNC_RESULT_HANDLE);
+impl_error_code!(DuplicateCompletionError, PROTOCOL_VIOLATION);
+impl_error_code!(OutputTooLargeError, OUTPUT_TOO_LARGE);
+impl_error_code!(UnawaitedAsyncResultsError, UNAWAITED_ASYNC_RESULTS);
+impl_error_code!(WouldSuspendError, WOULD_SUSPEND);
 impl_error_code!(BadEagerStateKeyError, INTERNAL);
 impl_error_code!(DecodeStateKeysProst, PROTOCOL_VIOLATION);
 impl_error_code!(DecodeStateKeysUtf8, PROTOCOL_VIOLATION);
 impl_error_code!(EmptyStateKeys, PROTOCOL_VIOLATION);
 impl_error_code!(EmptyGetCallInvocationId, PROTOCOL_VIOLATION);
 impl_error_code!(DecodeGetCallInvocationIdUtf8, PROTOCOL_VIOLATION);
+impl_error_code!(EmptyPromiseCompleted, PROTOCOL_VIOLATION);
+impl_error_code!(DecodePromiseCompleted, PROTOCOL_VIOLATION);
 impl_error_code!(UnsupportedFeatureForNegotiatedVersion, UNSUPPORTED_FEATURE);
+impl_error_code!(
+    UnsupportedMessageTypeForNegotiatedVersion,
+    PROTOCOL_VIOLATION
+);
@@ -0,0 +1,123 @@
+use crate::service_protocol::messages::*;
+use crate::service_protocol::{MessageType, RawMessage, Version};
+use crate::vm::errors::{InvalidJournalError, UnsupportedMessageTypeForNegotiatedVersion};
+use crate::Error;
+use std::collections::HashMap;
+
+/// Validates a recorded journal offline, without executing the handler.
+///
+/// `entries` must start with the input entry, and can contain only entries and completions. Checks that:
+///
+/// * Every message is supported by the given protocol `version`, and its payload can be decoded.
+/// * Every completion refers to a previous completable entry, which was not completed already.
+pub fn validate_journal(version: Version, entries: &[RawMessage]) -> Result<(), Error> {
+    // Entry index -> true if completed
+    let mut completable_entries: HashMap<u32, bool> = HashMap::new();
+    let mut entry_index = 0u32;
+
+    for (position, msg) in entries.iter().enumerate() {
+        let ty = msg.ty();
+        if ty.minimum_required_version() > version {
+            return Err(UnsupportedMessageTypeForNegotiatedVersion::new(ty, version).into());
+        }
+        if position == 0 && ty != MessageType::InputEntry {
+            return Err(InvalidJournalError::new(
+                position,
+                ty,
+                "the first entry must be the input",
+            )
+            .into());
+        }
+
+        if ty == MessageType::Completion {
+            let completion = msg.clone().decode_to::<CompletionMessage>()?;
+            match completable_entries.get_mut(&completion.entry_index) {
+                None => {
+                    return Err(InvalidJournalError::new(
+                        position,
+                        ty,
+                        format!(
+                            "entry {} doesn't exist or is not completable",
+                            completion.entry_index
+                        ),
+                    )
+                    .into())
+                }
+                Some(true) => {
+                    return Err(InvalidJournalError::new(
+                        position,
+                        ty,
+                        format!("entry {} was already completed", completion.entry_index),
+                    )
+                    .into())
+                }
+                Some(completed) => *completed = true,
+            }
+            continue;
+        }
+
+        if !ty.is_entry() {
+            return Err(InvalidJournalError::new(
+                position,
+                ty,
+                "expected only entries and completions",
+            )
+            .into());
+        }
+        decode_entry(msg)?;
+        if let Some(completed) = msg.header().completed() {
+            completable_entries.insert(entry_index, completed);
+        }
+        entry_index += 1;
+    }
+
+    Ok(())
+}
+
+fn decode_entry(msg: &RawMessage) -> Result<(), Error> {
+    let msg = msg.clone();
+    match msg.ty() {
+        MessageType::InputEntry => msg.decode_to::<InputEntryMessage>().map(drop),
+        MessageType::OutputEntry => msg.decode_to::<OutputEntryMessage>().map(drop),
+        MessageType::GetStateEntry => msg.decode_to::<GetStateEntryMessage>().map(drop),
+        MessageType::SetStateEntry => msg.decode_to::<SetStateEntryMessage>().map(drop),
+        MessageType::ClearStateEntry => msg.decode_to::<ClearStateEntryMessage>().map(drop),
+        MessageType::GetStateKeysEntry => msg.decode_to::<GetStateKeysEntryMessage>().map(drop),
+        MessageType::ClearAllStateEntry => msg.decode_to::<ClearAllStateEntryMessage>().map(drop),
+        MessageType::ClearStatePrefixEntry => {
+            msg.decode_to::<ClearStatePrefixEntryMessage>().map(drop)
+        }
+        MessageType::GetPromiseEntry => msg.decode_to::<GetPromiseEntryMessage>().map(drop),
+        MessageType::PeekPromiseEntry => msg.decode_to::<PeekPromiseEntryMessage>().map(drop),
+        MessageType::CompletePromiseEntry => {
+            msg.decode_to::<CompletePromiseEntryMessage>().map(drop)
+        }
+        MessageType::CompletePromiseIfUnsetEntry => msg
+            .decode_to::<CompletePromiseIfUnsetEntryMessage>()
+            .map(drop),
+        MessageType::SleepEntry => msg.decode_to::<SleepEntryMessage>().map(drop),
+        MessageType::CallEntry => msg.decode_to::<CallEntryMessage>().map(drop),
+        MessageType::OneWayCallEntry => msg.decode_to::<OneWayCallEntryMessage>().map(drop),
+        MessageType::AwakeableEntry => msg.decode_to::<AwakeableEntryMessage>().map(drop),
+        MessageType::CompleteAwakeableEntry => {
+            msg.decode_to::<CompleteAwakeableEntryMessage>().map(drop)
+        }
+        MessageType::RunEntry => msg.decode_to::<RunEntryMessage>().map(drop),
+        MessageType::CancelInvocationEntry => {
+            msg.decode_to::<CancelInvocationEntryMessage>().map(drop)
+        }
+        MessageType::GetCallInvocationIdEntry => {
+            msg.decode_to::<GetCallInvocationIdEntryMessage>().map(drop)
+        }
+        MessageType::AttachInvocationEntry => {
+            msg.decode_to::<AttachInvocationEntryMessage>().map(drop)
+        }
+        MessageType::GetInvocationOutputEntry => {
+            msg.decode_to::<GetInvocationOutputEntryMessage>().map(drop)
+        }
+        MessageType::CombinatorEntry => msg.decode_to::<CombinatorEntryMessage>().map(drop),
+        // Custom entries are opaque
+        _ => Ok(()),
+    }
+    .map_err(Error::from)
+}
@@ -3,33 +3,36 @@ use crate::service_protocol::messages::get_state_keys_entry_message::StateKeys;
 use crate::service_protocol::messages::{
     attach_invocation_entry_message, cancel_invocation_entry_message,
     complete_awakeable_entry_message, complete_promise_entry_message,
-    get_invocation_output_entry_message, get_state_entry_message, get_state_keys_entry_message,
+    complete_promise_if_unset_entry_message, get_invocation_output_entry_message,
+    get_state_entry_message, get_state_keys_entry_message, headers_to_journal,
     output_entry_message, AttachInvocationEntryMessage, AwakeableEntryMessage, CallEntryMessage,
     CancelInvocationEntryMessage, ClearAllStateEntryMessage, ClearStateEntryMessage,
-    CompleteAwakeableEntryMessage, CompletePromiseEntryMessage, Empty,
-    GetCallInvocationIdEntryMessage, GetInvocationOutputEntryMessage, GetPromiseEntryMessage,
-    GetStateEntryMessage, GetStateKeysEntryMessage, IdempotentRequestTarget,
-    OneWayCallEntryMessage, OutputEntryMessage, PeekPromiseEntryMessage, SetStateEntryMessage,
-    SleepEntryMessage, WorkflowTarget,
+    ClearStatePrefixEntryMessage, CompleteAwakeableEntryMessage, CompletePromiseEntryMessage,
+    CompletePromiseIfUnsetEntryMessage, Empty, GetCallInvocationIdEntryMessage,
+    GetInvocationOutputEntryMessage, GetPromiseEntryMessage, GetStateEntryMessage,
+    GetStateKeysEntryMessage, IdempotentRequestTarget, OneWayCallEntryMessage, OutputEntryMessage,
+    PeekPromiseEntryMessage, SetStateEntryMessage, SleepEntryMessage, WorkflowTarget,
 };
-use crate::service_protocol::{Decoder, RawMessage, Version};
+use crate::service_protocol::{Decoder, MessageType, ProtocolFeature, RawMessage, Version};
 use crate::vm::context::{EagerGetState, EagerGetStateKeys};
 use crate::vm::errors::{
-    UnexpectedStateError, UnsupportedFeatureForNegotiatedVersion, EMPTY_IDEMPOTENCY_KEY,
+    UnexpectedStateError, UnsupportedFeatureForNegotiatedVersion,
+    UnsupportedMessageTypeForNegotiatedVersion,
 };
 use crate::vm::transitions::*;
 use crate::{
-    AsyncResultCombinator, AsyncResultHandle, AttachInvocationTarget, CancelInvocationTarget,
-    Error, GetInvocationIdTarget, Header, Input, NonEmptyValue, ResponseHead, RetryPolicy,
-    RunEnterResult, RunExitResult, SendHandle, SuspendedOrVMError, TakeOutputResult, Target,
-    VMOptions, VMResult, Value,
+    AsyncResultCombinator, AsyncResultHandle, AttachInvocationTarget, CallInvocationIdResult,
+    CancelInvocationTarget, CommandType, EntryRetryInfo, Error, GetInvocationIdTarget, Header,
+    Input, InvocationKind, JournalStats, NonEmptyValue, ResponseHead, RetryPolicy, RunEnterResult,
+    RunExitResult, RunKind, SendHandle, SuspendedOrVMError, SuspensionInfo, TakeOutputResult,
+    Target, TerminalFailure, VMOptions, VMResult, Value,
 };
 use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
 use base64::{alphabet, Engine};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use context::{AsyncResultsState, Context, Output, RunState};
 use std::borrow::Cow;
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 use std::fmt;
 use std::mem::size_of;
 use std::time::Duration;
@@ -38,13 +41,16 @@ use tracing::{debug, enabled, instrument, Level};
 
 mod context;
 pub(crate) mod errors;
+mod journal_validation;
 mod transitions;
 
+pub use journal_validation::validate_journal;
+
 pub(crate) use transitions::AsyncResultAccessTrackerInner;
 
 const CONTENT_TYPE: &str = "content-type";
 
-#[derive(Debug, IntoStaticStr)]
+#[derive(Debug, Clone, IntoStaticStr)]
 pub(crate) enum State {
     WaitingStart,
     WaitingReplayEntries {
@@ -71,6 +77,14 @@ impl State {
     }
 }
 
+/// The state machine of a single invocation.
+///
+/// Cloning snapshots the whole invocation: the journal, the pending async results, and the input and output buffers are copied,
+/// so the clone can be driven independently, e.g. to explore a speculative path and throw it away.
+/// The cost is proportional to the buffered input and output, plus the results not taken yet.
+/// Only one of the clones should drive the actual I/O with the runtime: the others must be discarded,
+/// as their output would duplicate the journal. [`VMOptions::on_suspension`] is shared between the clones.
+#[derive(Clone)]
 pub struct CoreVM {
     version: Version,
 
@@ -80,6 +94,12 @@ pub struct CoreVM {
     // State machine
     context: Context,
     last_transition: Result<State, Error>,
+
+    #[cfg(feature = "trace_events")]
+    transition_trace: VecDeque<crate::TransitionEvent>,
+
+    #[cfg(feature = "vm_stats")]
+    metrics: crate::InvocationMetrics,
 }
 
 impl CoreVM {
@@ -92,24 +112,143 @@ impl CoreVM {
         }
     }
 
-    fn verify_feature_support(
-        &mut self,
-        feature: &'static str,
-        minimum_required_protocol: Version,
-    ) -> VMResult<()> {
-        if self.version < minimum_required_protocol {
-            return self.do_transition(HitError {
-                error: UnsupportedFeatureForNegotiatedVersion::new(
-                    feature,
-                    self.version,
-                    minimum_required_protocol,
+    fn negotiate_version(
+        request_headers: &impl HeaderMap,
+        options: &VMOptions,
+    ) -> Result<Version, Error> {
+        let version = request_headers
+            .extract(CONTENT_TYPE)
+            .map_err(|e| {
+                Error::new(
+                    errors::codes::BAD_REQUEST,
+                    format!("cannot read '{CONTENT_TYPE}' header: {e:?}"),
                 )
-                .into(),
+            })?
+            .ok_or(errors::MISSING_CONTENT_TYPE)?
+            .parse::<Version>()?;
+
+        let maximum_version = if options.enable_experimental_protocol {
+            Version::maximum_experimental_version()
+        } else {
+            Version::maximum_supported_version()
+        };
+        if version < Version::minimum_supported_version() || version > maximum_version {
+            return Err(Error::new(
+                errors::codes::UNSUPPORTED_MEDIA_TYPE,
+                format!(
+                    "Unsupported protocol version {:?}. Supported versions: {:?} to {:?}",
+                    version,
+                    Version::minimum_supported_version(),
+                    maximum_version
+                ),
+            ));
+        }
+
+        Ok(version)
+    }
+
+    fn eager_get_state(&self, key: &str) -> EagerGetState {
+        if self.context.options.force_lazy_state {
+            EagerGetState::Unknown
+        } else {
+            self.context.eager_state.get(key)
+        }
+    }
+
+    fn async_results(&self) -> Option<&AsyncResultsState> {
+        match &self.last_transition {
+            Ok(State::Replaying { async_results, .. })
+            | Ok(State::Processing { async_results, .. }) => Some(async_results),
+            _ => None,
+        }
+    }
+
+    fn verify_feature_support(&mut self, feature: ProtocolFeature) -> VMResult<()> {
+        if !self.version.supports(feature) {
+            return self.do_transition(HitError {
+                error: UnsupportedFeatureForNegotiatedVersion::new(feature, self.version).into(),
                 next_retry_delay: None,
             });
         }
         Ok(())
     }
+
+    fn one_way_call_entry(
+        &mut self,
+        target: Target,
+        input: Bytes,
+        delay: Option<Duration>,
+    ) -> VMResult<OneWayCallEntryMessage> {
+        if target.idempotency_key.is_some() {
+            self.verify_feature_support(ProtocolFeature::IdempotencyKeyOnOneWayCall)?;
+        }
+        if let Err(error) = target.validate() {
+            self.do_transition(HitError {
+                error,
+                next_retry_delay: None,
+            })?;
+            unreachable!();
+        }
+        Ok(OneWayCallEntryMessage {
+            service_name: target.service,
+            handler_name: target.handler,
+            key: target.key.unwrap_or_default(),
+            idempotency_key: target.idempotency_key,
+            headers: headers_to_journal(target.headers),
+            parameter: input,
+            invoke_time: delay
+                .map(|d| {
+                    u64::try_from(d.as_millis()).expect("millis since Unix epoch should fit in u64")
+                })
+                .unwrap_or_default(),
+            ..Default::default()
+        })
+    }
+
+    /// Millis since Unix epoch of `now_since_unix_epoch + duration`, failing the invocation on overflow.
+    fn wake_up_time(
+        &mut self,
+        duration: Duration,
+        now_since_unix_epoch: Duration,
+    ) -> VMResult<u64> {
+        let Some(wake_up_time) = now_since_unix_epoch
+            .checked_add(duration)
+            .and_then(|t| u64::try_from(t.as_millis()).ok())
+        else {
+            self.do_transition(HitError {
+                error: errors::SLEEP_DURATION_OVERFLOW,
+                next_retry_delay: None,
+            })?;
+            unreachable!();
+        };
+        Ok(wake_up_time)
+    }
+
+    fn verify_completion_idempotency_key(&mut self, idempotency_key: &str) -> VMResult<()> {
+        self.verify_feature_support(ProtocolFeature::IdempotencyKeyOnCompletion)?;
+        if idempotency_key.is_empty() {
+            self.do_transition(HitError {
+                error: errors::EMPTY_COMPLETION_IDEMPOTENCY_KEY,
+                next_retry_delay: None,
+            })?;
+            unreachable!();
+        }
+        Ok(())
+    }
+}
+
+impl CoreVM {
+    /// Returns the last state machine transitions, oldest first, up to [`VMOptions::transition_trace_capacity`].
+    #[cfg(feature = "trace_events")]
+    pub fn transition_trace(&self) -> Vec<crate::TransitionEvent> {
+        self.transition_trace.iter().cloned().collect()
+    }
+
+    /// Returns the journal operations counters of the current invocation.
+    #[cfg(feature = "vm_stats")]
+    pub fn metrics(&self) -> crate::InvocationMetrics {
+        self.metrics.clone()
+    }
 }
 
 impl fmt::Debug for CoreVM {
@@ -148,47 +287,70 @@ macro_rules! invocation_debug_logs {
 impl super::VM for CoreVM {
     #[instrument(level = "trace", skip_all, ret)]
     fn new(request_headers: impl HeaderMap, options: VMOptions) -> Result<Self, Error> {
-        let version = request_headers
-            .extract(CONTENT_TYPE)
-            .map_err(|e| {
-                Error::new(
-                    errors::codes::BAD_REQUEST,
-                    format!("cannot read '{CONTENT_TYPE}' header: {e:?}"),
-                )
-            })?
-            .ok_or(errors::MISSING_CONTENT_TYPE)?
-            .parse::<Version>()?;
-
-        if version < Version::minimum_supported_version()
-            || version > Version::maximum_supported_version()
-        {
-            return Err(Error::new(
-                errors::codes::UNSUPPORTED_MEDIA_TYPE,
-                format!(
-                    "Unsupported protocol version {:?}. Supported versions: {:?} to {:?}",
-                    version,
-                    Version::minimum_supported_version(),
-                    Version::maximum_supported_version()
-                ),
-            ));
-        }
+        let version = Self::negotiate_version(&request_headers, &options)?;
 
         Ok(Self {
             version,
-            decoder: Decoder::new(version),
+            decoder: Decoder::new(version, options.max_message_size),
             context: Context {
                 input_is_closed: false,
-                output: Output::new(version),
+                input_consumed: false,
+                output_written: false,
+                output: Output::new(version, options.output_checksum),
                 start_info: None,
                 journal: Default::default(),
                 eager_state: Default::default(),
+                pending_state_keys_changes: Default::default(),
+                state_prefetch_hints: Default::default(),
+                prefetched_state: Default::default(),
+                unclaimed_prefetched_state: Default::default(),
+                last_suspension: None,
+                idempotent_run: None,
+                invocation_deadline: None,
                 next_retry_delay: None,
                 options,
             },
             last_transition: Ok(State::WaitingStart),
+            #[cfg(feature = "trace_events")]
+            transition_trace: VecDeque::new(),
+            #[cfg(feature = "vm_stats")]
+            metrics: Default::default(),
         })
     }
 
+    #[instrument(level = "trace", skip_all, ret)]
+    fn reset(&mut self, request_headers: impl HeaderMap, options: VMOptions) -> VMResult<()> {
+        let version = Self::negotiate_version(&request_headers, &options)?;
+
+        self.version = version;
+        self.decoder.reset(options.max_message_size);
+        self.context.input_is_closed = false;
+        self.context.input_consumed = false;
+        self.context.output_written = false;
+        self.context.output.reset(version, options.output_checksum);
+        self.context.start_info = None;
+        self.context.journal = Default::default();
+        self.context.eager_state = Default::default();
+        self.context.pending_state_keys_changes.clear();
+        self.context.state_prefetch_hints.clear();
+        self.context.prefetched_state.clear();
+        self.context.unclaimed_prefetched_state.clear();
+        self.context.last_suspension = None;
+        self.context.idempotent_run = None;
+        self.context.invocation_deadline = None;
+        self.context.next_retry_delay = None;
+        self.context.options = options;
+        self.last_transition = Ok(State::WaitingStart);
+        #[cfg(feature = "trace_events")]
+        self.transition_trace.clear();
+        #[cfg(feature = "vm_stats")]
+        {
+            self.metrics = Default::default();
+        }
+
+        Ok(())
+    }
+
     #[instrument(
         level = "trace",
         skip(self),
@@ -196,12 +358,21 @@ impl super::VM for CoreVM {
         ret
     )]
     fn get_response_head(&self) -> ResponseHead {
+        let mut headers = vec![Header {
+            key: Cow::Borrowed(CONTENT_TYPE),
+            value: Cow::Borrowed(self.version.content_type()),
+        }];
+        headers.extend(
+            self.context
+                .options
+                .extra_response_headers
+                .iter()
+                .filter(|h| !h.key.eq_ignore_ascii_case(CONTENT_TYPE))
+                .cloned(),
+        );
         ResponseHead {
             status_code: 200,
-            headers: vec![Header {
-                key: Cow::Borrowed(CONTENT_TYPE),
-                value: Cow::Borrowed(self.version.content_type()),
-            }],
+            headers,
             version: self.version,
         }
     }
@@ -213,9 +384,28 @@ impl super::VM for CoreVM {
         ret
     )]
     fn notify_input(&mut self, buffer: Bytes) {
+        if self.decoder.is_poisoned() {
+            // We already failed with the decoding error, the rest of the stream can't be decoded
+            return;
+        }
         self.decoder.push(buffer);
         loop {
             match self.decoder.consume_next() {
+                Ok(Some(msg)) if msg.ty().minimum_required_version() > self.version => {
+                    if self
+                        .do_transition(HitError {
+                            error: UnsupportedMessageTypeForNegotiatedVersion::new(
+                                msg.ty(),
+                                self.version,
+                            )
+                            .into(),
+                            next_retry_delay: None,
+                        })
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
                 Ok(Some(msg)) => {
                     if self.do_transition(NewMessage(msg)).is_err() {
                         return;
@@ -239,6 +429,14 @@ impl super::VM for CoreVM {
         }
     }
 
+    fn notify_input_buf(&mut self, mut buf: impl Buf) {
+        while buf.has_remaining() {
+            // Zero-copy for Bytes and BytesMut chunks
+            let chunk_len = buf.chunk().len();
+            self.notify_input(buf.copy_to_bytes(chunk_len));
+        }
+    }
+
     #[instrument(
         level = "trace",
         skip(self),
@@ -270,13 +468,9 @@ impl super::VM for CoreVM {
         ret
     )]
     fn take_output(&mut self) -> TakeOutputResult {
-        if self.context.output.buffer.has_remaining() {
-            TakeOutputResult::Buffer(
-                self.context
-                    .output
-                    .buffer
-                    .copy_to_bytes(self.context.output.buffer.remaining()),
-            )
+        self.context.output.reset_flush_recommended();
+        if !self.context.output.buffer.is_empty() {
+            TakeOutputResult::Buffer(self.context.output.buffer.split().freeze())
         } else if !self.context.output.is_closed() {
             TakeOutputResult::Buffer(Bytes::default())
         } else {
@@ -284,6 +478,21 @@ impl super::VM for CoreVM {
         }
     }
 
+    fn flush_hint(&self) -> bool {
+        self.context.output.flush_recommended()
+    }
+
+    fn output_buffer_len(&self) -> usize {
+        self.context.output.buffer.len()
+    }
+
+    fn is_output_buffer_full(&self) -> bool {
+        self.context
+            .options
+            .max_output_buffer_bytes
+            .is_some_and(|max| self.output_buffer_len() >= max)
+    }
+
     #[instrument(
         level = "trace",
         skip(self),
@@ -326,6 +535,67 @@ impl super::VM for CoreVM {
         }
     }
 
+    fn take_ready_async_results(
+        &mut self,
+        handles: &[AsyncResultHandle],
+    ) -> Result<Vec<(AsyncResultHandle, Value)>, SuspendedOrVMError> {
+        // Outside of replaying/processing, take_async_result reports the suspension or the state error
+        let mut seen = HashSet::new();
+        let ready: Vec<AsyncResultHandle> = handles
+            .iter()
+            .copied()
+            .filter(|handle| seen.insert(handle.0))
+            .filter(|handle| {
+                self.async_results()
+                    .is_none_or(|async_results| async_results.has_ready_result(handle.0))
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(ready.len());
+        for handle in ready {
+            if let Some(value) = self.take_async_result(handle)? {
+                results.push((handle, value));
+            }
+        }
+        Ok(results)
+    }
+
+    fn partition_ready(
+        &self,
+        handles: &[AsyncResultHandle],
+    ) -> (Vec<AsyncResultHandle>, Vec<AsyncResultHandle>) {
+        match self.async_results() {
+            Some(async_results) => handles
+                .iter()
+                .partition(|handle| async_results.has_ready_result(handle.0)),
+            None => (vec![], handles.to_vec()),
+        }
+    }
+
+    fn any_completed(&self, handles: &[AsyncResultHandle]) -> bool {
+        self.async_results().is_some_and(|async_results| {
+            handles
+                .iter()
+                .any(|handle| async_results.has_ready_result(handle.0))
+        })
+    }
+
+    fn all_completed(&self, handles: &[AsyncResultHandle]) -> bool {
+        self.async_results().is_some_and(|async_results| {
+            handles
+                .iter()
+                .all(|handle| async_results.has_ready_result(handle.0))
+        })
+    }
+
+    fn ready_result_len(&self, handle: AsyncResultHandle) -> Option<usize> {
+        match self.async_results()?.peek_ready_result(handle.0)? {
+            Value::Success(b) => Some(b.len()),
+            Value::Failure(f) => Some(f.message.len()),
+            _ => None,
+        }
+    }
+
     #[instrument(
         level = "trace",
         skip(self),
@@ -336,27 +606,71 @@ impl super::VM for CoreVM {
         self.do_transition(SysInput)
     }
 
+    fn sys_state_get(&mut self, key: String) -> Result<AsyncResultHandle, Error> {
+        self.sys_state_get_with_name(key, String::new())
+    }
+
     #[instrument(
         level = "trace",
         skip(self),
         fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
         ret
     )]
-    fn sys_state_get(&mut self, key: String) -> Result<AsyncResultHandle, Error> {
+    fn sys_state_get_with_name(
+        &mut self,
+        key: String,
+        name: String,
+    ) -> Result<AsyncResultHandle, Error> {
         invocation_debug_logs!(self, "Executing 'Get state {key}'");
-        let result = match self.context.eager_state.get(&key) {
+        if let Some(handle) = self.context.prefetched_state.remove(&key) {
+            self.context.unclaimed_prefetched_state.remove(&handle);
+            return Ok(AsyncResultHandle(handle));
+        }
+
+        let result = match self.eager_get_state(&key) {
             EagerGetState::Unknown => None,
             EagerGetState::Empty => Some(get_state_entry_message::Result::Empty(Empty::default())),
             EagerGetState::Value(v) => Some(get_state_entry_message::Result::Value(v)),
         };
-        self.do_transition(SysCompletableEntry(
+        let is_lazy = result.is_none();
+        let handle = self.do_transition(SysCompletableEntry(
             "SysStateGet",
             GetStateEntryMessage {
-                key: Bytes::from(key),
+                key: Bytes::from(key.clone()),
                 result,
-                ..Default::default()
+                name,
             },
-        ))
+        ))?;
+
+        if is_lazy {
+            // Issue the hinted lazy gets right away, so the runtime completes them all at once
+            for hinted_key in std::mem::take(&mut self.context.state_prefetch_hints) {
+                if hinted_key == key
+                    || self.context.prefetched_state.contains_key(&hinted_key)
+                    || !matches!(self.eager_get_state(&hinted_key), EagerGetState::Unknown)
+                {
+                    continue;
+                }
+                let hinted_handle =
+                    self.sys_state_get_with_name(hinted_key.clone(), String::new())?;
+                self.context
+                    .prefetched_state
+                    .insert(hinted_key, hinted_handle.0);
+                self.context
+                    .unclaimed_prefetched_state
+                    .insert(hinted_handle.0);
+            }
+        }
+
+        Ok(handle)
+    }
+
+    fn prefetch_state_keys(&mut self, keys: Vec<String>) {
+        self.context.state_prefetch_hints.extend(keys);
+    }
+
+    fn sys_state_get_keys(&mut self) -> VMResult<AsyncResultHandle> {
+        self.sys_state_get_keys_with_name(String::new())
     }
 
     #[instrument(
@@ -365,23 +679,37 @@ impl super::VM for CoreVM {
         fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
         ret
     )]
-    fn sys_state_get_keys(&mut self) -> VMResult<AsyncResultHandle> {
+    fn sys_state_get_keys_with_name(&mut self, name: String) -> VMResult<AsyncResultHandle> {
         invocation_debug_logs!(self, "Executing 'Get state keys'");
-        let result = match self.context.eager_state.get_keys() {
-            EagerGetStateKeys::Unknown => None,
-            EagerGetStateKeys::Keys(keys) => {
+        let eager_get_state_keys = if self.context.options.force_lazy_state {
+            EagerGetStateKeys::Unknown
+        } else {
+            self.context.eager_state.get_keys()
+        };
+        let (result, local_changes) = match eager_get_state_keys {
+            EagerGetStateKeys::Unknown => (None, Some(self.context.eager_state.local_changes())),
+            EagerGetStateKeys::Keys(keys) => (
                 Some(get_state_keys_entry_message::Result::Value(StateKeys {
                     keys: keys.into_iter().map(Bytes::from).collect(),
-                }))
-            }
+                })),
+                None,
+            ),
         };
-        self.do_transition(SysCompletableEntry(
+        let handle = self.do_transition(SysCompletableEntry(
             "SysStateGetKeys",
-            GetStateKeysEntryMessage {
-                result,
-                ..Default::default()
-            },
-        ))
+            GetStateKeysEntryMessage { result, name },
+        ))?;
+        // The runtime doesn't know about the keys set/cleared by this invocation, merge them in when taking the result
+        if let Some(local_changes) = local_changes.filter(|c| !c.is_empty()) {
+            self.context
+                .pending_state_keys_changes
+                .insert(handle.0, local_changes);
+        }
+        Ok(handle)
+    }
+
+    fn sys_state_set(&mut self, key: String, value: Bytes) -> Result<(), Error> {
+        self.sys_state_set_with_name(key, value, String::new())
     }
 
     #[instrument(
@@ -390,33 +718,44 @@ impl super::VM for CoreVM {
         fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
         ret
     )]
-    fn sys_state_set(&mut self, key: String, value: Bytes) -> Result<(), Error> {
+    fn sys_state_set_with_name(
+        &mut self,
+        key: String,
+        value: Bytes,
+        name: String,
+    ) -> Result<(), Error> {
         invocation_debug_logs!(self, "Executing 'Set state {key}'");
+        self.context.prefetched_state.remove(&key);
         self.context.eager_state.set(key.clone(), value.clone());
         self.do_transition(SysNonCompletableEntry(
             "SysStateSet",
             SetStateEntryMessage {
                 key: Bytes::from(key.into_bytes()),
                 value,
-                ..SetStateEntryMessage::default()
+                name,
             },
         ))
     }
 
+    fn sys_state_clear(&mut self, key: String) -> Result<(), Error> {
+        self.sys_state_clear_with_name(key, String::new())
+    }
+
     #[instrument(
         level = "trace",
         skip(self),
         fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
         ret
     )]
-    fn sys_state_clear(&mut self, key: String) -> Result<(), Error> {
+    fn sys_state_clear_with_name(&mut self, key: String, name: String) -> Result<(), Error> {
         invocation_debug_logs!(self, "Executing 'Clear state {key}'");
+        self.context.prefetched_state.remove(&key);
         self.context.eager_state.clear(key.clone());
         self.do_transition(SysNonCompletableEntry(
             "SysStateClear",
             ClearStateEntryMessage {
                 key: Bytes::from(key.into_bytes()),
-                ..ClearStateEntryMessage::default()
+                name,
             },
         ))
     }
@@ -429,6 +768,7 @@ impl super::VM for CoreVM {
     )]
     fn sys_state_clear_all(&mut self) -> Result<(), Error> {
         invocation_debug_logs!(self, "Executing 'Clear all state'");
+        self.context.prefetched_state.clear();
         self.context.eager_state.clear_all();
         self.do_transition(SysNonCompletableEntry(
             "SysStateClearAll",
@@ -436,6 +776,28 @@ impl super::VM for CoreVM {
         ))
     }
 
+    #[instrument(
+        level = "trace",
+        skip(self),
+        fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
+        ret
+    )]
+    fn sys_state_clear_prefix(&mut self, prefix: String) -> Result<(), Error> {
+        invocation_debug_logs!(self, "Executing 'Clear state by prefix {prefix}'");
+        self.verify_feature_support(ProtocolFeature::ClearStatePrefix)?;
+        self.context
+            .prefetched_state
+            .retain(|key, _| !key.starts_with(&prefix));
+        self.context.eager_state.clear_prefix(prefix.clone());
+        self.do_transition(SysNonCompletableEntry(
+            "SysStateClearPrefix",
+            ClearStatePrefixEntryMessage {
+                prefix: Bytes::from(prefix.into_bytes()),
+                ..ClearStatePrefixEntryMessage::default()
+            },
+        ))
+    }
+
     #[instrument(
         level = "trace",
         skip(self),
@@ -470,26 +832,63 @@ impl super::VM for CoreVM {
 
     #[instrument(
         level = "trace",
-        skip(self, input),
+        skip(self),
         fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
         ret
     )]
+    fn sys_sleep_for(
+        &mut self,
+        name: String,
+        duration: Duration,
+        now_since_unix_epoch: Duration,
+    ) -> VMResult<AsyncResultHandle> {
+        invocation_debug_logs!(self, "Executing 'Sleeping for {duration:?}'");
+        let wake_up_time = self.wake_up_time(duration, now_since_unix_epoch)?;
+
+        self.do_transition(SysCompletableEntry(
+            "SysSleep",
+            SleepEntryMessage {
+                wake_up_time,
+                name,
+                ..Default::default()
+            },
+        ))
+    }
+
     fn sys_call(&mut self, target: Target, input: Bytes) -> VMResult<AsyncResultHandle> {
+        self.sys_call_delayed(target, input, None)
+    }
+
+    #[instrument(
+        level = "trace",
+        skip(self, input),
+        fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
+        ret
+    )]
+    fn sys_call_delayed(
+        &mut self,
+        target: Target,
+        input: Bytes,
+        execution_time_since_unix_epoch: Option<Duration>,
+    ) -> VMResult<AsyncResultHandle> {
         invocation_debug_logs!(
             self,
             "Executing 'Call {}/{}'",
             target.service,
             target.handler
         );
-        if let Some(idempotency_key) = &target.idempotency_key {
-            self.verify_feature_support("attach idempotency key to call", Version::V3)?;
-            if idempotency_key.is_empty() {
-                self.do_transition(HitError {
-                    error: EMPTY_IDEMPOTENCY_KEY,
-                    next_retry_delay: None,
-                })?;
-                unreachable!();
-            }
+        if target.idempotency_key.is_some() {
+            self.verify_feature_support(ProtocolFeature::IdempotencyKeyOnCall)?;
+        }
+        if let Err(error) = target.validate() {
+            self.do_transition(HitError {
+                error,
+                next_retry_delay: None,
+            })?;
+            unreachable!();
+        }
+        if execution_time_since_unix_epoch.is_some() {
+            self.verify_feature_support(ProtocolFeature::DelayedCall)?;
         }
         self.do_transition(SysCompletableEntry(
             "SysCall",
@@ -498,17 +897,33 @@ impl super::VM for CoreVM {
                 handler_name: target.handler,
                 key: target.key.unwrap_or_default(),
                 idempotency_key: target.idempotency_key,
-                headers: target
-                    .headers
-                    .into_iter()
-                    .map(crate::service_protocol::messages::Header::from)
-                    .collect(),
+                headers: headers_to_journal(target.headers),
                 parameter: input,
+                invoke_time: execution_time_since_unix_epoch
+                    .map(|d| {
+                        u64::try_from(d.as_millis())
+                            .expect("millis since Unix epoch should fit in u64")
+                    })
+                    .unwrap_or_default(),
                 ..Default::default()
             },
         ))
     }
 
+    fn sys_call_with_timeout(
+        &mut self,
+        target: Target,
+        input: Bytes,
+        timeout: Duration,
+        now_since_unix_epoch: Duration,
+    ) -> VMResult<(AsyncResultHandle, AsyncResultHandle)> {
+        // Validate the timeout before writing the call entry
+        self.wake_up_time(timeout, now_since_unix_epoch)?;
+        let call_handle = self.sys_call(target, input)?;
+        let sleep_handle = self.sys_sleep_for(String::new(), timeout, now_since_unix_epoch)?;
+        Ok((call_handle, sleep_handle))
+    }
+
     #[instrument(
         level = "trace",
         skip(self, input),
@@ -527,39 +942,38 @@ impl super::VM for CoreVM {
             target.service,
             target.handler
         );
-        if let Some(idempotency_key) = &target.idempotency_key {
-            self.verify_feature_support("attach idempotency key to one way call", Version::V3)?;
-            if idempotency_key.is_empty() {
-                self.do_transition(HitError {
-                    error: EMPTY_IDEMPOTENCY_KEY,
-                    next_retry_delay: None,
-                })?;
-                unreachable!();
-            }
+        let entry = self.one_way_call_entry(target, input, delay)?;
+        self.do_transition(SysNonCompletableEntry("SysOneWayCall", entry))
+            .map(|_| SendHandle(self.context.journal.expect_index()))
+    }
+
+    #[instrument(
+        level = "trace",
+        skip(self, input),
+        fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
+        ret
+    )]
+    fn sys_send_with_acceptance(
+        &mut self,
+        target: Target,
+        input: Bytes,
+        delay: Option<Duration>,
+    ) -> VMResult<(SendHandle, Option<AsyncResultHandle>)> {
+        invocation_debug_logs!(
+            self,
+            "Executing 'Send to {}/{}'",
+            target.service,
+            target.handler
+        );
+        let mut entry = self.one_way_call_entry(target, input, delay)?;
+        if !self.version.supports(ProtocolFeature::SendAcceptance) {
+            return self
+                .do_transition(SysNonCompletableEntry("SysOneWayCall", entry))
+                .map(|_| (SendHandle(self.context.journal.expect_index()), None));
         }
-        self.do_transition(SysNonCompletableEntry(
-            "SysOneWayCall",
-            OneWayCallEntryMessage {
-                service_name: target.service,
-                handler_name: target.handler,
-                key: target.key.unwrap_or_default(),
-                idempotency_key: target.idempotency_key,
-                headers: target
-                    .headers
-                    .into_iter()
-                    .map(crate::service_protocol::messages::Header::from)
-                    .collect(),
-                parameter: input,
-                invoke_time: delay
-                    .map(|d| {
-                        u64::try_from(d.as_millis())
-                            .expect("millis since Unix epoch should fit in u64")
-                    })
-                    .unwrap_or_default(),
-                ..Default::default()
-            },
-        ))
-        .map(|_| SendHandle(self.context.journal.expect_index()))
+        entry.await_acceptance = true;
+        let handle = self.do_transition(SysCompletableEntry("SysOneWayCall", entry))?;
+        Ok((SendHandle(handle.0), Some(handle)))
     }
 
     #[instrument(
@@ -576,7 +990,7 @@ impl super::VM for CoreVM {
         ))
         .map(|h| {
             (
-                awakeable_id(
+                encode_awakeable_id(
                     &self.context.expect_start_info().id,
                     self.context.journal.expect_index(),
                 ),
@@ -608,6 +1022,60 @@ impl super::VM for CoreVM {
         ))
     }
 
+    #[instrument(
+        level = "trace",
+        skip(self, value),
+        fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
+        ret
+    )]
+    fn sys_complete_awakeable_with_idempotency_key(
+        &mut self,
+        id: String,
+        value: NonEmptyValue,
+        idempotency_key: String,
+    ) -> VMResult<()> {
+        invocation_debug_logs!(
+            self,
+            "Executing 'Complete awakeable {id}' with idempotency key '{idempotency_key}'"
+        );
+        self.verify_completion_idempotency_key(&idempotency_key)?;
+        self.do_transition(SysNonCompletableEntry(
+            "SysCompleteAwakeable",
+            CompleteAwakeableEntryMessage {
+                id,
+                idempotency_key: Some(idempotency_key),
+                result: Some(match value {
+                    NonEmptyValue::Success(s) => complete_awakeable_entry_message::Result::Value(s),
+                    NonEmptyValue::Failure(f) => {
+                        complete_awakeable_entry_message::Result::Failure(f.into())
+                    }
+                }),
+                ..Default::default()
+            },
+        ))
+    }
+
+    #[instrument(
+        level = "trace",
+        skip(self),
+        fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
+        ret
+    )]
+    fn sys_complete_awakeable_void(&mut self, id: String) -> VMResult<()> {
+        invocation_debug_logs!(self, "Executing 'Complete awakeable {id}' with void");
+        self.verify_feature_support(ProtocolFeature::CompleteAwakeableWithVoid)?;
+        self.do_transition(SysNonCompletableEntry(
+            "SysCompleteAwakeable",
+            CompleteAwakeableEntryMessage {
+                id,
+                result: Some(complete_awakeable_entry_message::Result::Empty(
+                    Empty::default(),
+                )),
+                ..Default::default()
+            },
+        ))
+    }
+
     #[instrument(
         level = "trace",
         skip(self),
@@ -671,6 +1139,73 @@ impl super::VM for CoreVM {
         ))
     }
 
+    #[instrument(
+        level = "trace",
+        skip(self, value),
+        fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
+        ret
+    )]
+    fn sys_complete_promise_with_idempotency_key(
+        &mut self,
+        key: String,
+        value: NonEmptyValue,
+        idempotency_key: String,
+    ) -> VMResult<AsyncResultHandle> {
+        invocation_debug_logs!(
+            self,
+            "Executing 'Complete promise {key}' with idempotency key '{idempotency_key}'"
+        );
+        self.verify_completion_idempotency_key(&idempotency_key)?;
+        self.do_transition(SysCompletableEntry(
+            "SysCompletePromise",
+            CompletePromiseEntryMessage {
+                key,
+                idempotency_key: Some(idempotency_key),
+                completion: Some(match value {
+                    NonEmptyValue::Success(s) => {
+                        complete_promise_entry_message::Completion::CompletionValue(s)
+                    }
+                    NonEmptyValue::Failure(f) => {
+                        complete_promise_entry_message::Completion::CompletionFailure(f.into())
+                    }
+                }),
+                ..Default::default()
+            },
+        ))
+    }
+
+    #[instrument(
+        level = "trace",
+        skip(self, value),
+        fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
+        ret
+    )]
+    fn sys_complete_promise_if_unset(
+        &mut self,
+        key: String,
+        value: NonEmptyValue,
+    ) -> VMResult<AsyncResultHandle> {
+        invocation_debug_logs!(self, "Executing 'Complete promise {key} if unset'");
+        self.verify_feature_support(ProtocolFeature::CompletePromiseIfUnset)?;
+        self.do_transition(SysCompletableEntry(
+            "SysCompletePromiseIfUnset",
+            CompletePromiseIfUnsetEntryMessage {
+                key,
+                completion: Some(match value {
+                    NonEmptyValue::Success(s) => {
+                        complete_promise_if_unset_entry_message::Completion::CompletionValue(s)
+                    }
+                    NonEmptyValue::Failure(f) => {
+                        complete_promise_if_unset_entry_message::Completion::CompletionFailure(
+                            f.into(),
+                        )
+                    }
+                }),
+                ..Default::default()
+            },
+        ))
+    }
+
     #[instrument(
         level = "trace",
         skip(self),
@@ -718,6 +1253,48 @@ impl super::VM for CoreVM {
         self.do_transition(SysRunExit(value, retry_policy))
     }
 
+    fn sys_run_exit_success_buf(
+        &mut self,
+        mut value: impl Buf,
+        retry_policy: RetryPolicy,
+    ) -> VMResult<AsyncResultHandle> {
+        let len = value.remaining();
+        self.sys_run_exit(
+            RunExitResult::Success(value.copy_to_bytes(len)),
+            retry_policy,
+        )
+    }
+
+    #[instrument(
+        level = "trace",
+        skip(self),
+        fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
+        ret
+    )]
+    fn sys_run_enter_with_kind(&mut self, name: String, kind: RunKind) -> VMResult<RunEnterResult> {
+        match kind {
+            RunKind::AtLeastOnce => self.sys_run_enter(name),
+            RunKind::Idempotent => {
+                invocation_debug_logs!(self, "Executing idempotent 'run' named '{name}'");
+                self.do_transition(SysIdempotentRunEnter(name))
+            }
+        }
+    }
+
+    #[instrument(
+        level = "trace",
+        skip(self, value, retry_policy),
+        fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
+        ret
+    )]
+    fn sys_run_exit_idempotent(
+        &mut self,
+        value: RunExitResult,
+        retry_policy: RetryPolicy,
+    ) -> VMResult<NonEmptyValue> {
+        self.do_transition(SysIdempotentRunExit(value, retry_policy))
+    }
+
     #[instrument(
         level = "trace",
         skip(self),
@@ -729,7 +1306,7 @@ impl super::VM for CoreVM {
         target: GetInvocationIdTarget,
     ) -> VMResult<AsyncResultHandle> {
         invocation_debug_logs!(self, "Executing 'Get invocation id'");
-        self.verify_feature_support("get call invocation id", Version::V3)?;
+        self.verify_feature_support(ProtocolFeature::GetCallInvocationId)?;
         self.do_transition(SysCompletableEntry(
             "SysGetCallInvocationId",
             GetCallInvocationIdEntryMessage {
@@ -742,6 +1319,31 @@ impl super::VM for CoreVM {
         ))
     }
 
+    fn call_invocation_id(
+        &mut self,
+        target: GetInvocationIdTarget,
+    ) -> VMResult<CallInvocationIdResult> {
+        let handle = self.sys_get_call_invocation_id(target)?;
+        let is_ready = self.async_results().is_some_and(|async_results| {
+            matches!(
+                async_results.peek_ready_result(handle.0),
+                Some(Value::InvocationId(_))
+            )
+        });
+        if !is_ready {
+            // Failures are left to be taken through the handle as well
+            return Ok(CallInvocationIdResult::Pending(handle));
+        }
+
+        match self.take_async_result(handle) {
+            Ok(Some(Value::InvocationId(id))) => Ok(CallInvocationIdResult::Ready(id)),
+            Ok(_) | Err(SuspendedOrVMError::Suspended(_)) => {
+                Ok(CallInvocationIdResult::Pending(handle))
+            }
+            Err(SuspendedOrVMError::VM(e)) => Err(e),
+        }
+    }
+
     #[instrument(
         level = "trace",
         skip(self),
@@ -750,7 +1352,7 @@ impl super::VM for CoreVM {
     )]
     fn sys_cancel_invocation(&mut self, target: CancelInvocationTarget) -> VMResult<()> {
         invocation_debug_logs!(self, "Executing 'Cancel invocation'");
-        self.verify_feature_support("cancel invocation", Version::V3)?;
+        self.verify_feature_support(ProtocolFeature::CancelInvocation)?;
         self.do_transition(SysNonCompletableEntry(
             "SysCancelInvocation",
             CancelInvocationEntryMessage {
@@ -778,7 +1380,7 @@ impl super::VM for CoreVM {
     )]
     fn sys_attach_invocation(&mut self, target: AttachInvocationTarget) -> VMResult<()> {
         invocation_debug_logs!(self, "Executing 'Attach invocation'");
-        self.verify_feature_support("attach invocation", Version::V3)?;
+        self.verify_feature_support(ProtocolFeature::AttachInvocation)?;
         self.do_transition(SysNonCompletableEntry(
             "SysAttachInvocation",
             AttachInvocationEntryMessage {
@@ -825,7 +1427,7 @@ impl super::VM for CoreVM {
     )]
     fn sys_get_invocation_output(&mut self, target: AttachInvocationTarget) -> VMResult<()> {
         invocation_debug_logs!(self, "Executing 'Get invocation output'");
-        self.verify_feature_support("get invocation output", Version::V3)?;
+        self.verify_feature_support(ProtocolFeature::GetInvocationOutput)?;
         self.do_transition(SysNonCompletableEntry(
             "SysGetInvocationOutput",
             GetInvocationOutputEntryMessage {
@@ -881,16 +1483,13 @@ impl super::VM for CoreVM {
                 invocation_debug_logs!(self, "Writing invocation result failure value");
             }
         }
-        self.do_transition(SysNonCompletableEntry(
-            "SysWriteOutput",
-            OutputEntryMessage {
-                result: Some(match value {
-                    NonEmptyValue::Success(b) => output_entry_message::Result::Value(b),
-                    NonEmptyValue::Failure(f) => output_entry_message::Result::Failure(f.into()),
-                }),
-                ..OutputEntryMessage::default()
-            },
-        ))
+        self.do_transition(SysWriteOutput(OutputEntryMessage {
+            result: Some(match value {
+                NonEmptyValue::Success(b) => output_entry_message::Result::Value(b),
+                NonEmptyValue::Failure(f) => output_entry_message::Result::Failure(f.into()),
+            }),
+            ..OutputEntryMessage::default()
+        }))
     }
 
     #[instrument(
@@ -904,6 +1503,27 @@ impl super::VM for CoreVM {
         self.do_transition(SysEnd)
     }
 
+    #[instrument(
+        level = "trace",
+        skip(self),
+        fields(restate.invocation.id = self.debug_invocation_id(), restate.journal.index = self.context.journal.index(), restate.protocol.version = %self.version),
+        ret
+    )]
+    fn sys_self_cancel(&mut self) -> VMResult<()> {
+        invocation_debug_logs!(self, "Cancelling the invocation");
+        self.sys_complete(NonEmptyValue::Failure(TerminalFailure::cancelled()))
+    }
+
+    fn sys_complete(&mut self, value: NonEmptyValue) -> VMResult<()> {
+        self.sys_write_output(value)?;
+        self.sys_end()
+    }
+
+    fn run_simple(&mut self, f: impl FnOnce(Input) -> NonEmptyValue) -> VMResult<()> {
+        let input = self.sys_input()?;
+        self.sys_complete(f(input))
+    }
+
     fn is_processing(&self) -> bool {
         matches!(&self.last_transition, Ok(State::Processing { .. }))
     }
@@ -918,6 +1538,120 @@ impl super::VM for CoreVM {
         )
     }
 
+    fn negotiated_version(&self) -> Version {
+        self.version
+    }
+
+    fn supports(&self, feature: ProtocolFeature) -> bool {
+        self.version.supports(feature)
+    }
+
+    fn peek_next_async_result_handle(&self) -> AsyncResultHandle {
+        AsyncResultHandle(self.context.journal.next_index())
+    }
+
+    fn was_replayed(&self, handle: AsyncResultHandle) -> bool {
+        self.context
+            .start_info()
+            .is_some_and(|start_info| handle.0 < start_info.entries_to_replay)
+    }
+
+    fn resolve_handle(&self, raw: u32) -> Option<AsyncResultHandle> {
+        let async_results = self.async_results()?;
+        (i64::from(raw) <= self.context.journal.index() && async_results.is_pending_result(raw))
+            .then_some(AsyncResultHandle(raw))
+    }
+
+    fn is_entry_acked(&self, entry_index: u32) -> bool {
+        self.was_replayed(AsyncResultHandle(entry_index))
+            || self
+                .async_results()
+                .is_some_and(|async_results| async_results.is_acked(entry_index))
+    }
+
+    fn replayed_resolutions(&self) -> Vec<(AsyncResultHandle, CommandType)> {
+        match &self.last_transition {
+            Ok(State::Replaying { async_results, .. })
+            | Ok(State::Processing { async_results, .. }) => async_results.replayed_resolutions(),
+            _ => vec![],
+        }
+    }
+
+    fn replay_commands(&self) -> Option<Vec<(CommandType, u32)>> {
+        let (entries, first_index) = match &self.last_transition {
+            Ok(State::WaitingReplayEntries { entries, .. }) => (entries, 0),
+            Ok(State::Replaying { entries, .. }) => (
+                entries,
+                self.context.expect_start_info().entries_to_replay - entries.len() as u32,
+            ),
+            _ => return None,
+        };
+        Some(
+            entries
+                .iter()
+                .zip(first_index..)
+                .filter_map(|(entry, index)| {
+                    CommandType::try_from(entry.ty()).ok().map(|ty| (ty, index))
+                })
+                .collect(),
+        )
+    }
+
+    fn supported_message_types(&self) -> &'static [MessageType] {
+        MessageType::supported_by(self.version)
+    }
+
+    fn journal_stats(&self) -> JournalStats {
+        let pending_async_results = self
+            .async_results()
+            .map(AsyncResultsState::pending_results)
+            .unwrap_or_default();
+        JournalStats {
+            entry_index: self.context.journal.index(),
+            pending_async_results,
+        }
+    }
+
+    fn time_since_last_stored_entry(&self) -> Option<Duration> {
+        self.context
+            .start_info()
+            .map(|si| Duration::from_millis(si.duration_since_last_stored_entry))
+    }
+
+    fn current_retry_info(&self) -> EntryRetryInfo {
+        if self.context.start_info().is_none() {
+            return EntryRetryInfo::default();
+        }
+        self.context
+            .infer_retry_info_at(self.context.journal.next_index())
+    }
+
+    fn invocation_kind(&self) -> Option<InvocationKind> {
+        self.context.start_info().map(|si| {
+            if si.key.is_empty() {
+                InvocationKind::Unknown
+            } else {
+                InvocationKind::Keyed
+            }
+        })
+    }
+
+    fn last_suspension(&self) -> Option<SuspensionInfo> {
+        self.context.last_suspension.clone()
+    }
+
+    fn invocation_deadline(&self) -> Option<Duration> {
+        self.context.invocation_deadline
+    }
+
+    fn next_retry_delay(&self) -> Option<Duration> {
+        self.context.next_retry_delay
+    }
+
+    fn eager_state_snapshot(&self) -> Option<Vec<(String, Bytes)>> {
+        self.context.eager_state.snapshot()
+    }
+
     #[instrument(
         level = "trace",
         skip(self),
@@ -937,9 +1671,31 @@ const INDIFFERENT_PAD: GeneralPurposeConfig = GeneralPurposeConfig::new()
     .with_encode_padding(false);
 const URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, INDIFFERENT_PAD);
 
-fn awakeable_id(id: &[u8], entry_index: u32) -> String {
-    let mut input_buf = BytesMut::with_capacity(id.len() + size_of::<u32>());
-    input_buf.put_slice(id);
+const AWAKEABLE_ID_PREFIX: &str = "prom_1";
+
+/// Encodes the id of the awakeable created by the invocation `invocation_id` at the given journal entry index.
+pub fn encode_awakeable_id(invocation_id: &[u8], entry_index: u32) -> String {
+    let mut input_buf = BytesMut::with_capacity(invocation_id.len() + size_of::<u32>());
+    input_buf.put_slice(invocation_id);
     input_buf.put_u32(entry_index);
-    format!("prom_1{}", URL_SAFE.encode(input_buf.freeze()))
+    format!(
+        "{AWAKEABLE_ID_PREFIX}{}",
+        URL_SAFE.encode(input_buf.freeze())
+    )
+}
+
+/// Reverses [`encode_awakeable_id`], returning the invocation id and the journal entry index.
+/// Returns `None` if the given string is not a valid awakeable id.
+pub fn decode_awakeable_id(awakeable_id: &str) -> Option<(Vec<u8>, u32)> {
+    let mut decoded = URL_SAFE
+        .decode(awakeable_id.strip_prefix(AWAKEABLE_ID_PREFIX)?)
+        .ok()?;
+    if decoded.len() < size_of::<u32>() {
+        return None;
+    }
+    let entry_index = decoded.split_off(decoded.len() - size_of::<u32>());
+    Some((
+        decoded,
+        u32::from_be_bytes(entry_index.try_into().expect("slice has the size of u32")),
+    ))
 }
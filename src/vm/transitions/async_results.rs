@@ -1,6 +1,7 @@
-use crate::vm::context::Context;
+use crate::vm::context::{AsyncResultsState, Context};
 use crate::vm::errors::{
-    AwaitingTwoAsyncResultError, UnexpectedStateError, INPUT_CLOSED_WHILE_WAITING_ENTRIES,
+    AwaitingTwoAsyncResultError, InvalidAsyncResultHandleError, UnexpectedStateError,
+    INPUT_CLOSED_WHILE_WAITING_ENTRIES,
 };
 use crate::vm::transitions::{HitSuspensionPoint, Transition, TransitionAndReturn};
 use crate::vm::State;
@@ -32,6 +33,15 @@ impl Transition<Context, NotifyInputClosed> for State {
     }
 }
 
+/// Rejects handles of entries never created and of non-completable entries, as awaiting them would never complete.
+/// Handles whose result was already taken are accepted, and taking them again returns no value.
+fn check_handle_is_valid(async_results: &AsyncResultsState, handle: u32) -> Result<(), Error> {
+    if !async_results.is_pending_result(handle) && !async_results.was_taken(handle) {
+        return Err(InvalidAsyncResultHandleError { handle }.into());
+    }
+    Ok(())
+}
+
 pub(crate) struct NotifyAwaitPoint(pub(crate) u32);
 
 impl Transition<Context, NotifyAwaitPoint> for State {
@@ -51,6 +61,7 @@ impl Transition<Context, NotifyAwaitPoint> for State {
                 ref async_results,
                 ..
             } => {
+                check_handle_is_valid(async_results, await_point)?;
                 if let Some(previous) = current_await_point {
                     if *previous != await_point {
                         if context.options.fail_on_wait_concurrent_async_result {
@@ -90,7 +101,7 @@ impl TransitionAndReturn<Context, TakeAsyncResult> for State {
 
     fn transition_and_return(
         mut self,
-        _: &mut Context,
+        context: &mut Context,
         TakeAsyncResult(async_result): TakeAsyncResult,
     ) -> Result<(Self, Self::Output), Error> {
         match self {
@@ -104,7 +115,18 @@ impl TransitionAndReturn<Context, TakeAsyncResult> for State {
                 ref mut async_results,
                 ..
             } => {
-                let opt = async_results.take_ready_result(async_result);
+                check_handle_is_valid(async_results, async_result)?;
+                let opt = async_results.take_ready_result(async_result).map(|value| {
+                    match (
+                        value,
+                        context.pending_state_keys_changes.remove(&async_result),
+                    ) {
+                        (Value::StateKeys(keys), Some(local_changes)) => {
+                            Value::StateKeys(local_changes.apply(keys))
+                        }
+                        (value, _) => value,
+                    }
+                });
 
                 // Reset current await point if matches
                 if opt.is_some() && current_await_point.is_some_and(|i| i == async_result) {
@@ -1,4 +1,4 @@
-use crate::service_protocol::messages::{CombinatorEntryMessage, SuspensionMessage};
+use crate::service_protocol::messages::CombinatorEntryMessage;
 use crate::vm::context::Context;
 use crate::vm::errors::{UnexpectedStateError, BAD_COMBINATOR_ENTRY};
 use crate::vm::transitions::{PopJournalEntry, TransitionAndReturn};
@@ -68,7 +68,7 @@ where
         context: &mut Context,
         SysTryCompleteCombinator(combinator): SysTryCompleteCombinator<C>,
     ) -> Result<(Self, Self::Output), Error> {
-        self.check_side_effect_guard()?;
+        self.check_side_effect_guard(context)?;
         match self {
             State::Processing {
                 ref mut async_results,
@@ -113,7 +113,7 @@ where
                     );
 
                     // Write out the combinator message
-                    context.output.send(&message);
+                    context.send_entry(&message)?;
 
                     Ok((self, Some(AsyncResultHandle(current_journal_index))))
                 } else {
@@ -129,13 +129,12 @@ where
                         };
 
                         // We can't do progress anymore, let's suspend
-                        context.output.send(&SuspensionMessage {
-                            entry_indexes: uncompleted_entries_order
+                        context.suspend(
+                            uncompleted_entries_order
                                 .into_iter()
                                 .map(Into::into)
                                 .collect(),
-                        });
-                        context.output.send_eof();
+                        )?;
 
                         Ok((State::Suspended, None))
                     } else {
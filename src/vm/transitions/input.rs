@@ -32,12 +32,19 @@ impl Transition<Context, NewMessage> for State {
 
 struct NewStartMessage(StartMessage);
 
+/// Start message fields exposed to the SDK through [`crate::Input::attributes`].
+/// Start-time attributes added to the protocol, such as region or priority, are mapped here.
+fn start_attributes(_msg: &StartMessage) -> Vec<(String, String)> {
+    Vec::new()
+}
+
 impl Transition<Context, NewStartMessage> for State {
     fn transition(
         self,
         context: &mut Context,
         NewStartMessage(msg): NewStartMessage,
     ) -> Result<Self, Error> {
+        let attributes = start_attributes(&msg);
         context.start_info = Some(StartInfo {
             id: msg.id,
             debug_id: msg.debug_id,
@@ -45,6 +52,7 @@ impl Transition<Context, NewStartMessage> for State {
             entries_to_replay: msg.known_entries,
             retry_count_since_last_stored_entry: msg.retry_count_since_last_stored_entry,
             duration_since_last_stored_entry: msg.duration_since_last_stored_entry,
+            attributes,
         });
         context.eager_state = EagerState::new(
             msg.partial_state,
@@ -1,28 +1,33 @@
 use crate::retries::NextRetry;
 use crate::service_protocol::messages::{
     run_entry_message, CompletableEntryMessage, EntryMessage, EntryMessageHeaderEq,
-    InputEntryMessage, RestateMessage, RunEntryMessage, WriteableRestateMessage,
+    InputEntryMessage, OutputEntryMessage, RestateMessage, RunEntryMessage,
+    WriteableRestateMessage,
 };
 use crate::vm::context::{Context, RunState};
 use crate::vm::errors::{
-    EntryMismatchError, UnavailableEntryError, UnexpectedStateError, INSIDE_RUN,
-    INVOKED_RUN_EXIT_WITHOUT_ENTER, UNEXPECTED_NONE_RUN_RESULT,
+    EntryMismatchError, UnavailableEntryError, UnexpectedStateError, INPUT_ALREADY_CONSUMED,
+    INSIDE_RUN, INVOKED_RUN_EXIT_WITHOUT_ENTER, OUTPUT_ALREADY_WRITTEN, UNEXPECTED_NONE_RUN_RESULT,
 };
 use crate::vm::transitions::{Transition, TransitionAndReturn};
 use crate::vm::State;
 use crate::{
-    AsyncResultHandle, Error, Header, Input, NonEmptyValue, RetryPolicy, RunEnterResult,
-    RunExitResult, TerminalFailure,
+    AsyncResultHandle, EntryRetryInfo, Error, Header, Input, NonEmptyValue, RetryPolicy,
+    RunEnterResult, RunExitResult, TerminalFailure, INVOCATION_DEADLINE_HEADER,
 };
+use std::time::Duration;
 use std::{fmt, mem};
 
 impl State {
-    pub(crate) fn check_side_effect_guard(&self) -> Result<(), Error> {
+    pub(crate) fn check_side_effect_guard(&self, context: &Context) -> Result<(), Error> {
         if let State::Processing { run_state, .. } = self {
             if run_state.is_running() {
                 return Err(INSIDE_RUN);
             }
         }
+        if context.idempotent_run.is_some() {
+            return Err(INSIDE_RUN);
+        }
         Ok(())
     }
 }
@@ -87,7 +92,7 @@ impl<M: RestateMessage + EntryMessageHeaderEq + EntryMessage + Clone + Writeable
     ) -> Result<(Self, Self::Output), Error> {
         match self {
             State::Processing { .. } => {
-                context.output.send(&expected);
+                context.send_entry(&expected)?;
                 Ok((self, expected))
             }
             s => s.transition_and_return(context, PopJournalEntry(sys_name, expected)),
@@ -105,13 +110,23 @@ impl TransitionAndReturn<Context, SysInput> for State {
         context: &mut Context,
         _: SysInput,
     ) -> Result<(Self, Self::Output), Error> {
+        if context.input_consumed {
+            return Err(INPUT_ALREADY_CONSUMED);
+        }
         context.journal.transition(&InputEntryMessage::default());
-        self.check_side_effect_guard()?;
+        self.check_side_effect_guard(context)?;
         let (s, msg) = TransitionAndReturn::transition_and_return(
             self,
             context,
             PopJournalEntry("SysInput", InputEntryMessage::default()),
         )?;
+        context.input_consumed = true;
+        context.invocation_deadline = msg
+            .headers
+            .iter()
+            .find(|h| h.key.eq_ignore_ascii_case(INVOCATION_DEADLINE_HEADER))
+            .and_then(|h| h.value.trim().parse::<u64>().ok())
+            .map(Duration::from_millis);
         let start_info = context.expect_start_info();
 
         Ok((
@@ -122,6 +137,7 @@ impl TransitionAndReturn<Context, SysInput> for State {
                 key: start_info.key.clone(),
                 headers: msg.headers.into_iter().map(Header::from).collect(),
                 input: msg.value,
+                attributes: start_info.attributes.clone(),
             },
         ))
     }
@@ -157,13 +173,33 @@ impl<M: RestateMessage + EntryMessageHeaderEq + EntryMessage + Clone + Writeable
         SysNonCompletableEntry(sys_name, expected): SysNonCompletableEntry<M>,
     ) -> Result<Self, Error> {
         context.journal.transition(&expected);
-        self.check_side_effect_guard()?;
+        self.check_side_effect_guard(context)?;
         let (s, _) =
             self.transition_and_return(context, PopOrWriteJournalEntry(sys_name, expected))?;
         Ok(s)
     }
 }
 
+pub(crate) struct SysWriteOutput(pub(crate) OutputEntryMessage);
+
+impl Transition<Context, SysWriteOutput> for State {
+    fn transition(
+        self,
+        context: &mut Context,
+        SysWriteOutput(expected): SysWriteOutput,
+    ) -> Result<Self, Error> {
+        // Replayed output entries are not checked, only the ones written while processing,
+        // but they still count as written output for the following calls
+        let is_processing = matches!(self, State::Processing { .. });
+        if is_processing && context.output_written && context.options.strict_output_check {
+            return Err(OUTPUT_ALREADY_WRITTEN);
+        }
+        let s = self.transition(context, SysNonCompletableEntry("SysWriteOutput", expected))?;
+        context.output_written = true;
+        Ok(s)
+    }
+}
+
 pub(crate) struct SysCompletableEntry<M>(pub(crate) &'static str, pub(crate) M);
 
 impl<
@@ -183,7 +219,8 @@ impl<
         SysCompletableEntry(sys_name, expected): SysCompletableEntry<M>,
     ) -> Result<(Self, Self::Output), Error> {
         context.journal.transition(&expected);
-        self.check_side_effect_guard()?;
+        self.check_side_effect_guard(context)?;
+        let is_replaying = matches!(self, State::Replaying { .. });
         let (mut s, actual) = TransitionAndReturn::transition_and_return(
             self,
             context,
@@ -201,7 +238,12 @@ impl<
                 ..
             } => {
                 if let Some(c) = actual.into_completion()? {
-                    async_results.insert_ready_result(ar_handle.0, c);
+                    if is_replaying {
+                        async_results.insert_replayed_result(ar_handle.0, M::command_type(), c);
+                    } else {
+                        // Resolved while processing, e.g. an eager state get
+                        async_results.insert_ready_result(ar_handle.0, c);
+                    }
                 } else {
                     async_results.insert_completion_parsing_hint(
                         ar_handle.0,
@@ -230,7 +272,7 @@ impl TransitionAndReturn<Context, SysRunEnter> for State {
             ..RunEntryMessage::default()
         };
         context.journal.transition(&expected);
-        self.check_side_effect_guard()?;
+        self.check_side_effect_guard(context)?;
         match self {
             State::Processing {
                 ref mut run_state, ..
@@ -278,33 +320,8 @@ impl TransitionAndReturn<Context, SysRunExit> for State {
                 };
                 let current_journal_index = context.journal.expect_index();
 
-                let value = match run_exit_result {
-                    RunExitResult::Success(s) => NonEmptyValue::Success(s),
-                    RunExitResult::TerminalFailure(f) => NonEmptyValue::Failure(f),
-                    RunExitResult::RetryableFailure {
-                        error: failure,
-                        attempt_duration,
-                    } => {
-                        let mut retry_info = context.infer_entry_retry_info();
-                        retry_info.retry_count += 1;
-                        retry_info.retry_loop_duration += attempt_duration;
-
-                        match retry_policy.next_retry(retry_info) {
-                            NextRetry::Retry(next_retry_interval) => {
-                                // We need to retry!
-                                context.next_retry_delay = next_retry_interval;
-                                return Err(Error::new(failure.code, failure.message));
-                            }
-                            NextRetry::DoNotRetry => {
-                                // We don't retry, but convert the retryable error to actual error
-                                NonEmptyValue::Failure(TerminalFailure {
-                                    code: failure.code,
-                                    message: failure.message.to_string(),
-                                })
-                            }
-                        }
-                    }
-                };
+                let retry_info = context.infer_entry_retry_info();
+                let value = run_exit_value(context, run_exit_result, retry_policy, retry_info)?;
 
                 async_results
                     .insert_waiting_ack_result(current_journal_index, value.clone().into());
@@ -316,7 +333,7 @@ impl TransitionAndReturn<Context, SysRunExit> for State {
                         NonEmptyValue::Failure(f) => run_entry_message::Result::Failure(f.into()),
                     }),
                 };
-                context.output.send(&expected);
+                context.send_entry(&expected)?;
 
                 Ok((self, AsyncResultHandle(current_journal_index)))
             }
@@ -325,6 +342,85 @@ impl TransitionAndReturn<Context, SysRunExit> for State {
     }
 }
 
+pub(crate) struct SysIdempotentRunEnter(pub(crate) String);
+
+impl TransitionAndReturn<Context, SysIdempotentRunEnter> for State {
+    type Output = RunEnterResult;
+
+    fn transition_and_return(
+        self,
+        context: &mut Context,
+        SysIdempotentRunEnter(name): SysIdempotentRunEnter,
+    ) -> Result<(Self, Self::Output), Error> {
+        self.check_side_effect_guard(context)?;
+        match self {
+            State::Replaying { .. } | State::Processing { .. } => {
+                context.idempotent_run = Some(name);
+                // No entry is written, so the attempt happens right before the next entry
+                let retry_info = context.infer_retry_info_at(context.journal.next_index());
+                Ok((self, RunEnterResult::NotExecuted(retry_info)))
+            }
+            s => Err(UnexpectedStateError::new(s.into(), "SysIdempotentRunEnter").into()),
+        }
+    }
+}
+
+pub(crate) struct SysIdempotentRunExit(pub(crate) RunExitResult, pub(crate) RetryPolicy);
+
+impl TransitionAndReturn<Context, SysIdempotentRunExit> for State {
+    type Output = NonEmptyValue;
+
+    fn transition_and_return(
+        self,
+        context: &mut Context,
+        SysIdempotentRunExit(run_exit_result, retry_policy): SysIdempotentRunExit,
+    ) -> Result<(Self, Self::Output), Error> {
+        match self {
+            State::Replaying { .. } | State::Processing { .. } => {
+                if context.idempotent_run.take().is_none() {
+                    return Err(INVOKED_RUN_EXIT_WITHOUT_ENTER);
+                }
+                let retry_info = context.infer_retry_info_at(context.journal.next_index());
+                let value = run_exit_value(context, run_exit_result, retry_policy, retry_info)?;
+                Ok((self, value))
+            }
+            s => Err(UnexpectedStateError::new(s.into(), "SysIdempotentRunExit").into()),
+        }
+    }
+}
+
+/// Resolves the value of a run, or fails with the run error if the retry policy wants to retry it.
+fn run_exit_value(
+    context: &mut Context,
+    run_exit_result: RunExitResult,
+    retry_policy: RetryPolicy,
+    mut retry_info: EntryRetryInfo,
+) -> Result<NonEmptyValue, Error> {
+    Ok(match run_exit_result {
+        RunExitResult::Success(s) => NonEmptyValue::Success(s),
+        RunExitResult::TerminalFailure(f) => NonEmptyValue::Failure(f),
+        RunExitResult::RetryableFailure {
+            error: failure,
+            attempt_duration,
+        } => {
+            retry_info.retry_count += 1;
+            retry_info.retry_loop_duration += attempt_duration;
+
+            match retry_policy.next_retry(retry_info) {
+                NextRetry::Retry(next_retry_interval) => {
+                    // We need to retry!
+                    context.next_retry_delay = next_retry_interval;
+                    return Err(Error::new(failure.code, failure.message));
+                }
+                NextRetry::DoNotRetry => {
+                    // We don't retry, but convert the retryable error to actual error
+                    NonEmptyValue::Failure(TerminalFailure::new(failure.code, failure.message))
+                }
+            }
+        }
+    })
+}
+
 fn check_entry_header_match<M: EntryMessageHeaderEq + Clone + fmt::Debug>(
     actual: &M,
     expected: &M,
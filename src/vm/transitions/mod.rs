@@ -62,7 +62,27 @@ impl CoreVM {
             }
             Ok(s) => {
                 let was_closed = matches!(s, State::Ended | State::Suspended);
-                match TransitionAndReturn::transition_and_return(s, &mut self.context, event) {
+                #[cfg(feature = "trace_events")]
+                let from_state: &'static str = (&s).into();
+                #[cfg(feature = "vm_stats")]
+                let previous_command_index = self.context.journal.index();
+                let res = TransitionAndReturn::transition_and_return(s, &mut self.context, event);
+                #[cfg(feature = "trace_events")]
+                self.record_transition::<E>(
+                    from_state,
+                    match &res {
+                        Ok((new_state, _)) => new_state.into(),
+                        Err(_) => "Errored",
+                    },
+                );
+                #[cfg(feature = "vm_stats")]
+                if !was_closed {
+                    self.record_metrics(
+                        previous_command_index,
+                        res.as_ref().map(|(new_state, _)| new_state),
+                    );
+                }
+                match res {
                     Ok((new_state, output)) => {
                         self.last_transition = Ok(new_state);
                         Ok(output)
@@ -102,4 +122,49 @@ impl CoreVM {
             }
         }
     }
+
+    #[cfg(feature = "trace_events")]
+    fn record_transition<E>(&mut self, from_state: &'static str, to_state: &'static str) {
+        let capacity = self.context.options.transition_trace_capacity;
+        if capacity == 0 {
+            return;
+        }
+        if self.transition_trace.len() == capacity {
+            self.transition_trace.pop_front();
+        }
+
+        // Strip module path and generic parameters, e.g. `SysCompletableEntry<...>` -> `SysCompletableEntry`
+        let event = std::any::type_name::<E>();
+        let event = event.split('<').next().unwrap_or(event);
+        let event = event.rsplit("::").next().unwrap_or(event);
+
+        self.transition_trace.push_back(crate::TransitionEvent {
+            event,
+            from_state,
+            to_state,
+            command_index: self.context.journal.index(),
+        });
+    }
+
+    #[cfg(feature = "vm_stats")]
+    fn record_metrics(&mut self, previous_command_index: i64, res: Result<&State, &Error>) {
+        match res {
+            Ok(new_state) => {
+                if self.context.journal.index() != previous_command_index {
+                    if let Ok(ty) =
+                        crate::CommandType::try_from(self.context.journal.current_entry_ty)
+                    {
+                        *self.metrics.commands.entry(ty).or_default() += 1;
+                    }
+                }
+                if matches!(new_state, State::Suspended) {
+                    self.metrics.suspensions += 1;
+                }
+            }
+            Err(e) if e.code_enum() == Some(crate::error::ErrorCode::JournalMismatch) => {
+                self.metrics.journal_mismatches += 1;
+            }
+            Err(_) => {}
+        }
+    }
 }
@@ -1,6 +1,6 @@
-use crate::service_protocol::messages::{EndMessage, SuspensionMessage};
+use crate::service_protocol::messages::EndMessage;
 use crate::vm::context::Context;
-use crate::vm::errors::UnexpectedStateError;
+use crate::vm::errors::{UnawaitedAsyncResultsError, UnexpectedStateError};
 use crate::vm::transitions::Transition;
 use crate::vm::State;
 use crate::Error;
@@ -40,10 +40,7 @@ impl Transition<Context, HitSuspensionPoint> for State {
             return Ok(self);
         }
         tracing::debug!("Suspending");
-        context.output.send(&SuspensionMessage {
-            entry_indexes: vec![await_point],
-        });
-        context.output.send_eof();
+        context.suspend(vec![await_point])?;
 
         Ok(State::Suspended)
     }
@@ -54,7 +51,17 @@ pub(crate) struct SysEnd;
 impl Transition<Context, SysEnd> for State {
     fn transition(self, context: &mut Context, _: SysEnd) -> Result<Self, Error> {
         match self {
-            State::Processing { .. } => {
+            State::Processing {
+                ref async_results, ..
+            } => {
+                if context.options.strict_completion_check {
+                    let mut handles = async_results.pending_result_indexes();
+                    // The SDK never received the handles of the prefetched state, so it can't await them
+                    handles.retain(|h| !context.unclaimed_prefetched_state.contains(h));
+                    if !handles.is_empty() {
+                        return Err(UnawaitedAsyncResultsError { handles }.into());
+                    }
+                }
                 context.output.send(&EndMessage {});
                 context.output.send_eof();
                 Ok(State::Ended)